@@ -1,4 +1,7 @@
-int main() {
-    print("Hello, world!\n");
-    return 42;
+#include <stdio.h>
+
+int main()
+{
+  printf("Hello, world!\n");
+  return 42;
 }
@@ -0,0 +1,439 @@
+//! A textual assembly language for VM programs, and its assembler.
+//!
+//! `assemble` turns text into a runnable `Program`, so VM behavior can be tested without the
+//! C front end, and `to_asm` prints a program in the same language, so compiled code can be
+//! kept as golden files. The format is line based; `;` starts a comment:
+//!
+//! ```text
+//! .string greeting "hi\n"   ; a NUL-terminated string literal in the data segment
+//! .global count             ; a zero-initialised, word-aligned global
+//! .locals 2                 ; frame slots to push before running top-level code
+//! .func twice 1             ; function `twice` taking 1 argument starts here
+//!     Enter 1
+//!     LoadLocal 0
+//!     Imm 2
+//!     Mul
+//!     Leave
+//! .func main 0              ; execution starts at `main` (or at the top without one)
+//!     Enter 0
+//!     Imm 21
+//!     Call twice
+//!     Jmp L9999
+//! L9999:                    ; `return` from `main` jumps to label 9999 at the end
+//! ```
+//!
+//! Instructions are written as in the disassembly: the variant name, then its operand.
+//! `Jmp`/`Jz`/`Jnz` take a label, `Call`/`TailCall` a function name (defined before or after), and `Imm`
+//! a number or the name of a string or global, which stands for its address. Labels named
+//! `L<n>` get id `n`; any other name gets a fresh id. The superinstructions take two operands
+//! separated by a comma: `AddLocal 0, -1` and `CmpJz Lt, loop`.
+
+use crate::diagnostic::Diagnostic;
+use crate::disasm::{mnemonic, quote};
+use crate::instruction::{Compare, Instruction};
+use crate::program::Program;
+use crate::token::Span;
+use crate::vm::{DATA_BASE, WORD};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Assemble `text` into a program, or report the first mistake in it.
+pub fn assemble(text: &str) -> Result<Program, Diagnostic> {
+    Assembler::default().run(text)
+}
+
+/// Print `program` as assembly that `assemble` turns back into the same instructions, data
+/// and symbols. String literals are named `str.0`, `str.1`, ... in address order. Fails if a
+/// string literal's address is outside the data segment.
+pub fn to_asm(program: &Program) -> Result<String, Diagnostic> {
+    let mut names: HashMap<usize, String> =
+        program.globals.iter().map(|(name, &addr)| (addr, name.clone())).collect();
+    let mut strings = program.strings.clone();
+    strings.sort_unstable();
+    for (i, &addr) in strings.iter().enumerate() {
+        names.insert(addr, format!("str.{}", i));
+    }
+
+    let mut out = String::new();
+    let mut data: Vec<&usize> = names.keys().collect();
+    data.sort_unstable();
+    for addr in data {
+        if strings.contains(addr) {
+            let start = addr
+                .checked_sub(DATA_BASE)
+                .filter(|&start| start < program.data.len())
+                .ok_or_else(|| Diagnostic::new(format!("string literal at {:#x} is outside the data section", addr), 0))?;
+            let bytes: Vec<u8> = program.data[start..].iter().take_while(|&&b| b != 0).copied().collect();
+            let _ = writeln!(out, ".string {} {}", names[addr], quote(&bytes));
+        } else {
+            let _ = writeln!(out, ".global {}", names[addr]);
+        }
+    }
+    if program.locals > 0 {
+        let _ = writeln!(out, ".locals {}", program.locals);
+    }
+
+    let entries: HashMap<usize, &str> =
+        program.functions.iter().map(|(name, &addr)| (addr, name.as_str())).collect();
+    for (i, instr) in program.code.iter().enumerate() {
+        if let Some(name) = entries.get(&i) {
+            let arity = program.function_arity.get(*name).copied().unwrap_or(0);
+            let _ = writeln!(out, ".func {} {}", name, arity);
+        }
+        let _ = match instr {
+            Instruction::Label(id) => writeln!(out, "L{}:", id),
+            Instruction::Jmp(id) => writeln!(out, "    Jmp L{}", id),
+            Instruction::Jz(id) => writeln!(out, "    Jz L{}", id),
+            Instruction::Jnz(id) => writeln!(out, "    Jnz L{}", id),
+            Instruction::CmpJz(cmp, id) => writeln!(out, "    CmpJz {:?}, L{}", cmp, id),
+            Instruction::Call(addr) | Instruction::TailCall(addr) => {
+                let op = if matches!(instr, Instruction::Call(_)) { "Call" } else { "TailCall" };
+                match entries.get(addr) {
+                    Some(name) => writeln!(out, "    {} {}", op, name),
+                    None => writeln!(out, "    {} {}", op, addr),
+                }
+            }
+            Instruction::Imm(value) => match usize::try_from(*value).ok().and_then(|addr| names.get(&addr)) {
+                Some(name) => writeln!(out, "    Imm {}", name),
+                None => writeln!(out, "    Imm {}", value),
+            },
+            Instruction::PrintStr(s) => writeln!(out, "    PrintStr {}", quote(s.as_bytes())),
+            other => writeln!(out, "    {}", mnemonic(other)),
+        };
+    }
+    Ok(out)
+}
+
+/// An operand naming something that may only be defined further down.
+enum Fixup {
+    Call(String), // of a `Call` or a `TailCall`
+    Imm(String),
+}
+
+#[derive(Default)]
+struct Assembler {
+    program: Program,
+    labels: HashMap<String, usize>, // label name → id
+    next_label: usize,              // first id not taken by an `L<n>` label
+    fixups: Vec<(usize, Fixup, Span)>,
+    data_symbols: HashMap<String, usize>, // string or global name → VM address
+}
+
+impl Assembler {
+    fn run(mut self, text: &str) -> Result<Program, Diagnostic> {
+        // numbered labels keep their ids, so give named ones ids above all of those
+        self.next_label = text
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter_map(|word| word.strip_prefix('L')?.parse::<usize>().ok())
+            .map(|id| id + 1)
+            .max()
+            .unwrap_or(0);
+
+        let mut offset = 0;
+        for (index, raw) in text.split_inclusive('\n').enumerate() {
+            let line = strip_comment(raw).trim();
+            if !line.is_empty() {
+                let column = raw.len() - raw.trim_start().len() + 1;
+                let span = Span {
+                    file_id: 0,
+                    start: offset + column - 1,
+                    end: offset + raw.trim_end().len(),
+                    line: index + 1,
+                    column,
+                };
+                self.line(line, span)?;
+            }
+            offset += raw.len();
+        }
+
+        for (at, fixup, span) in std::mem::take(&mut self.fixups) {
+            self.program.code[at] = match fixup {
+                Fixup::Call(name) => match (self.program.functions.get(&name), &self.program.code[at]) {
+                    (Some(&addr), Instruction::TailCall(_)) => Instruction::TailCall(addr),
+                    (Some(&addr), _) => Instruction::Call(addr),
+                    (None, _) => return Err(Diagnostic::at(format!("Unknown function `{}`", name), span)),
+                },
+                Fixup::Imm(name) => match self.data_symbols.get(&name) {
+                    Some(&addr) => Instruction::Imm(addr as i64),
+                    None => return Err(Diagnostic::at(format!("Unknown symbol `{}`", name), span)),
+                },
+            };
+        }
+        self.program.main_label = self.program.functions.get("main").copied();
+        Ok(self.program)
+    }
+
+    fn line(&mut self, line: &str, span: Span) -> Result<(), Diagnostic> {
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let error = |message: String| Err(Diagnostic::at(message, span));
+
+        if let Some(label) = word.strip_suffix(':') {
+            if !rest.is_empty() {
+                return error(format!("Unexpected `{}` after label", rest));
+            }
+            let id = self.label(label);
+            self.push(Instruction::Label(id), span);
+            return Ok(());
+        }
+
+        match word {
+            ".string" => {
+                let Some((name, literal)) = rest.split_once(char::is_whitespace) else {
+                    return error("Expected a name and a string after .string".to_string());
+                };
+                let bytes = unquote(literal.trim()).map_err(|message| Diagnostic::at(message, span))?;
+                let addr = DATA_BASE + self.program.data.len();
+                self.program.data.extend_from_slice(&bytes);
+                self.program.data.push(0);
+                self.program.strings.push(addr);
+                self.define_data(name, addr, span)
+            }
+            ".global" => {
+                let offset = self.program.data.len().next_multiple_of(WORD);
+                self.program.data.resize(offset + WORD, 0);
+                self.program.globals.insert(rest.to_string(), DATA_BASE + offset);
+                self.define_data(rest, DATA_BASE + offset, span)
+            }
+            ".locals" => {
+                self.program.locals = number(rest, span)?;
+                Ok(())
+            }
+            ".func" => {
+                let (name, arity) = rest.split_once(char::is_whitespace).unwrap_or((rest, "0"));
+                if self.program.functions.contains_key(name) {
+                    return error(format!("Function `{}` is defined twice", name));
+                }
+                let entry = self.program.code.len();
+                self.program.functions.insert(name.to_string(), entry);
+                self.program.function_arity.insert(name.to_string(), number(arity.trim(), span)?);
+                Ok(())
+            }
+            _ if word.starts_with('.') => error(format!("Unknown directive `{}`", word)),
+            _ => {
+                let instr = self.instruction(word, rest, span)?;
+                self.push(instr, span);
+                Ok(())
+            }
+        }
+    }
+
+    fn push(&mut self, instr: Instruction, span: Span) {
+        self.program.code.push(instr);
+        self.program.spans.push(span);
+    }
+
+    fn define_data(&mut self, name: &str, addr: usize, span: Span) -> Result<(), Diagnostic> {
+        if name.is_empty() || self.data_symbols.insert(name.to_string(), addr).is_some() {
+            return Err(Diagnostic::at(format!("Symbol `{}` is defined twice or empty", name), span));
+        }
+        Ok(())
+    }
+
+    /// The id of label `name`: `L<n>` is `n`, others are numbered in order of appearance.
+    fn label(&mut self, name: &str) -> usize {
+        if let Some(id) = name.strip_prefix('L').and_then(|n| n.parse().ok()) {
+            return id;
+        }
+        if let Some(&id) = self.labels.get(name) {
+            return id;
+        }
+        let id = self.next_label;
+        self.next_label += 1;
+        self.labels.insert(name.to_string(), id);
+        id
+    }
+
+    fn instruction(&mut self, name: &str, operand: &str, span: Span) -> Result<Instruction, Diagnostic> {
+        use Instruction::*;
+        let simple = match name {
+            "Add" => Some(Add),
+            "Sub" => Some(Sub),
+            "Mul" => Some(Mul),
+            "Div" => Some(Div),
+            "Mod" => Some(Mod),
+            "Neg" => Some(Neg),
+            "Not" => Some(Not),
+            "Deref" => Some(Deref),
+            "Cast" => Some(Cast),
+            "DerefChar" => Some(DerefChar),
+            "Store" => Some(Store),
+            "StoreChar" => Some(StoreChar),
+            "Dup" => Some(Dup),
+            "Pop" => Some(Pop),
+            "Eq" => Some(Eq),
+            "Ne" => Some(Ne),
+            "Lt" => Some(Lt),
+            "Gt" => Some(Gt),
+            "Le" => Some(Le),
+            "Ge" => Some(Ge),
+            "BitAnd" => Some(BitAnd),
+            "BitOr" => Some(BitOr),
+            "BitXor" => Some(BitXor),
+            "Shl" => Some(Shl),
+            "Shr" => Some(Shr),
+            "Print" => Some(Print),
+            "PrintF" => Some(PrintF),
+            "Getchar" => Some(Getchar),
+            "Putchar" => Some(Putchar),
+            "Read" => Some(Read),
+            "Write" => Some(Write),
+            "Close" => Some(Close),
+            "Exit" => Some(Exit),
+            "Malloc" => Some(Malloc),
+            "Free" => Some(Free),
+            "Memset" => Some(Memset),
+            "Memcmp" => Some(Memcmp),
+            "Leave" => Some(Leave),
+            _ => None,
+        };
+        if let Some(instr) = simple {
+            if !operand.is_empty() {
+                return Err(Diagnostic::at(format!("`{}` takes no operand", name), span));
+            }
+            return Ok(instr);
+        }
+
+        Ok(match name {
+            "Imm" => match operand.parse() {
+                Ok(value) => Imm(value),
+                Err(_) if operand.starts_with("0x") => {
+                    let value = u64::from_str_radix(&operand[2..], 16)
+                        .map_err(|_| Diagnostic::at(format!("Invalid number `{}`", operand), span))?;
+                    Imm(value as i64)
+                }
+                Err(_) if is_name(operand) => {
+                    self.fixups.push((self.program.code.len(), Fixup::Imm(operand.to_string()), span));
+                    Imm(0)
+                }
+                Err(_) => return Err(Diagnostic::at(format!("Invalid operand `{}` for `Imm`", operand), span)),
+            },
+            "ImmF" => ImmF(float(operand, span)?),
+            "PushF" => PushF(float(operand, span)?),
+            "Jmp" => Jmp(self.label_operand(operand, span)?),
+            "Jz" => Jz(self.label_operand(operand, span)?),
+            "Jnz" => Jnz(self.label_operand(operand, span)?),
+            "Call" | "TailCall" => {
+                let call = if name == "Call" { Call } else { TailCall };
+                match operand.parse() {
+                    Ok(addr) => call(addr),
+                    Err(_) if is_name(operand) => {
+                        self.fixups.push((self.program.code.len(), Fixup::Call(operand.to_string()), span));
+                        call(0)
+                    }
+                    Err(_) => {
+                        return Err(Diagnostic::at(format!("Invalid operand `{}` for `{}`", operand, name), span));
+                    }
+                }
+            }
+            "Addr" => Addr(number(operand, span)?),
+            "LoadLocal" => LoadLocal(number(operand, span)?),
+            "StoreLocal" => StoreLocal(number(operand, span)?),
+            "Enter" => Enter(number(operand, span)?),
+            "Printf" => Printf(number(operand, span)?),
+            "Scanf" => Scanf(number(operand, span)?),
+            "Open" => Open(number(operand, span)?),
+            "Label" => Label(self.label_operand(operand, span)?),
+            "AddLocal" => {
+                let (slot, n) = pair(operand, span)?;
+                let n = n.parse().map_err(|_| Diagnostic::at(format!("Invalid number `{}`", n), span))?;
+                AddLocal(number(slot, span)?, n)
+            }
+            "CmpJz" => {
+                let (cmp, label) = pair(operand, span)?;
+                let cmp = match cmp {
+                    "Eq" => Compare::Eq,
+                    "Ne" => Compare::Ne,
+                    "Lt" => Compare::Lt,
+                    "Gt" => Compare::Gt,
+                    "Le" => Compare::Le,
+                    "Ge" => Compare::Ge,
+                    _ => return Err(Diagnostic::at(format!("Unknown comparison `{}`", cmp), span)),
+                };
+                CmpJz(cmp, self.label_operand(label, span)?)
+            }
+            "PrintStr" => {
+                let bytes = unquote(operand).map_err(|message| Diagnostic::at(message, span))?;
+                PrintStr(String::from_utf8_lossy(&bytes).into_owned())
+            }
+            _ => return Err(Diagnostic::at(format!("Unknown instruction `{}`", name), span)),
+        })
+    }
+
+    fn label_operand(&mut self, operand: &str, span: Span) -> Result<usize, Diagnostic> {
+        if !is_name(operand) {
+            return Err(Diagnostic::at(format!("Expected a label, found `{}`", operand), span));
+        }
+        Ok(self.label(operand))
+    }
+}
+
+/// Whether `s` can name a label, function or data symbol.
+fn is_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// The two comma-separated operands of `AddLocal` and `CmpJz`.
+fn pair(operand: &str, span: Span) -> Result<(&str, &str), Diagnostic> {
+    operand
+        .split_once(',')
+        .map(|(a, b)| (a.trim(), b.trim()))
+        .ok_or_else(|| Diagnostic::at(format!("Expected two operands, found `{}`", operand), span))
+}
+
+fn number(operand: &str, span: Span) -> Result<usize, Diagnostic> {
+    operand.parse().map_err(|_| Diagnostic::at(format!("Expected a count, found `{}`", operand), span))
+}
+
+fn float(operand: &str, span: Span) -> Result<f64, Diagnostic> {
+    operand.parse().map_err(|_| Diagnostic::at(format!("Expected a number, found `{}`", operand), span))
+}
+
+/// `line` up to a `;` that isn't inside a string literal.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// The bytes of a double-quoted literal written by `quote`: `\n`, `\t`, `\r`, `\"`, `\\`,
+/// `\0` and `\xNN` escapes.
+fn unquote(literal: &str) -> Result<Vec<u8>, String> {
+    let body = literal
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .filter(|_| literal.len() >= 2)
+        .ok_or_else(|| format!("Expected a string in double quotes, found `{}`", literal))?;
+    let mut bytes = Vec::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        bytes.push(match chars.next() {
+            Some('n') => b'\n',
+            Some('t') => b'\t',
+            Some('r') => b'\r',
+            Some('0') => 0,
+            Some('"') => b'"',
+            Some('\\') => b'\\',
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                u8::from_str_radix(&hex, 16).map_err(|_| format!("Invalid escape `\\x{}`", hex))?
+            }
+            other => return Err(format!("Invalid escape `\\{}`", other.map_or(String::new(), String::from))),
+        });
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,289 @@
+//! The abstract syntax tree produced by the `Parser` and lowered to bytecode by `codegen`.
+//!
+//! A `TranslationUnit` is the list of top-level declarations of one (preprocessed) source
+//! file: enums, globals, function definitions, and — as an extension over C4 — statements and
+//! blocks outside any function, which run before `main`. Every expression and statement
+//! carries the `Span` of the source it was parsed from, so later passes can report errors
+//! against it and map the instructions they emit back to the source.
+
+use crate::token::Span;
+use crate::vm::WORD;
+
+/// The static type of a variable or expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Char,
+    Int,
+    Float,
+    Void,
+    Ptr(Box<Type>),
+}
+
+impl Type {
+    /// Size in bytes of a value of this type in memory.
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Char | Type::Void => 1,
+            _ => WORD,
+        }
+    }
+
+    /// A pointer to this type.
+    pub fn ptr(self) -> Type {
+        Type::Ptr(Box::new(self))
+    }
+
+    /// How far `+ 1` (or `++`) moves a value of this type: the pointee size for pointers.
+    pub fn stride(&self) -> i64 {
+        match self {
+            Type::Ptr(t) => t.size() as i64,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslationUnit {
+    pub decls: Vec<Decl>,
+}
+
+/// A top-level item.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decl {
+    Enum(EnumDecl),
+    Global(Global),
+    Function(Function),
+    Stmt(Stmt), // a statement or block outside any function
+}
+
+/// `enum [name] { A, B = 5, C };` with each member's value worked out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumDecl {
+    pub name: Option<String>,
+    pub members: Vec<(String, i64)>,
+    pub span: Span,
+}
+
+/// A global variable: one zero-initialised word in the data segment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub return_type: Type,
+    pub params: Vec<(String, Type)>,
+    pub body: Vec<Stmt>,
+    pub span: Span, // the whole definition
+    pub end: Span,  // the closing `}`, where falling off the end returns
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Locals(Vec<(String, Type)>), // `int a, *b;`
+    Expr(Expr),
+    If {
+        cond: Expr,
+        then: Box<Stmt>,
+        otherwise: Option<Box<Stmt>>,
+    },
+    While {
+        cond: Expr,
+        body: Box<Stmt>,
+    },
+    Return(Option<Expr>),
+    Block(Vec<Stmt>),
+    Empty, // a lone `;`
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Int(i64),
+    Float(f64),
+    Char(char), // a single byte, as a signed `char`
+    Str(Vec<u8>), // bytes of the literal, without the terminating NUL
+    Var(String),
+    Call {
+        name: String,
+        args: Vec<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Assign {
+        target: Box<Expr>,
+        value: Box<Expr>,
+    },
+    Cond {
+        cond: Box<Expr>,
+        then: Box<Expr>,
+        otherwise: Box<Expr>,
+    },
+    Index {
+        base: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Cast {
+        ty: Type,
+        expr: Box<Expr>,
+    },
+    Sizeof(Type),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,     // -x
+    Plus,    // +x
+    Not,     // !x
+    BitNot,  // ~x
+    Deref,   // *p
+    AddrOf,  // &x
+    PreInc,  // ++x
+    PreDec,  // --x
+    PostInc, // x++
+    PostDec, // x--
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add, Sub, Mul, Div, Mod,
+    Eq, Ne, Lt, Gt, Le, Ge,
+    BitAnd, BitOr, BitXor,
+    Shl, Shr,
+    LogAnd, LogOr,
+}
+
+impl TranslationUnit {
+    /// Reset every span in the tree, so trees parsed from differently laid out sources can
+    /// be compared by structure alone.
+    pub fn clear_spans(&mut self) {
+        fn clear(stmt: &mut Stmt) {
+            stmt.walk_mut(&mut |stmt| stmt.span = Span::default(), &mut |expr| expr.span = Span::default());
+        }
+        for decl in &mut self.decls {
+            match decl {
+                Decl::Enum(e) => e.span = Span::default(),
+                Decl::Global(global) => global.span = Span::default(),
+                Decl::Function(function) => {
+                    function.span = Span::default();
+                    function.end = Span::default();
+                    function.body.iter_mut().for_each(clear);
+                }
+                Decl::Stmt(stmt) => clear(stmt),
+            }
+        }
+    }
+}
+
+impl Function {
+    /// Whether the body uses `&` anywhere. The address might be of a local, which lives in
+    /// the frame, so the frame has to outlive every call the function makes.
+    pub fn takes_address(&self) -> bool {
+        let mut found = false;
+        for stmt in &self.body {
+            stmt.walk(&mut |_| {}, &mut |expr| {
+                found |= matches!(expr.kind, ExprKind::Unary { op: UnaryOp::AddrOf, .. })
+            });
+        }
+        found
+    }
+}
+
+/// Defines `walk` and `walk_mut` on `Stmt` and `Expr`, which call `on_stmt` and `on_expr` on
+/// the node and every statement and expression inside it, outermost first. The traversal is
+/// written once here and expanded for shared and for mutable references.
+macro_rules! walkers {
+    ($walk:ident $(, $mut:tt)?) => {
+        impl Stmt {
+            pub fn $walk(
+                &$($mut)? self,
+                on_stmt: &mut impl FnMut(&$($mut)? Stmt),
+                on_expr: &mut impl FnMut(&$($mut)? Expr),
+            ) {
+                on_stmt(self);
+                match &$($mut)? self.kind {
+                    StmtKind::Locals(_) | StmtKind::Empty | StmtKind::Return(None) => {}
+                    StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) => expr.$walk(on_stmt, on_expr),
+                    StmtKind::If { cond, then, otherwise } => {
+                        cond.$walk(on_stmt, on_expr);
+                        then.$walk(on_stmt, on_expr);
+                        if let Some(otherwise) = otherwise {
+                            otherwise.$walk(on_stmt, on_expr);
+                        }
+                    }
+                    StmtKind::While { cond, body } => {
+                        cond.$walk(on_stmt, on_expr);
+                        body.$walk(on_stmt, on_expr);
+                    }
+                    StmtKind::Block(stmts) => {
+                        for stmt in stmts {
+                            stmt.$walk(on_stmt, on_expr);
+                        }
+                    }
+                }
+            }
+        }
+
+        impl Expr {
+            pub fn $walk(
+                &$($mut)? self,
+                on_stmt: &mut impl FnMut(&$($mut)? Stmt),
+                on_expr: &mut impl FnMut(&$($mut)? Expr),
+            ) {
+                on_expr(self);
+                match &$($mut)? self.kind {
+                    ExprKind::Int(_)
+                    | ExprKind::Float(_)
+                    | ExprKind::Char(_)
+                    | ExprKind::Str(_)
+                    | ExprKind::Var(_)
+                    | ExprKind::Sizeof(_) => {}
+                    ExprKind::Call { args, .. } => {
+                        for arg in args {
+                            arg.$walk(on_stmt, on_expr);
+                        }
+                    }
+                    ExprKind::Unary { operand, .. } | ExprKind::Cast { expr: operand, .. } => {
+                        operand.$walk(on_stmt, on_expr)
+                    }
+                    ExprKind::Binary { lhs, rhs, .. }
+                    | ExprKind::Assign { target: lhs, value: rhs }
+                    | ExprKind::Index { base: lhs, index: rhs } => {
+                        lhs.$walk(on_stmt, on_expr);
+                        rhs.$walk(on_stmt, on_expr);
+                    }
+                    ExprKind::Cond { cond, then, otherwise } => {
+                        cond.$walk(on_stmt, on_expr);
+                        then.$walk(on_stmt, on_expr);
+                        otherwise.$walk(on_stmt, on_expr);
+                    }
+                }
+            }
+        }
+    };
+}
+
+walkers!(walk);
+walkers!(walk_mut, mut);
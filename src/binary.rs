@@ -0,0 +1,486 @@
+//! The `.c4b` binary format: a compiled `Program` saved to disk so it can be run without
+//! compiling again (`c4_rust compile -o prog.c4b prog.c`, then `c4_rust run prog.c4b`).
+//!
+//! A file is a header, a sequence of sections and a checksum:
+//!
+//! ```text
+//! "C4B\0"  u16 version  u16 flags (bit 0: debug info present)
+//! section*   u8 tag, u32 payload length, payload
+//!            1 code     instruction count, then one opcode byte and its operand each
+//!            2 data     the data segment, loaded at DATA_BASE
+//!            3 symbols  main entry, top-level locals, functions, globals, string literals
+//!            4 debug    the source span of every instruction (optional)
+//! u64 FNV-1a hash of everything before it
+//! ```
+//!
+//! Integers are little-endian, counts and addresses LEB128 varints, signed immediates
+//! zigzag varints and floats raw `f64` bits. `Program::read_from` checks the hash, then that
+//! every section is well formed and every address and label it mentions exists, so a damaged
+//! file is reported instead of crashing the VM.
+
+use crate::instruction::{Compare, Instruction};
+use crate::program::Program;
+use crate::token::Span;
+use crate::vm::DATA_BASE;
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+
+/// The first bytes of every `.c4b` file.
+pub const MAGIC: &[u8; 4] = b"C4B\0";
+
+/// The format version written, and the only one read.
+pub const VERSION: u16 = 1;
+
+const FLAG_DEBUG: u16 = 1;
+
+const CODE: u8 = 1;
+const DATA: u8 = 2;
+const SYMBOLS: u8 = 3;
+const DEBUG: u8 = 4;
+
+impl Program {
+    /// Write the program in the `.c4b` format. Debug info is included when the program has
+    /// spans for its instructions.
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let debug = !self.spans.is_empty() && self.spans.len() == self.code.len();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(if debug { FLAG_DEBUG } else { 0 }).to_le_bytes());
+
+        let mut code = Vec::new();
+        put_uint(&mut code, self.code.len() as u64);
+        for instr in &self.code {
+            encode(&mut code, instr);
+        }
+        section(&mut bytes, CODE, &code);
+        section(&mut bytes, DATA, &self.data);
+
+        let mut symbols = Vec::new();
+        put_uint(&mut symbols, self.main_label.map_or(0, |entry| entry as u64 + 1));
+        put_uint(&mut symbols, self.locals as u64);
+        let mut functions: Vec<(&String, &usize)> = self.functions.iter().collect();
+        functions.sort();
+        put_uint(&mut symbols, functions.len() as u64);
+        for (name, &entry) in functions {
+            put_str(&mut symbols, name);
+            put_uint(&mut symbols, entry as u64);
+            put_uint(&mut symbols, self.function_arity.get(name).copied().unwrap_or(0) as u64);
+        }
+        let mut globals: Vec<(&String, &usize)> = self.globals.iter().collect();
+        globals.sort();
+        put_uint(&mut symbols, globals.len() as u64);
+        for (name, &addr) in globals {
+            put_str(&mut symbols, name);
+            put_uint(&mut symbols, addr as u64);
+        }
+        put_uint(&mut symbols, self.strings.len() as u64);
+        for &addr in &self.strings {
+            put_uint(&mut symbols, addr as u64);
+        }
+        section(&mut bytes, SYMBOLS, &symbols);
+
+        if debug {
+            let mut spans = Vec::new();
+            for span in &self.spans {
+                for field in [span.file_id, span.start, span.end, span.line, span.column] {
+                    put_uint(&mut spans, field as u64);
+                }
+            }
+            section(&mut bytes, DEBUG, &spans);
+        }
+
+        let hash = fnv1a(&bytes);
+        bytes.extend_from_slice(&hash.to_le_bytes());
+        out.write_all(&bytes)
+    }
+
+    /// Read a program written by `write_to`. Files that are truncated, corrupted, from
+    /// another version, or that refer to code, labels or data that don't exist are rejected
+    /// with an `InvalidData` error.
+    pub fn read_from(input: &mut impl Read) -> io::Result<Program> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        if !bytes.starts_with(MAGIC) {
+            return Err(invalid("not a c4b file (bad magic number)"));
+        }
+        if bytes.len() < MAGIC.len() + 4 + 8 {
+            return Err(invalid("truncated c4b file"));
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version != VERSION {
+            return Err(invalid(format!("unsupported c4b version {} (expected {})", version, VERSION)));
+        }
+        let flags = u16::from_le_bytes([bytes[6], bytes[7]]);
+        if flags & !FLAG_DEBUG != 0 {
+            return Err(invalid(format!("unknown c4b flags {:#x}", flags)));
+        }
+        let (body, hash) = bytes.split_at(bytes.len() - 8);
+        if fnv1a(body) != u64::from_le_bytes(hash.try_into().unwrap()) {
+            return Err(invalid("corrupted c4b file (checksum mismatch)"));
+        }
+
+        let mut program = Program::default();
+        let mut seen = HashSet::new();
+        let mut sections = Reader::new(&body[8..]);
+        while !sections.is_empty() {
+            let tag = sections.byte()?;
+            let len = u32::from_le_bytes(sections.take(4)?.try_into().unwrap()) as usize;
+            let mut payload = Reader::new(sections.take(len)?);
+            if !seen.insert(tag) {
+                return Err(invalid(format!("duplicate section {}", tag)));
+            }
+            match tag {
+                CODE => {
+                    let count = payload.count()?;
+                    program.code = (0..count).map(|_| decode(&mut payload)).collect::<io::Result<_>>()?;
+                }
+                DATA => program.data = payload.take(len)?.to_vec(),
+                SYMBOLS => read_symbols(&mut payload, &mut program)?,
+                DEBUG if flags & FLAG_DEBUG != 0 => {
+                    while !payload.is_empty() {
+                        let mut field = || payload.usize();
+                        program.spans.push(Span {
+                            file_id: field()?,
+                            start: field()?,
+                            end: field()?,
+                            line: field()?,
+                            column: field()?,
+                        });
+                    }
+                }
+                _ => return Err(invalid(format!("unexpected section {}", tag))),
+            }
+            if !payload.is_empty() {
+                return Err(invalid(format!("section {} has {} trailing bytes", tag, payload.bytes.len())));
+            }
+        }
+        for (tag, name) in [(CODE, "code"), (DATA, "data"), (SYMBOLS, "symbol")] {
+            if !seen.contains(&tag) {
+                return Err(invalid(format!("missing {} section", name)));
+            }
+        }
+        if flags & FLAG_DEBUG != 0 && program.spans.len() != program.code.len() {
+            return Err(invalid("debug info does not match the code"));
+        }
+        validate(&program)?;
+        Ok(program)
+    }
+}
+
+fn read_symbols(payload: &mut Reader, program: &mut Program) -> io::Result<()> {
+    program.main_label = payload.usize()?.checked_sub(1);
+    program.locals = payload.usize()?;
+    for _ in 0..payload.count()? {
+        let name = payload.string()?;
+        program.functions.insert(name.clone(), payload.usize()?);
+        program.function_arity.insert(name, payload.usize()?);
+    }
+    for _ in 0..payload.count()? {
+        let name = payload.string()?;
+        program.globals.insert(name, payload.usize()?);
+    }
+    for _ in 0..payload.count()? {
+        program.strings.push(payload.usize()?);
+    }
+    Ok(())
+}
+
+/// Check that every address and label the program refers to exists.
+fn validate(program: &Program) -> io::Result<()> {
+    let len = program.code.len();
+    let mut labels = HashSet::new();
+    for instr in &program.code {
+        if let Instruction::Label(id) = instr
+            && !labels.insert(*id)
+        {
+            return Err(invalid(format!("label {} is defined twice", id)));
+        }
+    }
+    for (i, instr) in program.code.iter().enumerate() {
+        match instr {
+            Instruction::Jmp(id) | Instruction::Jz(id) | Instruction::Jnz(id) | Instruction::CmpJz(_, id)
+                if !labels.contains(id) => {
+                return Err(invalid(format!("instruction {} jumps to missing label {}", i, id)));
+            }
+            Instruction::Call(addr) | Instruction::TailCall(addr) if *addr >= len => {
+                return Err(invalid(format!("instruction {} calls address {} outside the code", i, addr)));
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, entry)) = program.functions.iter().find(|&(_, &entry)| entry >= len) {
+        return Err(invalid(format!("function `{}` starts at {} outside the code", name, entry)));
+    }
+    if program.main_label.is_some_and(|entry| entry >= len) {
+        return Err(invalid("main entry point is outside the code"));
+    }
+    let data = DATA_BASE..DATA_BASE + program.data.len();
+    if let Some((name, _)) = program.globals.iter().find(|&(_, &addr)| !data.contains(&addr)) {
+        return Err(invalid(format!("global `{}` is outside the data section", name)));
+    }
+    for &addr in &program.strings {
+        if !data.contains(&addr) || !program.data[addr - DATA_BASE..].contains(&0) {
+            return Err(invalid(format!("string literal at {:#x} is outside the data section", addr)));
+        }
+    }
+    Ok(())
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn section(out: &mut Vec<u8>, tag: u8, payload: &[u8]) {
+    out.push(tag);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+}
+
+fn put_uint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_int(out: &mut Vec<u8>, value: i64) {
+    put_uint(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_uint(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Append `instr` as its opcode byte followed by its operand, if any.
+fn encode(out: &mut Vec<u8>, instr: &Instruction) {
+    use Instruction::*;
+    let (opcode, operand): (u8, Option<u64>) = match instr {
+        Imm(value) => {
+            out.push(0);
+            return put_int(out, *value);
+        }
+        ImmF(value) => (1, Some(value.to_bits())),
+        PushF(value) => (2, Some(value.to_bits())),
+        PrintStr(s) => {
+            out.push(3);
+            return put_str(out, s);
+        }
+        AddLocal(slot, n) => {
+            out.push(16);
+            put_uint(out, *slot as u64);
+            return put_int(out, *n);
+        }
+        CmpJz(cmp, id) => {
+            out.push(17);
+            out.push(*cmp as u8);
+            return put_uint(out, *id as u64);
+        }
+        Addr(n) => (4, Some(*n as u64)),
+        Printf(n) => (5, Some(*n as u64)),
+        Scanf(n) => (6, Some(*n as u64)),
+        Open(n) => (7, Some(*n as u64)),
+        Jmp(id) => (8, Some(*id as u64)),
+        Jz(id) => (9, Some(*id as u64)),
+        Jnz(id) => (10, Some(*id as u64)),
+        Label(id) => (11, Some(*id as u64)),
+        LoadLocal(n) => (12, Some(*n as u64)),
+        StoreLocal(n) => (13, Some(*n as u64)),
+        Call(addr) => (14, Some(*addr as u64)),
+        Enter(n) => (15, Some(*n as u64)),
+        TailCall(addr) => (18, Some(*addr as u64)),
+        Add => (32, None),
+        Sub => (33, None),
+        Mul => (34, None),
+        Div => (35, None),
+        Mod => (36, None),
+        Neg => (37, None),
+        Not => (38, None),
+        Deref => (39, None),
+        Cast => (40, None),
+        DerefChar => (41, None),
+        Store => (42, None),
+        StoreChar => (43, None),
+        Dup => (44, None),
+        Pop => (45, None),
+        Eq => (46, None),
+        Ne => (47, None),
+        Lt => (48, None),
+        Gt => (49, None),
+        Le => (50, None),
+        Ge => (51, None),
+        BitAnd => (52, None),
+        BitOr => (53, None),
+        BitXor => (54, None),
+        Shl => (55, None),
+        Shr => (56, None),
+        Print => (57, None),
+        PrintF => (58, None),
+        Getchar => (59, None),
+        Putchar => (60, None),
+        Read => (61, None),
+        Write => (62, None),
+        Close => (63, None),
+        Exit => (64, None),
+        Malloc => (65, None),
+        Free => (66, None),
+        Memset => (67, None),
+        Memcmp => (68, None),
+        Leave => (69, None),
+    };
+    out.push(opcode);
+    match (opcode, operand) {
+        (1 | 2, Some(bits)) => out.extend_from_slice(&bits.to_le_bytes()),
+        (_, Some(value)) => put_uint(out, value),
+        (_, None) => {}
+    }
+}
+
+/// The inverse of `encode`.
+fn decode(input: &mut Reader) -> io::Result<Instruction> {
+    use Instruction::*;
+    let opcode = input.byte()?;
+    Ok(match opcode {
+        0 => Imm(input.int()?),
+        1 => ImmF(input.float()?),
+        2 => PushF(input.float()?),
+        3 => PrintStr(input.string()?),
+        4 => Addr(input.usize()?),
+        5 => Printf(input.usize()?),
+        6 => Scanf(input.usize()?),
+        7 => Open(input.usize()?),
+        8 => Jmp(input.usize()?),
+        9 => Jz(input.usize()?),
+        10 => Jnz(input.usize()?),
+        11 => Label(input.usize()?),
+        12 => LoadLocal(input.usize()?),
+        13 => StoreLocal(input.usize()?),
+        14 => Call(input.usize()?),
+        15 => Enter(input.usize()?),
+        16 => AddLocal(input.usize()?, input.int()?),
+        17 => {
+            let cmp = match input.byte()? {
+                0 => Compare::Eq,
+                1 => Compare::Ne,
+                2 => Compare::Lt,
+                3 => Compare::Gt,
+                4 => Compare::Le,
+                5 => Compare::Ge,
+                other => return Err(invalid(format!("unknown comparison {}", other))),
+            };
+            CmpJz(cmp, input.usize()?)
+        }
+        18 => TailCall(input.usize()?),
+        32 => Add,
+        33 => Sub,
+        34 => Mul,
+        35 => Div,
+        36 => Mod,
+        37 => Neg,
+        38 => Not,
+        39 => Deref,
+        40 => Cast,
+        41 => DerefChar,
+        42 => Store,
+        43 => StoreChar,
+        44 => Dup,
+        45 => Pop,
+        46 => Eq,
+        47 => Ne,
+        48 => Lt,
+        49 => Gt,
+        50 => Le,
+        51 => Ge,
+        52 => BitAnd,
+        53 => BitOr,
+        54 => BitXor,
+        55 => Shl,
+        56 => Shr,
+        57 => Print,
+        58 => PrintF,
+        59 => Getchar,
+        60 => Putchar,
+        61 => Read,
+        62 => Write,
+        63 => Close,
+        64 => Exit,
+        65 => Malloc,
+        66 => Free,
+        67 => Memset,
+        68 => Memcmp,
+        69 => Leave,
+        _ => return Err(invalid(format!("unknown opcode {}", opcode))),
+    })
+}
+
+/// A bounds-checked cursor over a section's bytes; running off the end is a truncated file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if n > self.bytes.len() {
+            return Err(invalid("truncated c4b file"));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn uint(&mut self) -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            value |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("malformed varint"))
+    }
+
+    fn usize(&mut self) -> io::Result<usize> {
+        usize::try_from(self.uint()?).map_err(|_| invalid("value out of range"))
+    }
+
+    /// A count of items that follow, each at least one byte long.
+    fn count(&mut self) -> io::Result<usize> {
+        let count = self.usize()?;
+        if count > self.bytes.len() {
+            return Err(invalid("truncated c4b file"));
+        }
+        Ok(count)
+    }
+
+    fn int(&mut self) -> io::Result<i64> {
+        let raw = self.uint()?;
+        Ok((raw >> 1) as i64 ^ -((raw & 1) as i64))
+    }
+
+    fn float(&mut self) -> io::Result<f64> {
+        Ok(f64::from_bits(u64::from_le_bytes(self.take(8)?.try_into().unwrap())))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.usize()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| invalid("symbol is not valid UTF-8"))
+    }
+}
@@ -0,0 +1,739 @@
+//! Lowering of the syntax tree (see `ast`) to the VM's linear instruction sequence.
+//!
+//! `CodeGen` walks a `TranslationUnit` in source order, resolving names (locals, then globals,
+//! then enum constants), working out the static type of every expression to choose load and
+//! store widths and scale pointer arithmetic, and laying out globals and string literals in
+//! the data segment. Name and type errors are reported here, against the span of the offending
+//! node; like the parser, it gives up on the broken statement and carries on with the next.
+//! Each emitted instruction is attributed to the span of the node it was emitted for.
+
+use crate::ast::{
+    BinaryOp, Decl, Expr, ExprKind, Function, Stmt, StmtKind, TranslationUnit, Type, UnaryOp,
+};
+use crate::diagnostic::Diagnostic;
+use crate::instruction::Instruction;
+use crate::pretty::type_name;
+use crate::token::Span;
+use crate::vm::{DATA_BASE, WORD};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// A name or type error ends code generation for the statement containing it.
+type GenResult<T = ()> = Result<T, Diagnostic>;
+
+/// Where an assignable expression lives, once its load has been taken back off the code.
+enum Place {
+    Local(usize), // frame slot
+    Memory,       // the address is on top of the stack
+}
+
+pub struct CodeGen {
+    pub code: Vec<Instruction>,
+    pub spans: Vec<Span>, // source of each instruction in `code`
+    span: Span,           // the node being generated
+    label_id: usize,
+    pub functions: HashMap<String, usize>, // name → entry address
+    pub function_arity: HashMap<String, usize>,
+    pub main_label: Option<usize>,
+    pub locals: HashMap<String, usize>, // name → frame slot
+    next_local_offset: usize,
+    current_fn: Option<String>, // name of the function being generated, or None
+    tail_calls: bool,           // whether `return f(...)` may reuse the current frame
+    pub data: Vec<u8>,          // string literals and globals, loaded at `DATA_BASE`
+    pub strings: Vec<usize>,    // VM addresses of the string literals
+    local_types: HashMap<String, Type>,
+    globals: HashMap<String, (usize, Type)>, // name → (VM address, type)
+    constants: HashMap<String, i64>,         // enum members
+    return_types: HashMap<String, Type>,
+    ty: Type, // type of the expression generated last
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Default for CodeGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CodeGen {
+    pub fn new() -> Self {
+        Self {
+            code: Vec::new(),
+            spans: Vec::new(),
+            span: Span::default(),
+            label_id: 0,
+            functions: HashMap::new(),
+            function_arity: HashMap::new(),
+            main_label: None,
+            locals: HashMap::new(),
+            next_local_offset: 0,
+            current_fn: None,
+            tail_calls: false,
+            data: Vec::new(),
+            strings: Vec::new(),
+            local_types: HashMap::new(),
+            globals: HashMap::new(),
+            constants: HashMap::new(),
+            return_types: HashMap::new(),
+            ty: Type::Int,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Generate code for every item of `unit`. Errors are collected in `self.diagnostics`.
+    pub fn generate(&mut self, unit: &TranslationUnit) {
+        for decl in &unit.decls {
+            match decl {
+                Decl::Enum(decl) => self.constants.extend(decl.members.iter().cloned()),
+                Decl::Global(global) => {
+                    let offset = self.data.len().next_multiple_of(WORD);
+                    self.data.resize(offset + WORD, 0);
+                    self.globals.insert(global.name.clone(), (DATA_BASE + offset, global.ty.clone()));
+                }
+                Decl::Function(function) => self.function(function),
+                Decl::Stmt(stmt) => self.recover(stmt),
+            }
+        }
+
+        // fake exit label for top-level Jmp(9999)
+        self.emit(Instruction::Label(9999));
+    }
+
+    /// The VM address of every global variable, by name.
+    pub fn global_addresses(&self) -> HashMap<String, usize> {
+        self.globals.iter().map(|(name, (addr, _))| (name.clone(), *addr)).collect()
+    }
+
+    /// Append `instr`, attributed to the node being generated.
+    fn emit(&mut self, instr: Instruction) {
+        self.code.push(instr);
+        self.spans.push(self.span);
+    }
+
+    fn new_label(&mut self) -> usize {
+        let id = self.label_id;
+        self.label_id += 1;
+        id
+    }
+
+    /// Give `name` the next frame slot.
+    fn declare_local(&mut self, name: String, ty: Type) {
+        self.locals.insert(name.clone(), self.next_local_offset);
+        self.local_types.insert(name, ty);
+        self.next_local_offset += 1;
+    }
+
+    fn function(&mut self, function: &Function) {
+        let name = &function.name;
+        self.current_fn = Some(name.clone());
+        // `main` isn't entered by a call, so it has no caller to return to in its place
+        self.tail_calls = name != "main" && !function.takes_address();
+        self.span = function.span;
+
+        // record entry point
+        let entry = self.code.len();
+        self.functions.insert(name.clone(), entry);
+        self.function_arity.insert(name.clone(), function.params.len());
+        self.return_types.insert(name.clone(), function.return_type.clone());
+        if name == "main" {
+            self.main_label = Some(entry);
+        }
+
+        // reset locals & assign parameter slots
+        self.locals.clear();
+        self.local_types.clear();
+        self.next_local_offset = 0;
+        for (p, ty) in &function.params {
+            self.declare_local(p.clone(), ty.clone());
+        }
+
+        // placeholder Enter, we'll patch after the body
+        self.emit(Instruction::Enter(0));
+
+        for stmt in &function.body {
+            self.recover(stmt);
+        }
+
+        // patch Enter with the number of slots handed out (params + any `int x;` inside)
+        let total_slots = self.next_local_offset;
+        if let Instruction::Enter(ref mut cnt) = self.code[entry] {
+            *cnt = total_slots;
+        }
+
+        // falling off the end returns 0
+        self.span = function.end;
+        self.emit(Instruction::Imm(0));
+        if name == "main" {
+            self.emit(Instruction::Jmp(9999));
+        } else {
+            self.emit(Instruction::Leave);
+        }
+
+        self.current_fn = None;
+        self.tail_calls = false;
+    }
+
+    /// Generate `stmt`, recording its error (if any) and moving on.
+    fn recover(&mut self, stmt: &Stmt) {
+        if let Err(diagnostic) = self.stmt(stmt) {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> GenResult {
+        self.span = stmt.span;
+        match &stmt.kind {
+            StmtKind::Locals(vars) => {
+                for (name, ty) in vars {
+                    self.declare_local(name.clone(), ty.clone());
+                }
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(expr)?;
+                // the value of an expression statement is discarded
+                self.span = stmt.span;
+                self.emit(Instruction::Pop);
+            }
+            StmtKind::If { cond, then, otherwise } => {
+                self.expr(cond)?;
+                self.span = stmt.span;
+                let false_label = self.new_label();
+                self.emit(Instruction::Jz(false_label)); // if cond == 0, jump to false_label
+
+                self.stmt(then)?;
+                self.span = stmt.span;
+
+                if let Some(otherwise) = otherwise {
+                    let end_label = self.new_label();
+                    self.emit(Instruction::Jmp(end_label)); // skip the else-body
+                    self.emit(Instruction::Label(false_label));
+                    self.stmt(otherwise)?;
+                    self.span = stmt.span;
+                    self.emit(Instruction::Label(end_label));
+                } else {
+                    self.emit(Instruction::Label(false_label));
+                }
+            }
+            StmtKind::While { cond, body } => {
+                let start_label = self.new_label();
+                let end_label = self.new_label();
+
+                self.emit(Instruction::Label(start_label)); // loop start
+                self.expr(cond)?; // loop condition
+                self.span = stmt.span;
+                self.emit(Instruction::Jz(end_label)); // break if false
+
+                self.stmt(body)?; // loop body
+                self.span = stmt.span;
+
+                self.emit(Instruction::Jmp(start_label)); // jump back to start
+                self.emit(Instruction::Label(end_label)); // loop end
+            }
+            // `return f(...)` in tail position: the callee takes over this frame and returns
+            // straight to our caller, so tail recursion runs in constant stack space
+            StmtKind::Return(Some(Expr { kind: ExprKind::Call { name, args }, span }))
+                if self.tail_calls && name != "print" && self.functions.contains_key(name) =>
+            {
+                self.check_arity(*span, name, args.len())?;
+                self.call_args(args)?;
+                self.span = stmt.span;
+                self.emit(Instruction::TailCall(self.functions[name]));
+            }
+            StmtKind::Return(value) => {
+                match value {
+                    Some(value) => self.expr(value)?, // emit the return-value
+                    None => self.emit(Instruction::Imm(0)), // `return;` still hands back a value
+                }
+                self.span = stmt.span;
+
+                // if we're in a named function that's NOT `main`, emit Leave,
+                // otherwise (main or top-level) jump to exit label:
+                match &self.current_fn {
+                    Some(name) if name != "main" => self.emit(Instruction::Leave),
+                    _ => self.emit(Instruction::Jmp(9999)),
+                }
+            }
+            StmtKind::Block(stmts) => {
+                for stmt in stmts {
+                    self.recover(stmt);
+                }
+            }
+            StmtKind::Empty => {}
+        }
+        Ok(())
+    }
+
+    /// Generate `expr`, leaving its value on the stack and its type in `self.ty`.
+    fn expr(&mut self, expr: &Expr) -> GenResult {
+        let outer = self.span;
+        self.span = expr.span;
+        let result = self.expr_kind(expr);
+        self.span = outer;
+        result
+    }
+
+    fn expr_kind(&mut self, expr: &Expr) -> GenResult {
+        match &expr.kind {
+            ExprKind::Float(f) => {
+                self.emit(Instruction::ImmF(*f));
+                self.ty = Type::Float;
+            }
+            ExprKind::Int(n) => {
+                self.emit(Instruction::Imm(*n));
+                self.ty = Type::Int;
+            }
+            ExprKind::Char(c) => {
+                // `char` is signed, so '\xff' is -1
+                self.emit(Instruction::Imm(*c as u8 as i8 as i64));
+                self.ty = Type::Int;
+            }
+            ExprKind::Str(s) => {
+                let addr = self.string_literal(s);
+                self.emit(Instruction::Imm(addr));
+                self.ty = Type::Char.ptr();
+            }
+            ExprKind::Sizeof(ty) => {
+                self.emit(Instruction::Imm(ty.size() as i64));
+                self.ty = Type::Int;
+            }
+            ExprKind::Var(name) => {
+                if let Some(&offset) = self.locals.get(name) {
+                    self.emit(Instruction::LoadLocal(offset));
+                    self.ty = self.local_types[name].clone();
+                } else if let Some((addr, ty)) = self.globals.get(name).cloned() {
+                    self.emit(Instruction::Imm(addr as i64));
+                    self.emit_load(&ty);
+                    self.ty = ty;
+                } else if let Some(&value) = self.constants.get(name) {
+                    self.emit(Instruction::Imm(value));
+                    self.ty = Type::Int;
+                } else {
+                    let error = Diagnostic::at(format!("Undefined variable `{}`", name), expr.span);
+                    let known = self.locals.keys().chain(self.globals.keys());
+                    return Err(error.with_suggestion(name, known));
+                }
+            }
+            ExprKind::Call { name, args } if name == "print" => self.print(expr.span, args)?,
+            ExprKind::Call { name, args } => {
+                // a call: user function first, then VM builtins
+                let argc = args.len();
+                if self.functions.contains_key(name) {
+                    self.check_arity(expr.span, name, argc)?;
+                }
+                self.call_args(args)?;
+                if let Some(&addr) = self.functions.get(name) {
+                    self.emit(Instruction::Call(addr));
+                    self.ty = self.return_types[name].clone();
+                } else if let Some((builtin, arity)) = builtin_instruction(name, argc) {
+                    if !arity.contains(&argc) {
+                        let message = format!("`{}` called with {} argument(s)", name, argc);
+                        return Err(Diagnostic::at(message, name_span(expr.span, name)));
+                    }
+                    self.emit(builtin);
+                    self.ty = Type::Int;
+                } else {
+                    let message = format!("Unknown function `{}`", name);
+                    let error = Diagnostic::at(message, name_span(expr.span, name));
+                    return Err(error.with_suggestion(name, self.functions.keys()));
+                }
+            }
+            ExprKind::Unary { op, operand } => self.unary(expr.span, *op, operand)?,
+            ExprKind::Binary { op, lhs, rhs } => self.binary(*op, lhs, rhs)?,
+            ExprKind::Assign { target, value } => {
+                self.expr(target)?;
+                let ty = self.ty.clone();
+                let place = self.take_place("assignment", target.span)?;
+                self.expr(value)?;
+                self.emit_store(place, &ty);
+                self.ty = ty;
+            }
+            ExprKind::Cond { cond, then, otherwise } => {
+                self.expr(cond)?;
+                let else_lbl = self.new_label();
+                self.emit(Instruction::Jz(else_lbl));
+
+                self.expr(then)?;
+                let then_ty = self.ty.clone();
+
+                let end_lbl = self.new_label();
+                self.emit(Instruction::Jmp(end_lbl));
+                self.emit(Instruction::Label(else_lbl));
+
+                self.expr(otherwise)?;
+                self.emit(Instruction::Label(end_lbl));
+                self.ty = conditional_type(&then_ty, &self.ty).ok_or_else(|| {
+                    let message = format!(
+                        "Mismatched types `{}` and `{}` in conditional expression",
+                        type_name(&then_ty),
+                        type_name(&self.ty)
+                    );
+                    Diagnostic::at(message, expr.span)
+                })?;
+            }
+            ExprKind::Index { base, index } => {
+                self.expr(base)?;
+                let Type::Ptr(elem) = self.ty.clone() else {
+                    return Err(Diagnostic::at("Subscripted value is not a pointer", expr.span));
+                };
+                self.expr(index)?;
+                self.emit_scale(elem.size() as i64);
+                self.emit(Instruction::Add);
+                self.emit_load(&elem);
+                self.ty = *elem;
+            }
+            ExprKind::Cast { ty, expr } => {
+                self.expr(expr)?;
+                self.emit(Instruction::Cast);
+                self.ty = ty.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a call to the user function `name` passes as many arguments as it has parameters.
+    fn check_arity(&self, call: Span, name: &str, argc: usize) -> GenResult {
+        let expected = self.function_arity[name];
+        if argc == expected {
+            return Ok(());
+        }
+        let message = format!("`{}` takes {} argument(s) but {} were given", name, expected, argc);
+        Err(Diagnostic::at(message, name_span(call, name)))
+    }
+
+    /// `print(...)`: a string literal is printed verbatim, anything else as a number.
+    fn print(&mut self, span: Span, args: &[Expr]) -> GenResult {
+        match args {
+            [Expr { kind: ExprKind::Str(s), .. }] => {
+                self.emit(Instruction::PrintStr(String::from_utf8_lossy(s).into_owned()));
+            }
+            [arg] => {
+                self.expr(arg)?;
+                // floats are values on the stack like ints (`ImmF`), and `Print` formats
+                // either; `PrintF` is only for the separate float stack `PushF` fills
+                self.emit(Instruction::Print);
+            }
+            _ => {
+                let message = format!("`print` called with {} argument(s)", args.len());
+                return Err(Diagnostic::at(message, name_span(span, "print")));
+            }
+        }
+        // like any other call, `print(...)` has a value
+        self.emit(Instruction::Imm(0));
+        self.ty = Type::Int;
+        Ok(())
+    }
+
+    fn unary(&mut self, span: Span, op: UnaryOp, operand: &Expr) -> GenResult {
+        self.expr(operand)?;
+        match op {
+            UnaryOp::Neg => {
+                self.emit(Instruction::Neg);
+                if self.ty != Type::Float {
+                    self.ty = Type::Int;
+                }
+            }
+            UnaryOp::Plus => {}
+            UnaryOp::Not => {
+                self.emit(Instruction::Not);
+                self.ty = Type::Int;
+            }
+            UnaryOp::BitNot => {
+                self.emit(Instruction::Imm(-1));
+                self.emit(Instruction::BitXor);
+                self.ty = Type::Int;
+            }
+            UnaryOp::Deref => {
+                let Type::Ptr(target) = self.ty.clone() else {
+                    let star = Span { end: span.start + 1, ..span };
+                    return Err(Diagnostic::at("Cannot dereference a non-pointer value", star));
+                };
+                self.emit_load(&target);
+                self.ty = *target;
+            }
+            UnaryOp::AddrOf => {
+                match self.take_place("address-of", operand.span)? {
+                    Place::Local(offset) => self.emit(Instruction::Addr(offset)),
+                    Place::Memory => {} // the address is already on the stack
+                }
+                self.ty = self.ty.clone().ptr();
+            }
+            UnaryOp::PreInc | UnaryOp::PreDec | UnaryOp::PostInc | UnaryOp::PostDec => {
+                let ty = self.ty.clone();
+                let place = self.take_place("increment", operand.span)?;
+                let inc = matches!(op, UnaryOp::PreInc | UnaryOp::PostInc);
+                let prefix = matches!(op, UnaryOp::PreInc | UnaryOp::PreDec);
+                self.emit_step(place, &ty, inc, prefix);
+            }
+        }
+        Ok(())
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr) -> GenResult {
+        self.expr(lhs)?;
+        let lhs_ty = self.ty.clone();
+
+        match op {
+            // `&&` and `||` only evaluate their right side when needed and yield 0 or 1
+            BinaryOp::LogAnd => {
+                let false_lbl = self.new_label();
+                let end_lbl = self.new_label();
+                self.emit(Instruction::Jz(false_lbl));
+                self.expr(rhs)?;
+                self.emit(Instruction::Jz(false_lbl));
+                self.emit(Instruction::Imm(1));
+                self.emit(Instruction::Jmp(end_lbl));
+                self.emit(Instruction::Label(false_lbl));
+                self.emit(Instruction::Imm(0));
+                self.emit(Instruction::Label(end_lbl));
+                self.ty = Type::Int;
+            }
+            BinaryOp::LogOr => {
+                let true_lbl = self.new_label();
+                let end_lbl = self.new_label();
+                self.emit(Instruction::Jnz(true_lbl));
+                self.expr(rhs)?;
+                self.emit(Instruction::Jnz(true_lbl));
+                self.emit(Instruction::Imm(0));
+                self.emit(Instruction::Jmp(end_lbl));
+                self.emit(Instruction::Label(true_lbl));
+                self.emit(Instruction::Imm(1));
+                self.emit(Instruction::Label(end_lbl));
+                self.ty = Type::Int;
+            }
+
+            BinaryOp::Add | BinaryOp::Sub => {
+                let rhs_start = self.code.len();
+                self.expr(rhs)?;
+                let rhs_ty = self.ty.clone();
+                let instr = if op == BinaryOp::Add {
+                    Instruction::Add
+                } else {
+                    Instruction::Sub
+                };
+                let is_ptr = |t: &Type| matches!(t, Type::Ptr(_));
+                self.ty = match (is_ptr(&lhs_ty), is_ptr(&rhs_ty)) {
+                    // pointer difference counts elements
+                    (true, true) if op == BinaryOp::Sub => {
+                        self.emit(instr);
+                        let stride = lhs_ty.stride();
+                        if stride != 1 {
+                            self.emit(Instruction::Imm(stride));
+                            self.emit(Instruction::Div);
+                        }
+                        Type::Int
+                    }
+                    (true, _) => {
+                        self.emit_scale(lhs_ty.stride());
+                        self.emit(instr);
+                        lhs_ty
+                    }
+                    (false, true) if op == BinaryOp::Add => {
+                        // int + pointer: scale the int, which is already below the pointer
+                        let rhs = self.code.split_off(rhs_start);
+                        let rhs_spans = self.spans.split_off(rhs_start);
+                        self.emit_scale(rhs_ty.stride());
+                        self.code.extend(rhs);
+                        self.spans.extend(rhs_spans);
+                        self.emit(instr);
+                        rhs_ty
+                    }
+                    _ => {
+                        self.emit(instr);
+                        arithmetic_type(&lhs_ty, &rhs_ty)
+                    }
+                };
+            }
+
+            _ => {
+                self.expr(rhs)?;
+                let rhs_ty = self.ty.clone();
+                self.ty = Type::Int;
+                let instr = match op {
+                    BinaryOp::Mul => {
+                        self.ty = arithmetic_type(&lhs_ty, &rhs_ty);
+                        Instruction::Mul
+                    }
+                    BinaryOp::Div => {
+                        self.ty = arithmetic_type(&lhs_ty, &rhs_ty);
+                        Instruction::Div
+                    }
+                    BinaryOp::Mod => Instruction::Mod,
+
+                    BinaryOp::Eq => Instruction::Eq,
+                    BinaryOp::Ne => Instruction::Ne,
+                    BinaryOp::Lt => Instruction::Lt,
+                    BinaryOp::Gt => Instruction::Gt,
+                    BinaryOp::Le => Instruction::Le,
+                    BinaryOp::Ge => Instruction::Ge,
+
+                    BinaryOp::BitAnd => Instruction::BitAnd,
+                    BinaryOp::BitOr => Instruction::BitOr,
+                    BinaryOp::BitXor => Instruction::BitXor,
+
+                    BinaryOp::Shl => Instruction::Shl,
+                    BinaryOp::Shr => Instruction::Shr,
+
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::LogAnd | BinaryOp::LogOr => {
+                        unreachable!("handled above")
+                    }
+                };
+                self.emit(instr);
+            }
+        }
+        Ok(())
+    }
+
+    /// Take back the load that ended the last expression (`target`) so it can be assigned to
+    /// (or have its address taken) instead.
+    fn take_place(&mut self, context: &str, target: Span) -> GenResult<Place> {
+        let place = match self.code.last() {
+            Some(Instruction::LoadLocal(offset)) => Place::Local(*offset),
+            Some(Instruction::Deref | Instruction::DerefChar) => Place::Memory,
+            _ => return Err(Diagnostic::at(format!("Invalid lvalue in {}", context), target)),
+        };
+        self.code.pop();
+        self.spans.pop();
+        Ok(place)
+    }
+
+    /// Load a `ty` from the address on top of the stack.
+    fn emit_load(&mut self, ty: &Type) {
+        if ty.size() == 1 {
+            self.emit(Instruction::DerefChar);
+        } else {
+            self.emit(Instruction::Deref);
+        }
+    }
+
+    /// Store the value on top of the stack into `place`, leaving the value behind.
+    fn emit_store(&mut self, place: Place, ty: &Type) {
+        match place {
+            Place::Local(offset) => self.emit(Instruction::StoreLocal(offset)),
+            Place::Memory if ty.size() == 1 => self.emit(Instruction::StoreChar),
+            Place::Memory => self.emit(Instruction::Store),
+        }
+    }
+
+    /// Multiply the integer on top of the stack by `stride` (pointer arithmetic).
+    fn emit_scale(&mut self, stride: i64) {
+        if stride != 1 {
+            self.emit(Instruction::Imm(stride));
+            self.emit(Instruction::Mul);
+        }
+    }
+
+    /// Emit `++`/`--` on `place`, which holds a `ty`. Prefix forms yield the updated value,
+    /// postfix forms the original one.
+    fn emit_step(&mut self, place: Place, ty: &Type, inc: bool, prefix: bool) {
+        let step = ty.stride();
+        let (apply, undo) = if inc {
+            (Instruction::Add, Instruction::Sub)
+        } else {
+            (Instruction::Sub, Instruction::Add)
+        };
+        match place {
+            Place::Local(offset) => {
+                if !prefix {
+                    self.emit(Instruction::LoadLocal(offset)); // the result
+                }
+                self.emit(Instruction::LoadLocal(offset));
+                self.emit(Instruction::Imm(step));
+                self.emit(apply);
+                self.emit(Instruction::StoreLocal(offset));
+                if !prefix {
+                    self.emit(Instruction::Pop);
+                }
+            }
+            Place::Memory => {
+                self.emit(Instruction::Dup);
+                self.emit_load(ty);
+                self.emit(Instruction::Imm(step));
+                self.emit(apply);
+                self.emit_store(Place::Memory, ty);
+                if !prefix {
+                    // recover the original value from the stored one
+                    self.emit(Instruction::Imm(step));
+                    self.emit(undo);
+                }
+            }
+        }
+        self.ty = ty.clone();
+    }
+
+    /// Emit the arguments of a call right-to-left, so the first argument ends up on top of
+    /// the stack. They are generated in source order (string literals are laid out in that
+    /// order) and then reordered.
+    fn call_args(&mut self, args: &[Expr]) -> GenResult {
+        let mut chunks = Vec::new();
+        for arg in args {
+            let start = self.code.len();
+            self.expr(arg)?;
+            chunks.push((self.code.split_off(start), self.spans.split_off(start)));
+        }
+        for (code, spans) in chunks.into_iter().rev() {
+            self.code.extend(code);
+            self.spans.extend(spans);
+        }
+        Ok(())
+    }
+
+    /// Place a string literal in the data segment and return its VM address.
+    fn string_literal(&mut self, s: &[u8]) -> i64 {
+        let offset = self.data.len();
+        self.data.extend_from_slice(s);
+        self.data.push(0);
+        self.strings.push(DATA_BASE + offset);
+        (DATA_BASE + offset) as i64
+    }
+}
+
+/// The span of the function name at the start of the call expression spanning `call`.
+fn name_span(call: Span, name: &str) -> Span {
+    Span { end: call.start + name.len(), ..call }
+}
+
+/// Result type of `*`, `/`, `+` and `-` on two non-pointer operands.
+fn arithmetic_type(lhs: &Type, rhs: &Type) -> Type {
+    if *lhs == Type::Float || *rhs == Type::Float {
+        Type::Float
+    } else {
+        Type::Int
+    }
+}
+
+/// Type of `c ? a : b` whose branches have types `then` and `otherwise`: the usual
+/// arithmetic conversions for numbers, and the pointer when the other branch is an integer
+/// (like `p ? p : 0`). `None` when the two can't be reconciled.
+fn conditional_type(then: &Type, otherwise: &Type) -> Option<Type> {
+    let is_integer = |ty: &Type| matches!(ty, Type::Char | Type::Int);
+    match (then, otherwise) {
+        _ if then == otherwise => Some(then.clone()),
+        (Type::Ptr(_), other) | (other, Type::Ptr(_)) if is_integer(other) => {
+            Some(if is_integer(then) { otherwise.clone() } else { then.clone() })
+        }
+        (Type::Ptr(_) | Type::Void, _) | (_, Type::Ptr(_) | Type::Void) => None,
+        _ => Some(arithmetic_type(then, otherwise)),
+    }
+}
+
+/// Map a call to a VM builtin onto its instruction and the argument counts it accepts.
+/// Returns `None` when `name` is not a builtin.
+fn builtin_instruction(name: &str, argc: usize) -> Option<(Instruction, RangeInclusive<usize>)> {
+    let (instr, arity) = match name {
+        "printf" => (Instruction::Printf(argc), 1..=usize::MAX),
+        "scanf" => (Instruction::Scanf(argc), 1..=usize::MAX),
+        "getchar" => (Instruction::Getchar, 0..=0),
+        "putchar" => (Instruction::Putchar, 1..=1),
+        "open" => (Instruction::Open(argc), 2..=3),
+        "read" => (Instruction::Read, 3..=3),
+        "write" => (Instruction::Write, 3..=3),
+        "close" => (Instruction::Close, 1..=1),
+        "malloc" => (Instruction::Malloc, 1..=1),
+        "free" => (Instruction::Free, 1..=1),
+        "memset" => (Instruction::Memset, 3..=3),
+        "memcmp" => (Instruction::Memcmp, 3..=3),
+        "exit" => (Instruction::Exit, 1..=1),
+        _ => return None,
+    };
+    Some((instr, arity))
+}
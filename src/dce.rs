@@ -0,0 +1,155 @@
+//! Dead code elimination (`-O1`): removes the instructions no path from the entry point can
+//! reach, and the functions nothing reachable calls.
+//!
+//! The code generator leaves some behind on its own: the `Imm 0; Leave` ending every
+//! function even when its last statement returned, or the jump over an `else` when the
+//! `then` branch returned. Constant folding adds more by turning `if (0)` into a jump over
+//! the branch. The pass follows the control flow graph from `Program::entry`, and from the
+//! entry of each function called along the way, and keeps only what it reaches. Function
+//! entries and `Call` targets are moved to match, and removed functions are forgotten.
+//!
+//! What is removed can also be reported as warnings (`c4_rust -Wdead-code`): a function that
+//! is never called, and each statement that can never run. Labels, jumps and a function's
+//! implicit `return 0` are the code generator's doing, so they go without a warning.
+
+use crate::diagnostic::Diagnostic;
+use crate::instruction::Instruction;
+use crate::optimize::relocate;
+use crate::program::Program;
+use crate::token::Span;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Remove `program`'s unreachable code and uncalled functions, returning warnings about
+/// them (see `dead_code_warnings`).
+pub fn eliminate_dead_code(program: &mut Program) -> Vec<Diagnostic> {
+    let live = reachable(program);
+    let warnings = warnings(program, &live);
+
+    // an entry outside the code is left for the verifier to reject
+    let dead: Vec<String> = program
+        .functions
+        .iter()
+        .filter(|&(_, &entry)| !live.get(entry).copied().unwrap_or(true))
+        .map(|(name, _)| name.clone())
+        .collect();
+    for name in &dead {
+        program.functions.remove(name);
+        program.function_arity.remove(name);
+    }
+
+    let mut code = Vec::new();
+    let mut spans = Vec::new();
+    let mut new_index = Vec::with_capacity(program.code.len() + 1);
+    for (i, instr) in program.code.iter().enumerate() {
+        new_index.push(code.len());
+        if live[i] {
+            code.push(instr.clone());
+            spans.extend(program.spans.get(i).copied());
+        }
+    }
+    new_index.push(code.len());
+    relocate(program, code, spans, &new_index);
+    warnings
+}
+
+/// Warnings about the dead code in `program`, without removing it: "function `f` is never
+/// called" at each uncalled function, and "unreachable code" at the first statement of each
+/// stretch that can't run. A program without spans gets none, having nothing to point at.
+pub fn dead_code_warnings(program: &Program) -> Vec<Diagnostic> {
+    warnings(program, &reachable(program))
+}
+
+/// The labels closing the code, which `return` in `main` jumps to, start here.
+fn exit(code: &[Instruction]) -> usize {
+    code.len() - code.iter().rev().take_while(|i| matches!(i, Instruction::Label(_))).count()
+}
+
+/// Which instructions some path from the entry point reaches. The exit labels always count.
+fn reachable(program: &Program) -> Vec<bool> {
+    let code = &program.code;
+    let labels: HashMap<usize, usize> = code
+        .iter()
+        .enumerate()
+        .filter_map(|(i, instr)| match instr {
+            Instruction::Label(id) => Some((*id, i)),
+            _ => None,
+        })
+        .collect();
+
+    let mut live = vec![false; code.len()];
+    live[exit(code)..].fill(true);
+    let mut pending = vec![program.entry()];
+    while let Some(ip) = pending.pop() {
+        if ip >= code.len() || live[ip] {
+            continue;
+        }
+        live[ip] = true;
+        match &code[ip] {
+            Instruction::Jmp(id) => pending.extend(labels.get(id)),
+            Instruction::Jz(id) | Instruction::Jnz(id) | Instruction::CmpJz(_, id) => {
+                pending.extend(labels.get(id));
+                pending.push(ip + 1);
+            }
+            Instruction::Call(addr) => pending.extend([*addr, ip + 1]),
+            Instruction::TailCall(addr) => pending.push(*addr),
+            Instruction::Leave | Instruction::Exit => {}
+            _ => pending.push(ip + 1),
+        }
+    }
+    live
+}
+
+fn warnings(program: &Program, live: &[bool]) -> Vec<Diagnostic> {
+    let code = &program.code;
+    if program.spans.len() != code.len() {
+        return Vec::new();
+    }
+    let mut entries: Vec<(usize, &str)> =
+        program.functions.iter().map(|(name, &entry)| (entry, name.as_str())).collect();
+    entries.sort_unstable();
+
+    // the code generator's own instructions: labels, jumps and each function's closing
+    // `Imm 0; Leave` (`Imm 0; Jmp L9999` in `main`)
+    let mut generated: Vec<bool> = code.iter().map(|i| matches!(i, Instruction::Label(_) | Instruction::Jmp(_))).collect();
+    let ends = entries.iter().map(|&(entry, _)| entry).chain([exit(code)]);
+    for end in ends.filter(|&end| (2..=code.len()).contains(&end)) {
+        if code[end - 2] == Instruction::Imm(0) && matches!(code[end - 1], Instruction::Leave | Instruction::Jmp(_)) {
+            generated[end - 2] = true;
+            generated[end - 1] = true;
+        }
+    }
+
+    // an unreachable stretch is reported at its outermost span, the statement it starts with
+    let mut warnings = Vec::new();
+    let mut stretch: Option<Span> = None;
+    let mut in_dead_function = false;
+    for (i, &reached) in live.iter().enumerate() {
+        if let Ok(found) = entries.binary_search_by_key(&i, |&(entry, _)| entry) {
+            flush(&mut stretch, &mut warnings);
+            in_dead_function = !reached;
+            if !reached {
+                let message = format!("function `{}` is never called", entries[found].1);
+                warnings.push(Diagnostic::at(message, program.spans[i]));
+            }
+        }
+        if reached {
+            flush(&mut stretch, &mut warnings);
+            in_dead_function = false;
+        } else if !in_dead_function && !generated[i] {
+            let span = program.spans[i];
+            stretch = match stretch {
+                Some(outer) if (outer.start, Reverse(outer.end)) <= (span.start, Reverse(span.end)) => Some(outer),
+                _ => Some(span),
+            };
+        }
+    }
+    flush(&mut stretch, &mut warnings);
+    warnings
+}
+
+fn flush(stretch: &mut Option<Span>, warnings: &mut Vec<Diagnostic>) {
+    if let Some(span) = stretch.take() {
+        warnings.push(Diagnostic::at("unreachable code", span));
+    }
+}
@@ -0,0 +1,259 @@
+//! The `Diagnostic` struct describes an error in the user's program (a bad preprocessor
+//! directive, a malformed token, ...) together with where it was found. Compiler stages
+//! return it instead of panicking so the driver can report it and exit cleanly. The
+//! optimizer also uses it for warnings about code that can never run.
+//!
+//! `Renderer` prints diagnostics the way rustc does: a `file:line:col` header, the offending
+//! source line with the span underlined, and any notes or help, optionally in color.
+
+use crate::preprocessor::Preprocessed;
+use crate::token::Span;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub file: Option<Box<str>>, // source file, when known
+    pub line: usize,
+    pub span: Option<Span>,   // exact range, for errors found by the lexer and parser
+    pub annotations: Vec<Annotation>,
+}
+
+/// An extra line printed under a diagnostic.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Annotation {
+    Note(String), // more context
+    Help(String), // how to fix it
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, line: usize) -> Self {
+        Self {
+            message: message.into(),
+            file: None,
+            line,
+            span: None,
+            annotations: Vec::new(),
+        }
+    }
+
+    /// A diagnostic about the source text covered by `span`.
+    pub fn at(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            span: Some(span),
+            ..Self::new(message, span.line)
+        }
+    }
+
+    /// Attribute the diagnostic to `file`.
+    pub fn in_file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into().into_boxed_str());
+        self
+    }
+
+    /// Add a note giving more context.
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.annotations.push(Annotation::Note(note.into()));
+        self
+    }
+
+    /// Add a suggestion for fixing the error.
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.annotations.push(Annotation::Help(help.into()));
+        self
+    }
+
+    /// The first suggestion attached, if any.
+    pub fn help(&self) -> Option<&str> {
+        self.annotations.iter().find_map(|a| match a {
+            Annotation::Help(help) => Some(help.as_str()),
+            Annotation::Note(_) => None,
+        })
+    }
+
+    /// Suggest the candidate closest to the misspelt `name`, if one is close enough.
+    pub fn with_suggestion<S: AsRef<str>>(self, name: &str, candidates: impl IntoIterator<Item = S>) -> Self {
+        match closest(name, candidates) {
+            Some(found) => self.with_help(format!("did you mean `{}`?", found)),
+            None => self,
+        }
+    }
+}
+
+/// `location: message`. There is no "error" or "warning" in it: the same type carries both,
+/// and only the caller knows which it holds (see `Renderer::render_warning`).
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.span) {
+            (Some(file), Some(span)) => write!(f, "{}:{}:{}: {}", file, self.line, span.column, self.message),
+            (Some(file), None) => write!(f, "{}:{}: {}", file, self.line, self.message),
+            (None, Some(span)) => write!(f, "line {}:{}: {}", self.line, span.column, self.message),
+            (None, None) => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// The candidate with the smallest edit distance to `name`, allowing roughly one typo per
+/// three characters.
+fn closest<S: AsRef<str>>(name: &str, candidates: impl IntoIterator<Item = S>) -> Option<String> {
+    let limit = name.chars().count().div_ceil(3);
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(name, c.as_ref()), c.as_ref().to_string()))
+        .filter(|(distance, candidate)| *distance <= limit && candidate != name)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Renders diagnostics against the source text their spans point into.
+pub struct Renderer<'a> {
+    file: &'a str,
+    source: &'a str,
+    origins: Option<&'a Preprocessed>, // maps preprocessed lines back to their files
+    color: bool,
+}
+
+impl<'a> Renderer<'a> {
+    /// A renderer for diagnostics about `source`, which was read from `file`.
+    pub fn new(file: &'a str, source: &'a str) -> Self {
+        Self {
+            file,
+            source,
+            origins: None,
+            color: false,
+        }
+    }
+
+    /// A renderer for diagnostics about preprocessor output; headers name the file and line
+    /// each snippet originally came from.
+    pub fn preprocessed(preprocessed: &'a Preprocessed) -> Self {
+        Self {
+            file: preprocessed.files.first().map_or("<input>", String::as_str),
+            source: &preprocessed.text,
+            origins: Some(preprocessed),
+            color: false,
+        }
+    }
+
+    /// Turn ANSI colors on or off.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Render `diagnostic` as a multi-line message ending in a newline.
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        self.render_as(diagnostic, "error", RED)
+    }
+
+    /// Render `diagnostic` as a warning rather than an error.
+    pub fn render_warning(&self, diagnostic: &Diagnostic) -> String {
+        self.render_as(diagnostic, "warning", YELLOW)
+    }
+
+    fn render_as(&self, diagnostic: &Diagnostic, severity: &str, accent: &str) -> String {
+        let (file, line) = self.locate(diagnostic);
+        let location = match diagnostic.span {
+            Some(span) => format!("{}:{}:{}", file, line, span.column),
+            None => format!("{}:{}", file, line),
+        };
+        let number = line.to_string();
+        let gutter = " ".repeat(number.len());
+        let bar = self.paint(BLUE, "|");
+
+        let mut out = format!(
+            "{}{}\n",
+            self.paint(accent, severity),
+            self.paint(BOLD, &format!(": {}", diagnostic.message))
+        );
+        out += &format!("{}{} {}\n", gutter, self.paint(BLUE, "-->"), location);
+        if let Some((text, indent, width)) = self.snippet(diagnostic) {
+            out += &format!("{} {}\n", gutter, bar);
+            out += &format!("{} {} {}\n", self.paint(BLUE, &number), bar, text);
+            out += &format!("{} {} {}{}\n", gutter, bar, indent, self.paint(accent, &"^".repeat(width)));
+        }
+        let equals = self.paint(BLUE, "=");
+        for annotation in &diagnostic.annotations {
+            let (label, text) = match annotation {
+                Annotation::Note(note) => (self.paint(BOLD, "note"), note),
+                Annotation::Help(help) => (self.paint(CYAN, "help"), help),
+            };
+            out += &format!("{} {} {}: {}\n", gutter, equals, label, text);
+        }
+        out
+    }
+
+    /// The file and line to report, mapped back through the preprocessor when we can.
+    fn locate<'d>(&'d self, diagnostic: &'d Diagnostic) -> (&'d str, usize) {
+        if let (Some(span), Some(origins)) = (diagnostic.span, self.origins)
+            && let Some(&(file_id, line)) = origins.line_origins.get(span.line.wrapping_sub(1))
+        {
+            return (&origins.files[file_id], line);
+        }
+        (diagnostic.file.as_deref().unwrap_or(self.file), diagnostic.line)
+    }
+
+    /// The source line to show, the padding before its underline and how many carets to draw.
+    fn snippet(&self, diagnostic: &Diagnostic) -> Option<(&'a str, String, usize)> {
+        let line_text = |line: usize| Some(self.source.lines().nth(line.checked_sub(1)?)?.trim_end());
+        match diagnostic.span {
+            Some(span) => {
+                let text = line_text(span.line)?;
+                // keep tabs in the padding so the carets line up under them
+                let indent: String = text
+                    .chars()
+                    .take(span.column.saturating_sub(1))
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let rest = text.chars().count().saturating_sub(span.column.saturating_sub(1));
+                // spans are byte offsets, but each character gets one caret
+                let width = self.source.get(span.start..span.end).map_or(0, |s| s.chars().count());
+                let width = width.min(rest).max(1);
+                Some((text, indent, width))
+            }
+            // a line-only diagnostic about the text we hold underlines the whole line
+            None if self.origins.is_none()
+                && diagnostic.file.as_deref().is_none_or(|file| file == self.file) =>
+            {
+                let text = line_text(diagnostic.line)?;
+                let body = text.trim_start();
+                let indent = text[..text.len() - body.len()].to_string();
+                Some((text, indent, body.chars().count().max(1)))
+            }
+            None => None,
+        }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
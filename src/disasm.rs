@@ -0,0 +1,194 @@
+//! A human-readable listing of a compiled `Program`.
+//!
+//! Each instruction is printed with its index, and each operand that refers to something is
+//! resolved: jumps show the label and the index it lands on, calls the function name, and
+//! immediates that are data addresses the string literal or global there. Function entry
+//! points get a `name/arity:` header and their `Enter` the frame size. Given the source, the
+//! line each run of instructions was compiled from is interleaved as a comment.
+//!
+//! `Program`'s `Display` is the listing without source lines; `c4_rust disasm file.c` prints
+//! it with them.
+
+use crate::instruction::Instruction;
+use crate::program::Program;
+use crate::vm::DATA_BASE;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// The label `return` in `main` and top-level code jump to.
+const EXIT_LABEL: usize = 9999;
+
+/// Width of the instruction column, after which comments start.
+const COLUMN: usize = 28;
+
+/// List `program`, interleaving the lines of `source` (what it was compiled from) if given.
+pub fn disassemble(program: &Program, source: Option<&str>) -> String {
+    let lines: Vec<&str> = source.map(|s| s.lines().collect()).unwrap_or_default();
+    let labels: HashMap<usize, usize> = program
+        .code
+        .iter()
+        .enumerate()
+        .filter_map(|(i, instr)| match instr {
+            Instruction::Label(id) => Some((*id, i)),
+            _ => None,
+        })
+        .collect();
+    let entries: HashMap<usize, &str> =
+        program.functions.iter().map(|(name, &addr)| (addr, name.as_str())).collect();
+
+    let mut out = String::new();
+    data_section(&mut out, program);
+
+    if !program.code.is_empty() && !entries.contains_key(&0) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str("<top level>:\n");
+    }
+    let mut last_line = 0;
+    for (i, instr) in program.code.iter().enumerate() {
+        if let Some(name) = entries.get(&i) {
+            let arity = program.function_arity.get(*name).copied().unwrap_or(0);
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "{}/{}:", name, arity);
+            last_line = 0;
+        }
+
+        let line = program.spans.get(i).map_or(0, |span| span.line);
+        if line != 0 && line != last_line {
+            match lines.get(line - 1) {
+                Some(text) => {
+                    let _ = writeln!(out, "      ; {:>4} | {}", line, text.trim_end());
+                }
+                None if source.is_none() => {
+                    let _ = writeln!(out, "      ; line {}", line);
+                }
+                None => {}
+            }
+            last_line = line;
+        }
+
+        let (text, comment) = describe(program, instr, &labels);
+        let _ = write!(out, "{:>5}  {}", i, text);
+        if let Some(comment) = comment {
+            let pad = COLUMN.saturating_sub(text.chars().count()).max(1);
+            let _ = write!(out, "{}; {}", " ".repeat(pad), comment);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// The header listing what the data segment holds: string literals and globals.
+fn data_section(out: &mut String, program: &Program) {
+    if program.data.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "; data: {} bytes at {:#x}", program.data.len(), DATA_BASE);
+    let mut items: Vec<(usize, String)> = program
+        .strings
+        .iter()
+        .map(|&addr| {
+            let string = string_at(program, addr).map_or("outside the data section".to_string(), |s| quote(&s));
+            (addr, format!("string {}", string))
+        })
+        .chain(program.globals.iter().map(|(name, &addr)| (addr, format!("global {}", name))))
+        .collect();
+    items.sort();
+    for (addr, what) in items {
+        let _ = writeln!(out, ";   {:#x}  {}", addr, what);
+    }
+}
+
+/// The mnemonic and operands of `instr`, and a comment resolving what they refer to.
+fn describe(program: &Program, instr: &Instruction, labels: &HashMap<usize, usize>) -> (String, Option<String>) {
+    let target = |id: &usize| {
+        let at = labels.get(id).map_or("missing".to_string(), |i| i.to_string());
+        if *id == EXIT_LABEL {
+            format!("-> {} (exit)", at)
+        } else {
+            format!("-> {}", at)
+        }
+    };
+    match instr {
+        Instruction::Label(id) => (format!("L{}:", id), None),
+        Instruction::Jmp(id) => (format!("Jmp L{}", id), Some(target(id))),
+        Instruction::Jz(id) => (format!("Jz L{}", id), Some(target(id))),
+        Instruction::Jnz(id) => (format!("Jnz L{}", id), Some(target(id))),
+        Instruction::CmpJz(cmp, id) => (format!("CmpJz {:?}, L{}", cmp, id), Some(target(id))),
+        Instruction::Call(addr) | Instruction::TailCall(addr) => {
+            let name = program.function_at(*addr).unwrap_or("?");
+            let arity = program.function_arity.get(name).copied().unwrap_or(0);
+            (mnemonic(instr), Some(format!("{}/{}", name, arity)))
+        }
+        Instruction::Enter(slots) => {
+            let plural = if *slots == 1 { "" } else { "s" };
+            (format!("Enter {}", slots), Some(format!("frame: {} slot{}", slots, plural)))
+        }
+        Instruction::Imm(value) => (format!("Imm {}", value), data_comment(program, *value)),
+        Instruction::PrintStr(s) => (format!("PrintStr {}", quote(s.as_bytes())), None),
+        other => (mnemonic(other), None),
+    }
+}
+
+/// What lives at `value`, if it is the address of a string literal or global.
+fn data_comment(program: &Program, value: i64) -> Option<String> {
+    let addr = usize::try_from(value).ok()?;
+    if program.strings.contains(&addr) {
+        return string_at(program, addr).map(|s| quote(&s));
+    }
+    let (name, _) = program.globals.iter().find(|&(_, &global)| global == addr)?;
+    Some(format!("&{}", name))
+}
+
+/// The NUL-terminated bytes at VM address `addr` of the data segment, if it is in there.
+fn string_at(program: &Program, addr: usize) -> Option<Vec<u8>> {
+    let start = addr.checked_sub(DATA_BASE).filter(|&start| start < program.data.len())?;
+    Some(program.data[start..].iter().take_while(|&&b| b != 0).copied().collect())
+}
+
+/// `bytes` as a double-quoted C string literal, which `asm` reads back as the same bytes.
+/// Printable text (UTF-8 included) is kept as is; control characters and bytes that aren't
+/// UTF-8 are written as `\x` escapes, one per byte.
+pub(crate) fn quote(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\n' => out.push_str("\\n"),
+                '\t' => out.push_str("\\t"),
+                '\r' => out.push_str("\\r"),
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                c if c.is_control() => {
+                    for b in c.encode_utf8(&mut [0; 4]).bytes() {
+                        let _ = write!(out, "\\x{:02x}", b);
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            let _ = write!(out, "\\x{:02x}", b);
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `Name operand` for the instructions whose operands need no resolving.
+pub(crate) fn mnemonic(instr: &Instruction) -> String {
+    let debug = format!("{:?}", instr);
+    match debug.split_once('(') {
+        Some((name, operand)) => format!("{} {}", name, operand.trim_end_matches(')')),
+        None => debug,
+    }
+}
+
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&disassemble(self, None))
+    }
+}
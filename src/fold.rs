@@ -0,0 +1,161 @@
+//! Constant folding and algebraic simplification (`-O1`).
+//!
+//! The code generator turns `3 + 4 * 2 - 5` into five `Imm`s and three arithmetic
+//! instructions that run every time. This pass evaluates such expressions once, at compile
+//! time: instructions are copied over one by one, and an operator whose operands were just
+//! pushed as constants replaces them with its result, so nested expressions collapse from the
+//! inside out. It also
+//!
+//! - drops operations with an identity operand: `x - 0`, `x * 1`, `x / 1`;
+//! - drops `Cast`, which does nothing at run time, and a constant that is pushed and popped;
+//! - resolves `Jz`/`Jnz` on a constant into a `Jmp` or nothing.
+//!
+//! Folding never looks back past a label or a function entry, since control may arrive there
+//! from elsewhere. Results are computed exactly as the VM would (wrapping integer arithmetic,
+//! int/float promotion); a division by zero or a `%` on floats is left for run time to report.
+//! The operand of an identity may be a float at run time even when its static type is an int
+//! (`int x; x = 1.5;` stores a float), so only identities that also hold for every float are
+//! applied. `x + 0` is kept because `-0.0 + 0` is `0.0`, and the bitwise operators and shifts
+//! because the VM rejects float operands there at run time.
+
+use crate::instruction::Instruction;
+use crate::optimize::{rewrite, Rewriter};
+use crate::program::Program;
+use crate::token::Span;
+use crate::vm::Value;
+
+/// Fold the constant expressions in `program`'s code.
+pub fn fold_constants(program: &mut Program) {
+    rewrite(program, fold);
+}
+
+/// The constant pushed `back` instructions from the end of `out` (1 is the last), if it is
+/// one and part of the current straight run.
+fn constant(out: &Rewriter, back: usize) -> Option<Value> {
+    let tail = out.tail();
+    match tail.get(tail.len().checked_sub(back)?)? {
+        Instruction::Imm(n) => Some(Value::Int(*n)),
+        Instruction::ImmF(f) => Some(Value::Flt(*f)),
+        _ => None,
+    }
+}
+
+/// Append `instr` to `out`, folding it into the constants before it where possible.
+fn fold(out: &mut Rewriter, instr: Instruction, span: Span) {
+    use Instruction::*;
+    match &instr {
+        Cast => {}
+        Pop if constant(out, 1).is_some() => out.pop(1),
+        Neg | Not => match constant(out, 1) {
+            Some(a) => {
+                out.pop(1);
+                out.emit(immediate(unary(&instr, a)), span);
+            }
+            None => out.emit(instr, span),
+        },
+        Jz(id) | Jnz(id) => match constant(out, 1) {
+            Some(cond) => {
+                out.pop(1);
+                if truthy(&cond) == matches!(instr, Jnz(_)) {
+                    out.emit(Jmp(*id), span);
+                }
+            }
+            None => out.emit(instr, span),
+        },
+        Add | Sub | Mul | Div | Mod | Eq | Ne | Lt | Gt | Le | Ge | BitAnd | BitOr | BitXor | Shl | Shr => {
+            let folded = match (constant(out, 2), constant(out, 1)) {
+                (Some(a), Some(b)) => binary(&instr, a, b),
+                _ => None,
+            };
+            if let Some(result) = folded {
+                out.pop(2);
+                out.emit(immediate(result), span);
+            } else if matches!(constant(out, 1), Some(Value::Int(b)) if is_identity(&instr, b)) {
+                out.pop(1);
+            } else {
+                out.emit(instr, span);
+            }
+        }
+        _ => out.emit(instr, span),
+    }
+}
+
+fn immediate(value: Value) -> Instruction {
+    match value {
+        Value::Int(n) => Instruction::Imm(n),
+        Value::Flt(f) => Instruction::ImmF(f),
+    }
+}
+
+/// Whether `Jnz` would jump on `value` (and `Jz` wouldn't).
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Int(n) => *n != 0,
+        Value::Flt(f) => *f != 0.0,
+    }
+}
+
+/// Whether `x op b` is just `x`, for an int or a float `x`.
+fn is_identity(op: &Instruction, b: i64) -> bool {
+    use Instruction::*;
+    match op {
+        Sub => b == 0,
+        Mul | Div => b == 1,
+        _ => false,
+    }
+}
+
+/// `Neg` or `Not` applied to a constant.
+fn unary(op: &Instruction, a: Value) -> Value {
+    match (op, a) {
+        (Instruction::Neg, Value::Int(n)) => Value::Int(n.wrapping_neg()),
+        (Instruction::Neg, Value::Flt(f)) => Value::Flt(-f),
+        (_, a) => Value::Int(!truthy(&a) as i64),
+    }
+}
+
+/// `a op b` as the VM computes it, or `None` where the VM would fail at run time.
+fn binary(op: &Instruction, a: Value, b: Value) -> Option<Value> {
+    use Instruction::*;
+    let float = |v: &Value| match *v {
+        Value::Int(n) => n as f64,
+        Value::Flt(f) => f,
+    };
+    if let (Value::Int(x), Value::Int(y)) = (&a, &b) {
+        let (x, y) = (*x, *y);
+        return Some(Value::Int(match op {
+            Add => x.wrapping_add(y),
+            Sub => x.wrapping_sub(y),
+            Mul => x.wrapping_mul(y),
+            Div => x.checked_div(y)?,
+            Mod => x.checked_rem(y)?,
+            Eq => (x == y) as i64,
+            Ne => (x != y) as i64,
+            Lt => (x < y) as i64,
+            Gt => (x > y) as i64,
+            Le => (x <= y) as i64,
+            Ge => (x >= y) as i64,
+            BitAnd => x & y,
+            BitOr => x | y,
+            BitXor => x ^ y,
+            Shl => x.wrapping_shl(y as u32),
+            Shr => x.wrapping_shr(y as u32),
+            _ => return None,
+        }));
+    }
+    // at least one float: arithmetic and ordering promote the other, equality doesn't
+    let (x, y) = (float(&a), float(&b));
+    Some(match op {
+        Add => Value::Flt(x + y),
+        Sub => Value::Flt(x - y),
+        Mul => Value::Flt(x * y),
+        Div => Value::Flt(x / y),
+        Eq => Value::Int((a == b) as i64),
+        Ne => Value::Int((a != b) as i64),
+        Lt => Value::Int((x < y) as i64),
+        Gt => Value::Int((x > y) as i64),
+        Le => Value::Int((x <= y) as i64),
+        Ge => Value::Int((x >= y) as i64),
+        _ => return None,
+    })
+}
@@ -0,0 +1,407 @@
+//! The `format` module implements the C `printf` formatting rules used by the VM's `printf`
+//! builtin. A format string is scanned for `%` conversions, each of which may carry flags
+//! (`-`, `+`, space, `0`, `#`), a field width, a precision (either may be `*` to take the value
+//! from the argument list) and length modifiers, which are accepted and ignored because every
+//! VM integer is 64 bits wide. Supported conversions are `%d %i %u %c %s %f %F %e %E %x %X %o %p`
+//! and `%%`. The output is produced as raw bytes so that it matches what a gcc-compiled program
+//! would write.
+//!
+//! The module also implements the input side used by the `scanf` builtin, which understands
+//! `%d %i %c %s %f` (with an optional maximum field width and `*` to suppress assignment).
+
+use crate::vm::Value;
+use std::io::BufRead;
+
+/// Flags and sizes parsed from a single conversion specification.
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    zero: bool,
+    alt: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+/// Format `fmt` with `args`, C `printf` style.
+///
+/// `read_str` resolves the address passed to a `%s` conversion to the bytes of the string
+/// (without its terminating NUL). Missing arguments are treated as zero.
+pub fn sprintf(fmt: &[u8], args: &[Value], read_str: impl Fn(i64) -> Vec<u8>) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut args = args.iter();
+    let mut next_arg = || args.next().cloned().unwrap_or(Value::Int(0));
+
+    let mut i = 0;
+    while i < fmt.len() {
+        if fmt[i] != b'%' {
+            out.push(fmt[i]);
+            i += 1;
+            continue;
+        }
+        i += 1;
+
+        let mut spec = Spec::default();
+
+        // flags
+        while i < fmt.len() {
+            match fmt[i] {
+                b'-' => spec.left = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'0' => spec.zero = true,
+                b'#' => spec.alt = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        // width
+        if fmt.get(i) == Some(&b'*') {
+            i += 1;
+            let w = as_int(&next_arg());
+            if w < 0 {
+                spec.left = true;
+            }
+            spec.width = w.unsigned_abs() as usize;
+        } else {
+            spec.width = read_number(fmt, &mut i);
+        }
+
+        // precision
+        if fmt.get(i) == Some(&b'.') {
+            i += 1;
+            if fmt.get(i) == Some(&b'*') {
+                i += 1;
+                let p = as_int(&next_arg());
+                spec.precision = if p < 0 { None } else { Some(p as usize) };
+            } else {
+                spec.precision = Some(read_number(fmt, &mut i));
+            }
+        }
+
+        // length modifiers carry no meaning for 64-bit VM values
+        while i < fmt.len() && matches!(fmt[i], b'h' | b'l' | b'L' | b'q' | b'j' | b'z' | b't') {
+            i += 1;
+        }
+
+        let Some(&conv) = fmt.get(i) else {
+            out.push(b'%');
+            break;
+        };
+        i += 1;
+
+        match conv {
+            b'%' => out.push(b'%'),
+            b'd' | b'i' => {
+                let n = as_int(&next_arg());
+                let sign = sign_prefix(n < 0, &spec);
+                let digits = integer_digits(n.unsigned_abs().to_string(), &spec);
+                pad_number(&mut out, sign, &digits, &spec);
+            }
+            b'u' => {
+                let n = as_int(&next_arg()) as u64;
+                let digits = integer_digits(n.to_string(), &spec);
+                pad_number(&mut out, "", &digits, &spec);
+            }
+            b'x' | b'X' => {
+                let n = as_int(&next_arg()) as u64;
+                let mut digits = integer_digits(format!("{:x}", n), &spec);
+                let mut prefix = if spec.alt && n != 0 { "0x" } else { "" };
+                if conv == b'X' {
+                    digits = digits.to_uppercase();
+                    prefix = if prefix.is_empty() { "" } else { "0X" };
+                }
+                pad_number(&mut out, prefix, &digits, &spec);
+            }
+            b'o' => {
+                let n = as_int(&next_arg()) as u64;
+                let mut digits = integer_digits(format!("{:o}", n), &spec);
+                if spec.alt && !digits.starts_with('0') {
+                    digits.insert(0, '0');
+                }
+                pad_number(&mut out, "", &digits, &spec);
+            }
+            b'c' => {
+                let c = as_int(&next_arg()) as u8;
+                pad(&mut out, &[c], &spec);
+            }
+            b's' => {
+                let addr = as_int(&next_arg());
+                let mut s = if addr == 0 { b"(null)".to_vec() } else { read_str(addr) };
+                if let Some(p) = spec.precision {
+                    s.truncate(p);
+                }
+                pad(&mut out, &s, &spec);
+            }
+            b'p' => {
+                let addr = as_int(&next_arg());
+                let text = if addr == 0 {
+                    "(nil)".to_string()
+                } else {
+                    format!("0x{:x}", addr)
+                };
+                pad(&mut out, text.as_bytes(), &spec);
+            }
+            b'f' | b'F' | b'e' | b'E' => {
+                let f = as_float(&next_arg());
+                let precision = spec.precision.unwrap_or(6);
+                let sign = sign_prefix(f.is_sign_negative() && !f.is_nan(), &spec);
+                let mut digits = if !f.is_finite() {
+                    spec.zero = false;
+                    if f.is_nan() { "nan".to_string() } else { "inf".to_string() }
+                } else if conv == b'f' || conv == b'F' {
+                    format!("{:.*}", precision, f.abs())
+                } else {
+                    exponent_digits(f.abs(), precision)
+                };
+                if spec.alt && precision == 0 && f.is_finite() && !digits.contains('.') {
+                    match digits.find('e') {
+                        Some(at) => digits.insert(at, '.'),
+                        None => digits.push('.'),
+                    }
+                }
+                if conv.is_ascii_uppercase() {
+                    digits = digits.to_uppercase();
+                }
+                // precision means fraction digits here, so it must not suppress '0' padding
+                spec.precision = None;
+                pad_number(&mut out, sign, &digits, &spec);
+            }
+            other => {
+                // unknown conversion: echo it back untouched
+                out.push(b'%');
+                out.push(other);
+            }
+        }
+    }
+
+    out
+}
+
+fn as_int(v: &Value) -> i64 {
+    match v {
+        Value::Int(i) => *i,
+        Value::Flt(f) => *f as i64,
+    }
+}
+
+fn as_float(v: &Value) -> f64 {
+    match v {
+        Value::Int(i) => *i as f64,
+        Value::Flt(f) => *f,
+    }
+}
+
+fn read_number(fmt: &[u8], i: &mut usize) -> usize {
+    let mut n = 0usize;
+    while let Some(d) = fmt.get(*i).filter(|c| c.is_ascii_digit()) {
+        n = n * 10 + (d - b'0') as usize;
+        *i += 1;
+    }
+    n
+}
+
+fn sign_prefix(negative: bool, spec: &Spec) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+/// Apply an integer precision (minimum digit count) to `digits`.
+fn integer_digits(digits: String, spec: &Spec) -> String {
+    match spec.precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(p) if digits.len() < p => format!("{}{}", "0".repeat(p - digits.len()), digits),
+        _ => digits,
+    }
+}
+
+/// Render `f` as `d.ddde±XX` with `precision` fraction digits.
+fn exponent_digits(f: f64, precision: usize) -> String {
+    let s = format!("{:.*e}", precision, f);
+    let (mantissa, exp) = s.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exp.abs())
+}
+
+/// Emit a number made of `prefix` (sign or radix marker) and `digits`, honouring width,
+/// left-justification and zero padding.
+fn pad_number(out: &mut Vec<u8>, prefix: &str, digits: &str, spec: &Spec) {
+    let len = prefix.len() + digits.len();
+    let fill = spec.width.saturating_sub(len);
+    if spec.left {
+        out.extend_from_slice(prefix.as_bytes());
+        out.extend_from_slice(digits.as_bytes());
+        out.extend(std::iter::repeat_n(b' ', fill));
+    } else if spec.zero && spec.precision.is_none() {
+        out.extend_from_slice(prefix.as_bytes());
+        out.extend(std::iter::repeat_n(b'0', fill));
+        out.extend_from_slice(digits.as_bytes());
+    } else {
+        out.extend(std::iter::repeat_n(b' ', fill));
+        out.extend_from_slice(prefix.as_bytes());
+        out.extend_from_slice(digits.as_bytes());
+    }
+}
+
+/// Emit `bytes` space-padded to the field width.
+fn pad(out: &mut Vec<u8>, bytes: &[u8], spec: &Spec) {
+    let fill = spec.width.saturating_sub(bytes.len());
+    if spec.left {
+        out.extend_from_slice(bytes);
+        out.extend(std::iter::repeat_n(b' ', fill));
+    } else {
+        out.extend(std::iter::repeat_n(b' ', fill));
+        out.extend_from_slice(bytes);
+    }
+}
+
+/// A value converted by `scanf`, to be stored through the matching pointer argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scanned {
+    Int(i64),
+    Flt(f64),
+    /// `%c`: exactly the characters read, no terminator.
+    Chars(Vec<u8>),
+    /// `%s`: a word, to be stored NUL-terminated.
+    Str(Vec<u8>),
+}
+
+/// Read from `input` according to `fmt`, C `scanf` style.
+///
+/// Whitespace in `fmt` skips any amount of input whitespace and other characters must match
+/// the input literally. Scanning stops at the first mismatch, leaving the offending byte unread.
+/// Returns `None` if the input ended before the first conversion (C's `EOF` result).
+pub fn scanf(fmt: &[u8], input: &mut dyn BufRead) -> Option<Vec<Scanned>> {
+    let mut values = Vec::new();
+    let mut i = 0;
+    while i < fmt.len() {
+        let c = fmt[i];
+        i += 1;
+
+        if c.is_ascii_whitespace() {
+            skip_space(input);
+            continue;
+        }
+        if c != b'%' || fmt.get(i) == Some(&b'%') {
+            if c == b'%' {
+                i += 1;
+                skip_space(input);
+            }
+            match peek(input) {
+                Some(b) if b == c => input.consume(1),
+                None if values.is_empty() => return None,
+                _ => break,
+            }
+            continue;
+        }
+
+        let suppress = fmt.get(i) == Some(&b'*');
+        if suppress {
+            i += 1;
+        }
+        let width = match read_number(fmt, &mut i) {
+            0 => usize::MAX,
+            w => w,
+        };
+        while i < fmt.len() && matches!(fmt[i], b'h' | b'l' | b'L' | b'q' | b'j' | b'z' | b't') {
+            i += 1;
+        }
+        let Some(&conv) = fmt.get(i) else { break };
+        i += 1;
+
+        if conv != b'c' {
+            skip_space(input);
+        }
+        if peek(input).is_none() {
+            return if values.is_empty() { None } else { Some(values) };
+        }
+
+        let value = match conv {
+            b'd' | b'i' | b'u' => {
+                let text = take_while(input, width, |s, b| {
+                    b.is_ascii_digit() || (s.is_empty() && (b == b'-' || b == b'+'))
+                });
+                match text.parse::<i64>() {
+                    Ok(n) => Scanned::Int(n),
+                    Err(_) => break,
+                }
+            }
+            b'f' | b'e' | b'g' | b'F' | b'E' | b'G' => {
+                let text = take_while(input, width, |s, b| {
+                    b.is_ascii_digit()
+                        || (b == b'.' && !s.contains('.') && !s.contains(['e', 'E']))
+                        || ((b == b'e' || b == b'E') && s.ends_with(|c: char| c.is_ascii_digit())
+                            && !s.contains(['e', 'E']))
+                        || ((b == b'-' || b == b'+') && (s.is_empty() || s.ends_with(['e', 'E'])))
+                });
+                match text.parse::<f64>() {
+                    Ok(f) => Scanned::Flt(f),
+                    Err(_) => break,
+                }
+            }
+            b's' => {
+                let text = take_bytes(input, width, |b| !b.is_ascii_whitespace());
+                Scanned::Str(text)
+            }
+            b'c' => {
+                let count = if width == usize::MAX { 1 } else { width };
+                Scanned::Chars(take_bytes(input, count, |_| true))
+            }
+            _ => break,
+        };
+        if !suppress {
+            values.push(value);
+        }
+    }
+    Some(values)
+}
+
+fn peek(input: &mut dyn BufRead) -> Option<u8> {
+    input.fill_buf().ok()?.first().copied()
+}
+
+fn skip_space(input: &mut dyn BufRead) {
+    while peek(input).is_some_and(|b| b.is_ascii_whitespace()) {
+        input.consume(1);
+    }
+}
+
+/// Consume up to `max` bytes while `accept` holds.
+fn take_bytes(input: &mut dyn BufRead, max: usize, accept: impl Fn(u8) -> bool) -> Vec<u8> {
+    let mut out = Vec::new();
+    while out.len() < max {
+        match peek(input) {
+            Some(b) if accept(b) => {
+                out.push(b);
+                input.consume(1);
+            }
+            _ => break,
+        }
+    }
+    out
+}
+
+/// Like `take_bytes`, but `accept` also sees the text collected so far.
+fn take_while(input: &mut dyn BufRead, max: usize, accept: impl Fn(&str, u8) -> bool) -> String {
+    let mut out = String::new();
+    while out.len() < max {
+        match peek(input) {
+            Some(b) if accept(&out, b) => {
+                out.push(b as char);
+                input.consume(1);
+            }
+            _ => break,
+        }
+    }
+    out
+}
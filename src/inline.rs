@@ -0,0 +1,207 @@
+//! Function inlining (`-O2`): replaces calls to small functions with a copy of their body.
+//!
+//! A `Call` saves the frame pointer and return address, moves the arguments into a new frame
+//! and `Leave` undoes it all, which costs more than the body of a function like
+//! `int sum(int a, int b) { return a + b; }`. Inlining a call gives the callee's frame slots
+//! to the caller instead, above its own: `Enter` in the caller grows to make room, the
+//! arguments are stored into the first slots (the callee's other locals start at 0, as
+//! `Enter` would leave them), and every `Leave` becomes a jump past the copy, with the
+//! return value on the stack where the call would have left it (a `TailCall` becomes a
+//! `Call` followed by that jump). Labels get fresh ids in each copy.
+//!
+//! A function is inlined when its code has at most `threshold` instructions, it can't call
+//! itself (directly or through others), and it never takes the address of a local, which
+//! would point into a different frame. Rounds repeat until nothing changes, so a call inside
+//! an inlined body is inlined in turn. Functions no longer called are left for `dce`.
+
+use crate::instruction::Instruction;
+use crate::optimize::relocate;
+use crate::program::Program;
+use crate::token::Span;
+use std::collections::{HashMap, HashSet};
+
+/// The largest function `-O2` inlines, in instructions.
+pub const INLINE_THRESHOLD: usize = 16;
+
+/// Inline calls to the non-recursive functions in `program` with at most `threshold`
+/// instructions.
+pub fn inline_functions(program: &mut Program, threshold: usize) {
+    while inline_round(program, threshold) {}
+}
+
+/// The instructions of the function starting at `entry`, in order: everything reached from
+/// it without following calls.
+fn body(code: &[Instruction], labels: &HashMap<usize, usize>, entry: usize) -> Vec<usize> {
+    let mut reached = HashSet::new();
+    let mut pending = vec![entry];
+    while let Some(ip) = pending.pop() {
+        if ip >= code.len() || !reached.insert(ip) {
+            continue;
+        }
+        match &code[ip] {
+            Instruction::Jmp(id) => pending.extend(labels.get(id)),
+            Instruction::Jz(id) | Instruction::Jnz(id) | Instruction::CmpJz(_, id) => {
+                pending.extend(labels.get(id));
+                pending.push(ip + 1);
+            }
+            Instruction::Leave | Instruction::Exit | Instruction::TailCall(_) => {}
+            _ => pending.push(ip + 1),
+        }
+    }
+    let mut body: Vec<usize> = reached.into_iter().collect();
+    body.sort_unstable();
+    body
+}
+
+/// Whether the function at `entry` can end up calling itself.
+fn is_recursive(entry: usize, calls: &HashMap<usize, Vec<usize>>) -> bool {
+    let mut seen = HashSet::new();
+    let mut pending = calls.get(&entry).cloned().unwrap_or_default();
+    while let Some(callee) = pending.pop() {
+        if callee == entry {
+            return true;
+        }
+        if seen.insert(callee) {
+            pending.extend(calls.get(&callee).into_iter().flatten());
+        }
+    }
+    false
+}
+
+/// Inline every call that qualifies in the code as it stands, returning whether any did.
+fn inline_round(program: &mut Program, threshold: usize) -> bool {
+    let code = &program.code;
+    let labels: HashMap<usize, usize> = code
+        .iter()
+        .enumerate()
+        .filter_map(|(i, instr)| match instr {
+            Instruction::Label(id) => Some((*id, i)),
+            _ => None,
+        })
+        .collect();
+    let arity = program.arity_by_entry();
+
+    // which function each instruction belongs to; code shared by two is left alone
+    let bodies: HashMap<usize, Vec<usize>> = arity
+        .keys()
+        .filter(|&&entry| matches!(code.get(entry), Some(Instruction::Enter(_))))
+        .map(|&entry| (entry, body(code, &labels, entry)))
+        .collect();
+    let mut owner: Vec<Option<usize>> = vec![None; code.len()];
+    let mut shared = vec![false; code.len()];
+    for (&entry, body) in &bodies {
+        for &i in body {
+            shared[i] |= owner[i].is_some();
+            owner[i] = Some(entry);
+        }
+    }
+    let calls: HashMap<usize, Vec<usize>> = bodies
+        .iter()
+        .map(|(&entry, body)| {
+            let callees = body.iter().filter_map(|&i| match code[i] {
+                Instruction::Call(addr) | Instruction::TailCall(addr) => Some(addr),
+                _ => None,
+            });
+            (entry, callees.collect())
+        })
+        .collect();
+
+    let inlinable: HashSet<usize> = bodies
+        .iter()
+        .filter(|&(&entry, body)| {
+            entry != program.entry()
+                && body.len() <= threshold
+                && body[0] == entry
+                && matches!(
+                    code[body[body.len() - 1]],
+                    Instruction::Leave | Instruction::Jmp(_) | Instruction::Exit | Instruction::TailCall(_)
+                )
+                && body.iter().all(|&i| !shared[i] && !matches!(code[i], Instruction::Addr(_)))
+                && !is_recursive(entry, &calls)
+        })
+        .map(|(&entry, _)| entry)
+        .collect();
+
+    let mut used: HashSet<usize> = labels.keys().copied().collect();
+    let mut next_label = 0;
+    let mut fresh_label = || {
+        while !used.insert(next_label) {
+            next_label += 1;
+        }
+        next_label
+    };
+
+    let span = |i: usize| program.spans.get(i).copied().unwrap_or_default();
+    let mut out: Vec<Instruction> = Vec::with_capacity(code.len());
+    let mut spans: Vec<Span> = Vec::with_capacity(code.len());
+    let mut new_index = Vec::with_capacity(code.len() + 1);
+    let mut grown: HashMap<usize, usize> = HashMap::new(); // caller entry → slots added
+    for (i, instr) in code.iter().enumerate() {
+        new_index.push(out.len());
+        let (callee, caller) = match (instr, owner[i]) {
+            (Instruction::Call(addr), Some(caller)) if inlinable.contains(addr) && !shared[i] => (*addr, caller),
+            _ => {
+                out.push(instr.clone());
+                spans.push(span(i));
+                continue;
+            }
+        };
+        let (Instruction::Enter(base), Instruction::Enter(slots)) = (&code[caller], &code[callee]) else {
+            unreachable!("functions in `bodies` start with `Enter`");
+        };
+        let (base, slots) = (*base, *slots);
+        let grow = grown.entry(caller).or_default();
+        *grow = (*grow).max(slots);
+
+        // the first argument is on top of the stack
+        let mut emit = |instr: Instruction, at: usize| {
+            out.push(instr);
+            spans.push(span(at));
+        };
+        for slot in 0..arity[&callee] {
+            emit(Instruction::StoreLocal(base + slot), i);
+            emit(Instruction::Pop, i);
+        }
+        for slot in arity[&callee]..slots {
+            emit(Instruction::Imm(0), i);
+            emit(Instruction::StoreLocal(base + slot), i);
+            emit(Instruction::Pop, i);
+        }
+        let end = fresh_label();
+        let mut renamed: HashMap<usize, usize> = HashMap::new();
+        let mut rename = |id: &usize| *renamed.entry(*id).or_insert_with(&mut fresh_label);
+        for &j in &bodies[&callee][1..] {
+            let copy = match &code[j] {
+                Instruction::LoadLocal(slot) => Instruction::LoadLocal(base + slot),
+                Instruction::StoreLocal(slot) => Instruction::StoreLocal(base + slot),
+                Instruction::AddLocal(slot, n) => Instruction::AddLocal(base + slot, *n),
+                Instruction::Label(id) => Instruction::Label(rename(id)),
+                Instruction::Jmp(id) => Instruction::Jmp(rename(id)),
+                Instruction::Jz(id) => Instruction::Jz(rename(id)),
+                Instruction::Jnz(id) => Instruction::Jnz(rename(id)),
+                Instruction::CmpJz(cmp, id) => Instruction::CmpJz(*cmp, rename(id)),
+                Instruction::Leave => Instruction::Jmp(end),
+                // the copy has no frame of its own to hand over, so make it an ordinary call
+                Instruction::TailCall(addr) => {
+                    emit(Instruction::Call(*addr), j);
+                    Instruction::Jmp(end)
+                }
+                other => other.clone(),
+            };
+            emit(copy, j);
+        }
+        emit(Instruction::Label(end), i);
+    }
+    new_index.push(out.len());
+
+    if grown.is_empty() {
+        return false;
+    }
+    for (caller, slots) in grown {
+        if let Instruction::Enter(frame) = &mut out[new_index[caller]] {
+            *frame += slots;
+        }
+    }
+    relocate(program, out, spans, &new_index);
+    true
+}
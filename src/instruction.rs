@@ -3,24 +3,52 @@
 /// and represent low-level operations such as arithmetic, memory access, control flow,
 /// function calls, and printing. Each variant corresponds to a specific behavior that
 /// the virtual machine must implement.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     Imm(i64),
     Add, Sub, Mul, Div, Mod,
     Neg, Not, Deref, Addr(usize), Cast,
+    DerefChar,  // load the byte at the popped address
+    Store,      // pop value and address, store the word, push the value back
+    StoreChar,  // pop value and address, store the low byte, push it back
+    Dup, Pop,
     Eq, Ne, Lt, Gt, Le, Ge,
     BitAnd, BitOr, BitXor,
     Shl, Shr,Print,
     PrintStr(String),// prints a Rust string literal
     PushF(f64),
     PrintF,
+    Printf(usize), // C `printf`; operand is the argument count including the format
+    Scanf(usize),  // C `scanf`; operand is the argument count including the format
+    Getchar,
+    Putchar,
+    Open(usize), // open(path, flags[, mode]); operand is the argument count
+    Read,        // read(fd, buf, n)
+    Write,       // write(fd, buf, n)
+    Close,
+    Exit,
+    Malloc,
+    Free,
+    Memset, // memset(dst, byte, n)
+    Memcmp, // memcmp(a, b, n)
     ImmF(f64),
     Jmp(usize),
     Jz(usize),
+    Jnz(usize),
     Label(usize),
     LoadLocal(usize),
     StoreLocal(usize),
     Call(usize),
+    TailCall(usize), // `return f(...)`: call reusing the current frame, returning to our caller
     Enter(usize),
     Leave,
+    // superinstructions, fused by the peephole optimizer from the sequences they replace
+    AddLocal(usize, i64),  // LoadLocal n; Imm c; Add; StoreLocal n, rewritten as AddLocal; LoadLocal n
+    CmpJz(Compare, usize), // Lt (or another comparison); Jz label
+}
+
+/// The comparison a `CmpJz` makes before branching.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compare {
+    Eq, Ne, Lt, Gt, Le, Ge,
 }
@@ -1,15 +1,24 @@
 /// The `Lexer` struct is responsible for converting raw source code into a stream of tokens
 /// for further processing by the parser. It implements lexical analysis by reading characters
 /// from the input string and recognizing token patterns such as identifiers, literals, keywords,
-/// and operators. The lexer also handles skipping whitespace and single-line comments, supports
+/// and operators. The lexer also handles skipping whitespace and `//` and `/* */` comments
+/// (a `#` line means the preprocessor didn't run, and is an error), supports
 /// escape sequences in string and character literals, and distinguishes between integer and
-/// floating-point number formats. It maintains the current line number to aid error reporting
-/// and produces `Token` instances that are later consumed by the parser during compilation.
-use crate::token::{Token, TokenKind};
+/// floating-point number formats. It tracks the byte offset, line and column of every token
+/// (its `Span`) to aid error reporting and produces `Token` instances that are later consumed by the parser during compilation.
+/// Malformed input (such as an unterminated comment) is recorded in `diagnostics`, and input
+/// that is valid but likely a mistake (a multi-character constant) in `warnings`.
+use crate::diagnostic::Diagnostic;
+use crate::token::{Span, Token, TokenKind};
 
 pub struct Lexer<'a> {
     chars: std::iter::Peekable<std::str::Chars<'a>>,
+    pos: usize,    // byte offset of the upcoming character
     line: usize,
+    column: usize,
+    file_id: usize,
+    pub diagnostics: Vec<Diagnostic>,
+    pub warnings: Vec<Diagnostic>,
 }
 
 impl<'a> Lexer<'a> {
@@ -17,26 +26,63 @@ impl<'a> Lexer<'a> {
     pub fn new(source: &'a str) -> Self {
         Self {
             chars: source.chars().peekable(),
+            pos: 0,
             line: 1,
+            column: 1,
+            file_id: 0,
+            diagnostics: Vec::new(),
+            warnings: Vec::new(),
         }
     }
 
-    /// Consume the next character, updating line count on `\n`.
+    /// Tag the spans of this lexer's tokens with `file_id`.
+    pub fn with_file_id(mut self, file_id: usize) -> Self {
+        self.file_id = file_id;
+        self
+    }
+
+    /// Consume the next character, updating the position on `\n`.
     fn bump(&mut self) -> Option<char> {
         let c = self.chars.next()?;
+        self.pos += c.len_utf8();
         if c == '\n' {
             self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
         }
         Some(c)
     }
 
+    /// An empty span at the upcoming character.
+    fn here(&self) -> Span {
+        Span {
+            file_id: self.file_id,
+            start: self.pos,
+            end: self.pos,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    /// Extend `span` up to the current position.
+    fn to_here(&self, span: Span) -> Span {
+        Span { end: self.pos, ..span }
+    }
+
     /// Peek the upcoming character without consuming.
     fn peek(&mut self) -> Option<char> {
         self.chars.peek().copied()
     }
 
-    /// Skip whitespace and `//` comments.
-    // src/lexer.rs, inside impl<'a> Lexer<'a>
+    /// Peek the character after the upcoming one.
+    fn second(&self) -> Option<char> {
+        let mut clone = self.chars.clone();
+        clone.next();
+        clone.next()
+    }
+
+    /// Skip whitespace and `//` and `/* */` comments, reporting any `#` line.
     fn skip_whitespace_and_comments(&mut self) {
         loop {
             // 1) Skip all whitespace
@@ -48,7 +94,19 @@ impl<'a> Lexer<'a> {
                 }
             }
 
-            // 2) If we see "//", skip to end of line
+            // 2) Directives are the preprocessor's job; one left here was never run through it.
+            //    The rest of the line is skipped so it doesn't cause errors of its own.
+            if self.peek() == Some('#') {
+                let span = self.here();
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.bump();
+                }
+                let message = "stray `#` in program (directives are handled by the preprocessor)";
+                self.diagnostics.push(Diagnostic::at(message, self.to_here(span)));
+                continue;
+            }
+
+            // 3) If we see "//", skip to end of line
             if self.peek() == Some('/') {
                 // peek second character
                 let mut clone = self.chars.clone();
@@ -66,6 +124,25 @@ impl<'a> Lexer<'a> {
                     // now re-loop to skip any whitespace/comments again
                     continue;
                 }
+                // 4) "/*" runs to the next "*/", possibly across lines
+                if clone.peek() == Some(&'*') {
+                    let start = self.here();
+                    self.bump();
+                    self.bump();
+                    let start = self.to_here(start);
+                    let mut prev = '\0';
+                    loop {
+                        match self.bump() {
+                            Some('/') if prev == '*' => break,
+                            Some(c) => prev = c,
+                            None => {
+                                self.diagnostics.push(Diagnostic::at("unterminated comment", start));
+                                return;
+                            }
+                        }
+                    }
+                    continue;
+                }
             }
 
             // nothing more to skip
@@ -77,47 +154,20 @@ impl<'a> Lexer<'a> {
     pub fn next_token(&mut self) -> Token {
         self.skip_whitespace_and_comments();
 
-        let line = self.line;
+        let span = self.here();
         let c = match self.peek() {
             Some(c) => c,
             None => {
                 return Token {
                     kind: TokenKind::Eof,
-                    line,
+                    span,
                 };
             }
         };
 
-        // Numbers
-        // at the top of next_token():
-        if c.is_ascii_digit() {
-            let mut s = String::new();
-            let mut has_dot = false;
-            // consume all digits, allowing one dot
-            while let Some(&ch) = self.chars.peek() {
-                match ch {
-                    '0'..='9' => {
-                        s.push(ch);
-                        self.bump();
-                    }
-                    '.' if !has_dot => {
-                        has_dot = true;
-                        s.push('.');
-                        self.bump();
-                    }
-                    _ => break
-                }
-            }
-            let line = self.line;
-            if has_dot {
-                let f = s.parse::<f64>()
-                    .expect("lexer: invalid float literal");
-                return Token { kind: TokenKind::Float(f), line };
-            } else {
-                let i = s.parse::<i64>()
-                    .expect("lexer: invalid integer literal");
-                return Token { kind: TokenKind::Num(i), line };
-            }
+        // Numbers (`.5` starts one too)
+        if c.is_ascii_digit() || (c == '.' && self.second().is_some_and(|d| d.is_ascii_digit())) {
+            return self.lex_number();
         }
 
         // Identifiers or keywords
@@ -200,6 +250,10 @@ impl<'a> Lexer<'a> {
                 self.bump();
                 TokenKind::Xor
             }
+            '~' => {
+                self.bump();
+                TokenKind::Tilde
+            }
 
             '+' => {
                 self.bump();
@@ -271,37 +325,47 @@ impl<'a> Lexer<'a> {
                 self.bump();
                 TokenKind::RBracket
             }
-            _ => {
-                // Unknown character: skip and get the next token
+            other => {
+                // a character no token starts with: report it and carry on after it
                 self.bump();
+                let message = format!("stray `{}` in program", other.escape_debug());
+                self.diagnostics.push(Diagnostic::at(message, self.to_here(span)));
                 return self.next_token();
             }
         };
 
-        Token { kind: tok, line }
+        Token { kind: tok, span: self.to_here(span) }
     }
 
-    /// Lex an integer literal.
+    /// Lex a numeric literal. Like C's preprocessing numbers, the whole run of digits,
+    /// letters, dots and exponent signs is taken first and then validated, so `08` or
+    /// `10abc` is reported as one malformed literal instead of being split into tokens.
     fn lex_number(&mut self) -> Token {
-        let line = self.line;
-        let mut val = 0i64;
+        let span = self.here();
+        let mut text = String::new();
         while let Some(c) = self.peek() {
-            if let Some(d) = c.to_digit(10) {
-                val = val * 10 + d as i64;
+            let exponent_sign = (c == '+' || c == '-')
+                && matches!(text.chars().last(), Some('e' | 'E' | 'p' | 'P'))
+                && !text.starts_with("0x")
+                && !text.starts_with("0X");
+            if c.is_ascii_alphanumeric() || c == '_' || c == '.' || exponent_sign {
+                text.push(c);
                 self.bump();
             } else {
                 break;
             }
         }
-        Token {
-            kind: TokenKind::Num(val),
-            line,
-        }
+        let span = self.to_here(span);
+        let kind = parse_number(&text).unwrap_or_else(|message| {
+            self.diagnostics.push(Diagnostic::at(message, span));
+            TokenKind::Num(0)
+        });
+        Token { kind, span }
     }
 
     /// Lex identifiers and keywords.
     fn lex_identifier_or_keyword(&mut self) -> Token {
-        let line = self.line;
+        let span = self.here();
         let mut s = String::new();
         while let Some(c) = self.peek() {
             if c.is_ascii_alphanumeric() || c == '_' {
@@ -317,78 +381,217 @@ impl<'a> Lexer<'a> {
             "while" => TokenKind::While,
             "return" => TokenKind::Return,
             "int" => TokenKind::Int,
+            "long" => TokenKind::Long,
             "char" => TokenKind::Char,
             "sizeof" => TokenKind::Sizeof,
             "enum" => TokenKind::Enum,
+            "void" => TokenKind::Void,
             _ => TokenKind::Id(s),
         };
-        Token { kind, line }
+        Token { kind, span: self.to_here(span) }
     }
 
-    /// Lex a string literal, handling escape sequences.
+    /// Lex a string literal, handling escape sequences. Adjacent literals are joined, so
+    /// `"ab" "cd"` is the single string `"abcd"`, as in C.
     fn lex_string_literal(&mut self) -> Token {
-        let line = self.line;
-        self.bump(); // consume opening `"`
-        let mut s = String::new();
-        while let Some(c) = self.peek() {
-            if c == '"' {
-                self.bump();
-                break;
-            }
-            if c == '\\' {
-                self.bump();
-                if let Some(esc) = self.bump() {
-                    let real = match esc {
-                        'n' => '\n',
-                        't' => '\t',
-                        'r' => '\r',
-                        '\\' => '\\',
-                        '"' => '"',
-                        '\'' => '\'',
-                        other => other,
-                    };
-                    s.push(real);
-                    continue;
+        let span = self.here();
+        let mut bytes = Vec::new();
+        loop {
+            self.bump(); // consume opening `"`
+            loop {
+                match self.peek() {
+                    Some('"') => {
+                        self.bump();
+                        break;
+                    }
+                    None | Some('\n') => {
+                        let span = self.to_here(span);
+                        self.diagnostics.push(Diagnostic::at("unterminated string literal", span));
+                        return Token { kind: TokenKind::String(bytes), span };
+                    }
+                    Some('\\') => self.lex_escape(&mut bytes),
+                    Some(c) => {
+                        self.bump();
+                        bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    }
                 }
-            } else {
-                s.push(c);
-                self.bump();
+            }
+            self.skip_whitespace_and_comments();
+            if self.peek() != Some('"') {
+                break;
             }
         }
-        Token {
-            kind: TokenKind::String(s),
-            line,
-        }
+        Token { kind: TokenKind::String(bytes), span: self.to_here(span) }
     }
 
     /// Lex a character literal, handling escape sequences.
     fn lex_char_literal(&mut self) -> Token {
-        let line = self.line;
+        let span = self.here();
         self.bump(); // consume opening `'`
-        let mut ch = '\0';
-        if let Some(c) = self.bump() {
-            if c == '\\' {
-                if let Some(esc) = self.bump() {
-                    ch = match esc {
-                        'n' => '\n',
-                        't' => '\t',
-                        'r' => '\r',
-                        '\\' => '\\',
-                        '\'' => '\'',
-                        other => other,
-                    };
+        let mut bytes = Vec::new();
+        loop {
+            match self.peek() {
+                Some('\'') => {
+                    self.bump();
+                    break;
+                }
+                None | Some('\n') => {
+                    let span = self.to_here(span);
+                    self.diagnostics.push(Diagnostic::at("unterminated character constant", span));
+                    break;
+                }
+                Some('\\') => self.lex_escape(&mut bytes),
+                Some(c) => {
+                    self.bump();
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
                 }
-            } else {
-                ch = c;
             }
         }
-        // consume closing `'`
-        if self.peek() == Some('\'') {
-            self.bump();
+        let span = self.to_here(span);
+        let kind = match bytes[..] {
+            [] => {
+                self.diagnostics.push(Diagnostic::at("empty character constant", span));
+                TokenKind::CharLiteral('\0')
+            }
+            [b] => TokenKind::CharLiteral(char::from(b)),
+            // an `int` with the characters' bytes, the first one highest, as GCC and Clang do
+            _ => {
+                self.warnings.push(Diagnostic::at("multi-character character constant", span));
+                TokenKind::Num(bytes.iter().fold(0i64, |value, &b| value.wrapping_shl(8) | b as i64))
+            }
+        };
+        Token { kind, span }
+    }
+
+    /// Lex an escape sequence and append the byte it stands for to `bytes`: simple escapes
+    /// like `\n`, octal `\101` and hex `\x41`.
+    fn lex_escape(&mut self, bytes: &mut Vec<u8>) {
+        let span = self.here();
+        self.bump(); // consume `\`
+        let Some(esc) = self.bump() else {
+            bytes.push(b'\\');
+            return;
+        };
+        let byte = match esc {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            'a' => 0x07,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'v' => 0x0b,
+            '\\' | '\'' | '"' | '?' => esc as u8,
+            '0'..='7' => {
+                let mut value = esc.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match self.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            self.bump();
+                        }
+                        None => break,
+                    }
+                }
+                if value > 0xff {
+                    self.diagnostics.push(Diagnostic::at("octal escape sequence out of range", self.to_here(span)));
+                }
+                value as u8
+            }
+            'x' => {
+                let mut value: u32 = 0;
+                let mut digits = 0;
+                while let Some(digit) = self.peek().and_then(|c| c.to_digit(16)) {
+                    value = value.saturating_mul(16).saturating_add(digit);
+                    digits += 1;
+                    self.bump();
+                }
+                if digits == 0 {
+                    self.diagnostics.push(Diagnostic::at("\\x used with no following hex digits", self.to_here(span)));
+                } else if value > 0xff {
+                    self.diagnostics.push(Diagnostic::at("hex escape sequence out of range", self.to_here(span)));
+                }
+                value as u8
+            }
+            // like C compilers, keep the character and drop the backslash
+            other => {
+                let message = format!("unknown escape sequence `\\{}`", other);
+                self.warnings.push(Diagnostic::at(message, self.to_here(span)));
+                bytes.extend_from_slice(other.encode_utf8(&mut [0; 4]).as_bytes());
+                return;
+            }
+        };
+        bytes.push(byte);
+    }
+}
+
+/// Turn the text of a numeric literal into a `Num` or `Float` token.
+///
+/// Integers may be decimal, octal (`0777`), hexadecimal (`0xFF`) or binary (`0b1010`)
+/// with an optional `u`/`l`/`ll` suffix; they are all 64-bit in the VM, so unsigned
+/// values above `i64::MAX` wrap. Floats take an optional exponent and `f`/`l` suffix.
+fn parse_number(text: &str) -> Result<TokenKind, String> {
+    let prefixed = |lower: &str, upper: &str| {
+        text.strip_prefix(lower).or_else(|| text.strip_prefix(upper))
+    };
+    let (radix, rest) = if let Some(rest) = prefixed("0x", "0X") {
+        (16, rest)
+    } else if let Some(rest) = prefixed("0b", "0B") {
+        (2, rest)
+    } else {
+        let mantissa = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+        if matches!(text[mantissa..].chars().next(), Some('.' | 'e' | 'E')) {
+            return parse_float(text);
         }
-        Token {
-            kind: TokenKind::CharLiteral(ch),
-            line,
+        if text.starts_with('0') && mantissa > 1 { (8, &text[1..]) } else { (10, text) }
+    };
+
+    let end = rest.find(|c: char| !c.is_digit(radix)).unwrap_or(rest.len());
+    let (digits, suffix) = rest.split_at(end);
+    if let Some(bad) = suffix.chars().next().filter(|c| c.is_ascii_digit()) {
+        let base = if radix == 8 { "octal" } else { "binary" };
+        return Err(format!("invalid digit '{}' in {} literal `{}`", bad, base, text));
+    }
+    if digits.is_empty() {
+        return Err(format!("malformed integer literal `{}`", text));
+    }
+    let unsigned = suffix.contains(['u', 'U']);
+    let length = suffix.trim_start_matches(['u', 'U']).trim_end_matches(['u', 'U']);
+    if !matches!(length, "" | "l" | "L" | "ll" | "LL") || suffix.matches(['u', 'U']).count() > 1 {
+        return Err(format!("invalid suffix `{}` on integer literal `{}`", suffix, text));
+    }
+
+    let value = u64::from_str_radix(digits, radix)
+        .map_err(|_| format!("integer literal `{}` is too large", text))?;
+    // A decimal literal without `u` must fit in a signed 64-bit integer.
+    if radix == 10 && !unsigned && value > i64::MAX as u64 {
+        return Err(format!("integer literal `{}` is too large", text));
+    }
+    Ok(TokenKind::Num(value as i64))
+}
+
+/// Parse a decimal float such as `1.5`, `.5`, `1e-9` or `2.5f`.
+fn parse_float(text: &str) -> Result<TokenKind, String> {
+    let number = text.strip_suffix(['f', 'F', 'l', 'L']).unwrap_or(text);
+    let malformed = || format!("malformed floating-point literal `{}`", text);
+    let (mantissa, exponent) = match number.find(['e', 'E']) {
+        Some(at) => (&number[..at], Some(&number[at + 1..])),
+        None => (number, None),
+    };
+    if !mantissa.chars().any(|c| c.is_ascii_digit())
+        || mantissa.matches('.').count() > 1
+        || !mantissa.chars().all(|c| c.is_ascii_digit() || c == '.')
+    {
+        return Err(malformed());
+    }
+    if let Some(exponent) = exponent {
+        let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(malformed());
         }
     }
+    let value: f64 = number.parse().map_err(|_| malformed())?;
+    if value.is_infinite() {
+        return Err(format!("floating-point literal `{}` is too large", text));
+    }
+    Ok(TokenKind::Float(value))
 }
@@ -1,16 +1,48 @@
 //! This is the library entry point for the C4 Rust compiler backend.
 //! 
 //! It organizes the compilation pipeline into modular components:
+//! - `preprocessor`: Expands `#include`, `#define` and conditional directives before lexing.
 //! - `lexer`: Converts raw source code into a sequence of tokens.
 //! - `token`: Defines the token kinds used by the lexer and parser.
-//! - `parser`: Transforms tokens into an abstract syntax tree (AST) and then into bytecode.
+//! - `parser`: Transforms tokens into an abstract syntax tree (AST).
+//! - `ast`: Defines the syntax tree: declarations, statements, expressions and types.
+//! - `codegen`: Lowers the syntax tree to bytecode, reporting name and type errors.
+//! - `pretty`: Prints the syntax tree back as C or as JSON.
 //! - `instruction`: Contains the virtual machine instruction set.
+//! - `program`: A compiled program: instructions, data, and the names behind addresses.
+//! - `disasm`: Lists a compiled program's instructions with their operands resolved.
+//! - `asm`: A textual assembly language for programs, and its assembler.
+//! - `binary`: The versioned `.c4b` file format for saving and loading compiled programs.
+//! - `optimize`: Runs the optimization passes selected by the `-O` level.
+//! - `fold`: Folds constant expressions and simplifies algebraic identities.
+//! - `dce`: Removes unreachable code and functions that are never called.
+//! - `inline`: Replaces calls to small functions with a copy of their body.
+//! - `peephole`: Rewrites short instruction sequences, fusing them into superinstructions.
+//! - `verify`: Rejects malformed bytecode before the VM runs it.
 //! - `vm`: Provides the stack-based virtual machine that executes bytecode.
+//! - `format`: Implements C `printf` formatting for the VM's output builtins.
+//! - `diagnostic`: Errors (and warnings) reported against the user's source code.
 //!
 //! These modules together support parsing, compiling, and interpreting a small C-like language.
 
+pub mod preprocessor;
 pub mod lexer;
 pub mod token;
 pub mod parser;
+pub mod ast;
+pub mod codegen;
+pub mod pretty;
 pub mod instruction;
-pub mod vm;
\ No newline at end of file
+pub mod program;
+pub mod disasm;
+pub mod asm;
+pub mod binary;
+pub mod optimize;
+pub mod fold;
+pub mod dce;
+pub mod inline;
+pub mod peephole;
+pub mod verify;
+pub mod vm;
+pub mod format;
+pub mod diagnostic;
@@ -1,54 +1,228 @@
 //! This is the entry point for the C4 Rust compiler executable.
 //!
 //! It handles the following steps:
-//! 1. Reads the source code from a `.c` file provided as a command-line argument.
-//! 2. Uses the parser to convert the source code into bytecode instructions.
+//! 1. Reads the source code from a `.c` file provided as a command-line argument. The
+//!    `--allow-read`/`--allow-write` options list host paths the program may `open`, and any
+//!    arguments after the file are passed on to `main(int argc, char **argv)`.
+//! 2. Runs the preprocessor (`-I <dir>` adds an include path, `-D NAME[=VALUE]` predefines a
+//!    macro), then uses the parser to convert the result into bytecode instructions.
+//!    Errors are reported rustc-style with the offending source line (in color on a terminal
+//!    unless `NO_COLOR` is set) and exit with status 1.
+//!    With `--dump-ast` (or `--dump-ast=json`) the parsed syntax tree is printed instead and
+//!    nothing is run; `c4_rust disasm file.c` prints the compiled bytecode instead, and
+//!    `c4_rust compile [-o out.c4b] [--strip] file.c` saves it in the `.c4b` binary format.
+//!    Given a `.c4b` file (`c4_rust run prog.c4b`), compilation is skipped altogether.
+//!    `-O1` (or `-O`) optimizes the bytecode before it is run, saved or listed, folding
+//!    constants and removing dead code; `-O2` also inlines small functions and runs the
+//!    peephole pass, which fuses common sequences into superinstructions. The default is
+//!    `-O0`. Either way the bytecode is verified before it is optimized, saved, listed or
+//!    run. `-Wdead-code` warns about functions that are never called and statements that can
+//!    never run.
 //! 3. Initializes and runs the virtual machine (VM) starting from the `main` function label.
-//! 4. Prints the final return value of the executed program.
+//! 4. Reports the final return value of the executed program (or its `exit` status) on
+//!    stderr and uses it as the process exit status.
 //!
 //! This file ties together the compiler pipeline and serves as the user-facing interface.
 use std::env;
 use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+use c4_rust::binary::MAGIC;
+use c4_rust::diagnostic::Renderer;
+use c4_rust::disasm::disassemble;
+use c4_rust::optimize::optimize;
 use c4_rust::parser::Parser;
-use c4_rust::vm::VM;
+use c4_rust::preprocessor::Preprocessor;
+use c4_rust::pretty::{dump_ast, AstFormat};
+use c4_rust::program::Program;
+use c4_rust::vm::Outcome;
 
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {0} [run] [-I <dir>] [-D NAME[=VALUE]] [-O<level>] [-Wdead-code] [--allow-read <path>] [--allow-write <path>] \
+         [--dump-ast[=json]] <source_file.c | prog.c4b> [args...]\n       \
+         {0} compile [-I <dir>] [-D NAME[=VALUE]] [-O<level>] [-Wdead-code] [-o <prog.c4b>] [--strip] <source_file.c>\n       \
+         {0} disasm [-I <dir>] [-D NAME[=VALUE]] [-O<level>] <source_file.c | prog.c4b>",
+        program
+    );
+    std::process::exit(1);
+}
+
+/// `-D NAME` defines `NAME` as 1, `-D NAME=VALUE` as `VALUE`.
+fn define(preprocessor: &mut Preprocessor, definition: &str) {
+    match definition.split_once('=') {
+        Some((name, value)) => preprocessor.define(name, value),
+        None => preprocessor.define(definition, "1"),
+    }
+}
+
+/// What to do with the program once it is compiled (or loaded).
+#[derive(PartialEq)]
+enum Command {
+    Run,
+    Compile,
+    Disasm,
+}
 
 fn main() {
-    // Allow passing the file as a CLI argument
+    // Allow passing the file as a CLI argument, preceded by sandbox options
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <source_file.c>", args[0]);
+    let mut readable = Vec::new();
+    let mut writable = Vec::new();
+    let mut preprocessor = Preprocessor::new();
+    let mut dump = None;
+    let mut output = None;
+    let mut strip = false;
+    let mut level = 0;
+    let mut warn_dead_code = false;
+    let (command, first) = match args.get(1).map(String::as_str) {
+        Some("run") => (Command::Run, 2),
+        Some("compile") => (Command::Compile, 2),
+        Some("disasm") => (Command::Disasm, 2),
+        _ => (Command::Run, 1),
+    };
+    let mut rest = args[first.min(args.len())..].iter();
+    let filename = loop {
+        match rest.next().map(String::as_str) {
+            Some("--allow-read") => readable.push(rest.next().unwrap_or_else(|| usage(&args[0]))),
+            Some("--allow-write") => writable.push(rest.next().unwrap_or_else(|| usage(&args[0]))),
+            Some("-I") => preprocessor.add_include_path(rest.next().unwrap_or_else(|| usage(&args[0]))),
+            Some(opt) if opt.starts_with("-I") => preprocessor.add_include_path(&opt[2..]),
+            Some("-D") => define(&mut preprocessor, rest.next().unwrap_or_else(|| usage(&args[0]))),
+            Some(opt) if opt.starts_with("-D") => define(&mut preprocessor, &opt[2..]),
+            Some("-o") if command == Command::Compile => output = Some(rest.next().unwrap_or_else(|| usage(&args[0]))),
+            Some("--strip") if command == Command::Compile => strip = true,
+            Some("-O") => level = 1,
+            Some(opt) if opt.starts_with("-O") => level = opt[2..].parse().unwrap_or_else(|_| usage(&args[0])),
+            Some("-Wdead-code") => warn_dead_code = true,
+            Some("--dump-ast") => dump = Some(AstFormat::Text),
+            Some("--dump-ast=json") => dump = Some(AstFormat::Json),
+            Some(file) => break file.to_string(),
+            None => usage(&args[0]),
+        }
+    };
+    // whatever follows the source file is handed to the program's `main`
+    let program_args: Vec<String> = std::iter::once(filename.clone()).chain(rest.cloned()).collect();
+
+    let bytes = fs::read(&filename).unwrap_or_else(|_| panic!("Failed to read file: {}", filename));
+    let color = std::io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+    let (mut program, source) = if bytes.starts_with(MAGIC) {
+        // already compiled
+        if dump.is_some() {
+            eprintln!("error: {}: --dump-ast needs a C source file", filename);
+            std::process::exit(1);
+        }
+        match Program::read_from(&mut bytes.as_slice()) {
+            Ok(program) => (program, None),
+            Err(error) => {
+                eprintln!("error: {}: {}", filename, error);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        let source = String::from_utf8(bytes).unwrap_or_else(|_| panic!("Failed to read file: {}", filename));
+        let preprocessed = match preprocessor.preprocess(&source, &filename) {
+            Ok(preprocessed) => preprocessed,
+            Err(diagnostic) => {
+                eprintln!("{}", Renderer::new(&filename, &source).color(color).render(&diagnostic));
+                std::process::exit(1);
+            }
+        };
+
+        if let Some(format) = dump {
+            match dump_ast(&preprocessed.text, format) {
+                Ok(tree) => print!("{}", tree),
+                Err(diagnostics) => {
+                    let renderer = Renderer::preprocessed(&preprocessed).color(color);
+                    for diagnostic in &diagnostics {
+                        eprintln!("{}", renderer.render(diagnostic));
+                    }
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+
+        // Compile
+        let mut parser = Parser::new(&preprocessed.text);
+        let parsed = parser.try_parse();
+        let renderer = Renderer::preprocessed(&preprocessed).color(color);
+        for warning in &parser.warnings {
+            eprintln!("{}", renderer.render_warning(warning));
+        }
+        if let Err(diagnostics) = parsed {
+            for diagnostic in &diagnostics {
+                eprintln!("{}", renderer.render(diagnostic));
+            }
+            std::process::exit(1);
+        }
+        (parser.program(), Some(preprocessed))
+    };
+
+    // a `.c4b` file may have been written by anything; check it before any pass relies on it.
+    // This is the only check: the VM trusts what it is given
+    if let Err(error) = program.verify() {
+        eprintln!("error: {}: malformed program: {}", filename, error);
         std::process::exit(1);
     }
 
-    let filename = &args[1];
-    let source = fs::read_to_string(filename)
-        .unwrap_or_else(|_| panic!("Failed to read file: {}", filename));
+    let warnings = optimize(&mut program, level);
+    // a `.c4b` file has no source to point into; it was warned about when it was compiled
+    if warn_dead_code && let Some(preprocessed) = &source {
+        let renderer = Renderer::preprocessed(preprocessed).color(color);
+        for warning in &warnings {
+            eprintln!("{}", renderer.render_warning(warning));
+        }
+    }
 
-    // Compile
-    let mut parser = Parser::new(&source);
-    parser.parse();
+    match command {
+        Command::Disasm => {
+            print!("{}", disassemble(&program, source.as_ref().map(|p| p.text.as_str())));
+            return;
+        }
+        Command::Compile => {
+            let path = output.map_or_else(|| Path::new(&filename).with_extension("c4b"), Into::into);
+            if strip {
+                program.spans.clear();
+            }
+            let written = fs::File::create(&path).and_then(|mut file| program.write_to(&mut file));
+            if let Err(error) = written {
+                eprintln!("error: {}: {}", path.display(), error);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Command::Run => {}
+    }
 
-    let main_ip = parser
+    let main_ip = program
         .main_label
         .expect("No `main` function found in source");
 
-    let mut vm = VM::new(parser.functions.iter().map(|(name, addr)| {
-        let arity = *parser.function_arity.get(name).unwrap_or(&0);
-        (*addr, arity)
-    }).collect());
-
-    use c4_rust::vm::Value; 
-
-    // Push zeroes for top-level locals (outside functions)
-    for _ in 0..parser.locals.len() {
-        vm.stack.push(Value::Int(0)); //  wrap it in Value::Int
+    let mut vm = program.vm();
+    for path in &readable {
+        vm.allow_read(path);
+    }
+    for path in &writable {
+        vm.allow_write(path);
     }
-    
 
-    // Execute
-    let result = vm.run_from(&parser.code, main_ip)
-        .expect("Execution failed");
+    if program.function_arity.get("main").is_some_and(|&n| n > 0) {
+        vm.set_args(&program_args);
+    }
 
-    println!("\nProgram result: {}", result);
+    // Execute; `main`'s return value or the `exit` status becomes our exit status.
+    // The summary goes to stderr so stdout carries only what the program printed.
+    let status = match vm.execute(&program.code, main_ip) {
+        Outcome::Returned(result) => {
+            let result = result.expect("Execution failed");
+            eprintln!("\nProgram result: {}", result);
+            result
+        }
+        Outcome::Halted(code) => {
+            eprintln!("\nProgram exited with status {}", code);
+            code
+        }
+    };
+    std::process::exit(status as i32);
 }
@@ -0,0 +1,110 @@
+//! Optimization passes over a compiled `Program`, selected by level like a C compiler's `-O`.
+//!
+//! - `-O0` (the default) runs the code exactly as the code generator emitted it.
+//! - `-O1` folds constant expressions and simplifies algebraic identities (`fold`), then
+//!   removes unreachable code and functions that are never called (`dce`).
+//! - `-O2` also inlines calls to small functions (`inline`) and rewrites short instruction
+//!   sequences, fusing common ones into the superinstructions `AddLocal` and `CmpJz`
+//!   (`peephole`).
+//!
+//! Passes rewrite `Program::code` in place. Jumps name labels, so they survive instructions
+//! being added or removed, but `Call` operands, function entries and `main_label` are code
+//! indices; `relocate` moves them along with the instructions.
+
+use crate::dce::{dead_code_warnings, eliminate_dead_code};
+use crate::diagnostic::Diagnostic;
+use crate::fold::fold_constants;
+use crate::inline::{inline_functions, INLINE_THRESHOLD};
+use crate::peephole::peephole;
+use crate::instruction::Instruction;
+use crate::program::Program;
+use crate::token::Span;
+
+/// Optimize `program` at `level`: 0 changes nothing, and each level adds passes to the last.
+/// Returns warnings about the dead code found, which `-O0` reports but keeps.
+pub fn optimize(program: &mut Program, level: u8) -> Vec<Diagnostic> {
+    if level == 0 {
+        return dead_code_warnings(program);
+    }
+    fold_constants(program);
+    let warnings = eliminate_dead_code(program);
+    if level >= 2 {
+        inline_functions(program, INLINE_THRESHOLD);
+        // drops the functions every call to which was inlined, which isn't worth a warning
+        eliminate_dead_code(program);
+        peephole(program);
+    }
+    warnings
+}
+
+/// Code being rewritten by a pass, one instruction at a time. The instructions from
+/// `barrier` on run straight through to the end, so a pass may combine them with the next
+/// one; control can arrive at the barrier from elsewhere (it is a label or an entry point).
+pub(crate) struct Rewriter {
+    pub code: Vec<Instruction>,
+    pub spans: Vec<Span>,
+    pub barrier: usize,
+}
+
+impl Rewriter {
+    /// The straight run of instructions ending the code so far.
+    pub fn tail(&self) -> &[Instruction] {
+        &self.code[self.barrier..]
+    }
+
+    /// Remove the last `n` instructions.
+    pub fn pop(&mut self, n: usize) {
+        self.code.truncate(self.code.len() - n);
+        self.spans.truncate(self.spans.len() - n);
+        self.barrier = self.barrier.min(self.code.len());
+    }
+
+    pub fn emit(&mut self, instr: Instruction, span: Span) {
+        self.code.push(instr);
+        self.spans.push(span);
+    }
+}
+
+/// Pass `program`'s instructions through `push` in order, which appends whatever should
+/// replace each one, and install the result. A new straight run starts at every label and at
+/// every function entry and the program's entry point.
+pub(crate) fn rewrite(program: &mut Program, mut push: impl FnMut(&mut Rewriter, Instruction, Span)) {
+    let mut entries: Vec<usize> = program.functions.values().copied().collect();
+    entries.push(program.entry());
+    entries.sort_unstable();
+
+    let mut out = Rewriter { code: Vec::new(), spans: Vec::new(), barrier: 0 };
+    let mut new_index = Vec::with_capacity(program.code.len() + 1);
+    for (i, instr) in program.code.iter().enumerate() {
+        new_index.push(out.code.len());
+        if matches!(instr, Instruction::Label(_)) || entries.binary_search(&i).is_ok() {
+            out.barrier = out.code.len();
+        }
+        let span = program.spans.get(i).copied().unwrap_or_default();
+        push(&mut out, instr.clone(), span);
+    }
+    new_index.push(out.code.len());
+    relocate(program, out.code, out.spans, &new_index);
+}
+
+/// Replace `program`'s code with `code` (and its `spans`), where `new_index[i]` is where
+/// old instruction `i` ended up, or the instruction that took its place if it was removed
+/// (`new_index[code.len()]` is the new end). Entry points and `Call` targets are moved to
+/// match; spans are only kept if the program had them.
+pub(crate) fn relocate(program: &mut Program, mut code: Vec<Instruction>, spans: Vec<Span>, new_index: &[usize]) {
+    // out-of-range addresses are left for the verifier to reject
+    let moved = |addr: usize| new_index.get(addr).copied().unwrap_or(addr);
+    for instr in &mut code {
+        if let Instruction::Call(addr) | Instruction::TailCall(addr) = instr {
+            *addr = moved(*addr);
+        }
+    }
+    for entry in program.functions.values_mut() {
+        *entry = moved(*entry);
+    }
+    program.main_label = program.main_label.map(moved);
+    if !program.spans.is_empty() {
+        program.spans = spans;
+    }
+    program.code = code;
+}
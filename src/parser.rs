@@ -1,513 +1,610 @@
-/// The `Parser` struct is responsible for translating C-like source code into a linear sequence
-/// of virtual machine instructions. It performs recursive descent parsing over tokens provided
-/// by the lexer and constructs the internal representation of functions, expressions, and statements.
-/// This parser supports function definitions, control flow constructs, expressions with operator precedence,
-/// and type handling including basic float and integer operations. It also manages label generation,
-/// local variable tracking, and the mapping of function names to their instruction addresses.
-
-
+//! The `Parser` struct is responsible for translating C-like source code into an abstract syntax
+//! tree (see `ast`). It performs recursive descent parsing over tokens provided by the lexer,
+//! with precedence climbing for expressions, and recovers from syntax errors so every mistake
+//! in a file is reported in one run. It supports function definitions, global variables, enums,
+//! control flow constructs, and C4's type system (`char`, `int` and pointers to them, plus
+//! float literals).
+//!
+//! `try_parse` then hands the tree to `codegen`, which resolves names, checks types and emits
+//! the linear sequence of virtual machine instructions; the results are copied into the
+//! parser's public fields (`code`, `functions`, `data`, ...) for the VM to run.
+
+use crate::ast::{
+    BinaryOp, Decl, EnumDecl, Expr, ExprKind, Function, Global, Stmt, StmtKind, TranslationUnit,
+    Type, UnaryOp,
+};
+use crate::codegen::CodeGen;
+use crate::diagnostic::Diagnostic;
 use crate::instruction::Instruction;
 use crate::lexer::Lexer;
-use crate::token::{Token, TokenKind};
+use crate::program::Program;
+use crate::token::{Span, Token, TokenKind};
 use std::collections::HashMap;
 
+/// Binding power of the postfix operators `++`, `--` and `[]`. Operands of prefix operators
+/// and casts are parsed at this level, so only postfix operators bind tighter.
+const POSTFIX_BP: u8 = 13;
+
+/// An error is passed up as the `Err` to the nearest statement or top-level declaration,
+/// which records it and skips ahead to where parsing can resume.
+type ParseResult<T = ()> = Result<T, Diagnostic>;
+
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     current: Token,
+    prev_span: Span, // the token consumed last
     pub code: Vec<Instruction>,
-    label_id: usize,
-    pub functions: HashMap<String, usize>, // in struct Parser
+    pub spans: Vec<Span>, // source of each instruction in `code`
+    pub functions: HashMap<String, usize>,
     pub main_label: Option<usize>,
-    pub locals: HashMap<String, usize>,
-    next_local_offset: usize,
-    in_function: bool,              // new field
-    pub current_fn: Option<String>, // name of the function we're parsing, or None
+    pub locals: HashMap<String, usize>, // frame slots of the last function or top-level code
     pub function_arity: HashMap<String, usize>,
+    pub data: Vec<u8>, // string literals and globals, loaded at `DATA_BASE`
+    globals: HashMap<String, usize>, // name → VM address
+    strings: Vec<usize>,             // VM addresses of the string literals
+    pub diagnostics: Vec<Diagnostic>,
+    pub warnings: Vec<Diagnostic>, // from the lexer; they don't stop compilation
 }
 
 impl<'a> Parser<'a> {
     pub fn new(source: &'a str) -> Self {
         let mut lexer = Lexer::new(source);
         let current = lexer.next_token();
+        let diagnostics = std::mem::take(&mut lexer.diagnostics);
+        let warnings = std::mem::take(&mut lexer.warnings);
         Self {
             lexer,
             current,
+            prev_span: Span::default(),
             code: Vec::new(),
-            label_id: 0,
-            locals: HashMap::new(),
-            next_local_offset: 0,
+            spans: Vec::new(),
             functions: HashMap::new(),
             main_label: None,
-            in_function: false,
-            current_fn: None,
+            locals: HashMap::new(),
             function_arity: HashMap::new(),
+            data: Vec::new(),
+            globals: HashMap::new(),
+            strings: Vec::new(),
+            diagnostics,
+            warnings,
         }
     }
 
     fn next_token(&mut self) {
+        self.prev_span = self.current.span;
         self.current = self.lexer.next_token();
+        self.diagnostics.append(&mut self.lexer.diagnostics);
+        self.warnings.append(&mut self.lexer.warnings);
     }
 
+    /// An error at the current token.
+    fn error(&self, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::at(message, self.current.span)
+    }
+
+    /// The span from the start of `start` to the end of the token consumed last.
+    fn span_from(&self, start: Span) -> Span {
+        Span { end: self.prev_span.end, ..start }
+    }
+
+    /// Compile the whole source, panicking with the first error if there is one.
     pub fn parse(&mut self) {
+        if let Err(diagnostics) = self.try_parse() {
+            panic!("{}", diagnostics[0]);
+        }
+    }
+
+    /// Compile the whole source, returning every diagnostic (lexer errors included) if it
+    /// has mistakes. The diagnostics also stay in `self.diagnostics`.
+    ///
+    /// Code is generated even for a source with syntax errors, from whatever parsed, so
+    /// mistakes like undefined variables are reported alongside them.
+    pub fn try_parse(&mut self) -> Result<(), Vec<Diagnostic>> {
+        let unit = self.translation_unit();
+
+        let mut codegen = CodeGen::new();
+        codegen.generate(&unit);
+        self.globals = codegen.global_addresses();
+        self.code = codegen.code;
+        self.spans = codegen.spans;
+        self.functions = codegen.functions;
+        self.main_label = codegen.main_label;
+        self.locals = codegen.locals;
+        self.function_arity = codegen.function_arity;
+        self.data = codegen.data;
+        self.strings = codegen.strings;
+
+        self.diagnostics.extend(codegen.diagnostics);
+        self.diagnostics.sort_by_key(|d| d.span.map_or(usize::MAX, |span| span.start));
+
+        if self.diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(self.diagnostics.clone())
+        }
+    }
+
+    /// The compiled program, once `parse` or `try_parse` has run.
+    pub fn program(&self) -> Program {
+        Program {
+            code: self.code.clone(),
+            spans: self.spans.clone(),
+            functions: self.functions.clone(),
+            function_arity: self.function_arity.clone(),
+            main_label: self.main_label,
+            data: self.data.clone(),
+            globals: self.globals.clone(),
+            strings: self.strings.clone(),
+            locals: self.locals.len(),
+        }
+    }
+
+    /// Parse the whole source into a syntax tree without generating code. Only syntax (and
+    /// lexer) errors are found here; name and type errors are `codegen`'s.
+    pub fn parse_ast(&mut self) -> Result<TranslationUnit, Vec<Diagnostic>> {
+        let unit = self.translation_unit();
+        if self.diagnostics.is_empty() {
+            Ok(unit)
+        } else {
+            Err(self.diagnostics.clone())
+        }
+    }
+
+    /// Parse top-level items until the end of input, recovering from errors. Whatever parsed
+    /// is returned; the errors are in `self.diagnostics`.
+    fn translation_unit(&mut self) -> TranslationUnit {
+        let mut decls = Vec::new();
         while self.current.kind != TokenKind::Eof {
-            // —— Named-function prologue detection ——
-            if !self.in_function && self.current.kind == TokenKind::Int {
-                self.next_token(); // consume `int`
-
-                if let TokenKind::Id(func_name) = &self.current.kind {
-                    let name = func_name.clone();
-                    self.next_token(); // consume the identifier
-
-                    if self.current.kind == TokenKind::LParen {
-                        // + mark that we’re inside this named function
-                        self.in_function = true;
-                        self.current_fn = Some(name.clone());
-
-                        // —— parse parameter list ——
-                        self.next_token(); // consume '('
-                        let mut params = Vec::new();
-                        while self.current.kind == TokenKind::Int
-                            || self.current.kind == TokenKind::Char
-                        {
-                            self.next_token(); // consume type
-                            if let TokenKind::Id(p) = &self.current.kind {
-                                params.push(p.clone());
-                                self.next_token(); // consume name
-                                if self.current.kind == TokenKind::Comma {
-                                    self.next_token(); // consume ','
-                                }
-                            } else {
-                                panic!("Expected identifier in parameter list");
-                            }
-                        }
-                        if self.current.kind != TokenKind::RParen {
-                            panic!("Expected ')' after parameters");
-                        }
-                        self.next_token(); // consume ')'
-
-                        // —— function body open ——
-                        if self.current.kind != TokenKind::LBrace {
-                            panic!("Expected '{{' to start function body");
-                        }
-                        self.next_token(); // consume '{'
-
-                        // record entry point
-                        let entry = self.code.len();
-                        self.functions.insert(name.clone(), entry);
-                        self.function_arity.insert(name.clone(), params.len());
-
-                        if name == "main" {
-                            self.main_label = Some(entry);
-                        }
-
-                        // reset locals & assign parameter slots
-                        self.locals.clear();
-                        self.next_local_offset = 0;
-                        for (i, p) in params.into_iter().enumerate() {
-                            self.locals.insert(p, i);
-                            self.next_local_offset += 1;
-                        }
-
-                        // placeholder Enter, we'll patch after the body
-                        self.code.push(Instruction::Enter(0));
-
-                        // —— parse the function body ——
-                        while self.current.kind != TokenKind::RBrace {
-                            self.stmt();
-                        }
-                        self.next_token(); // consume '}'
-
-                        // now that all locals (params + any `int x;` inside) are in self.locals:
-                        let total_slots = self.locals.len();
-                        // patch Enter with correct slot count
-                        if let Instruction::Enter(ref mut cnt) = self.code[entry] {
-                            *cnt = total_slots;
-                        }
-
-                        // leave the function
-                        self.code.push(Instruction::Leave);
-
-                        // + done with this function
-                        self.in_function = false;
-                        self.current_fn = None;
-
-                        continue;
+            let start = self.current.span.start;
+            if let Err(diagnostic) = self.top_level(&mut decls) {
+                self.report(diagnostic);
+                self.synchronize(true);
+                if self.current.span.start == start {
+                    self.next_token(); // make progress past a stray `}`
+                }
+            }
+        }
+        TranslationUnit { decls }
+    }
+
+    /// Record an error, unless it is fallout from an earlier one: a second complaint about
+    /// the same token, or about running out of input after something already went wrong
+    /// (an unterminated comment, a missing `}`, ...).
+    fn report(&mut self, diagnostic: Diagnostic) {
+        let at_end = self.current.kind == TokenKind::Eof && !self.diagnostics.is_empty();
+        let repeated = self.diagnostics.last().is_some_and(|last| last.span == diagnostic.span);
+        if !at_end && !repeated {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Skip ahead after an error to where parsing can resume: past the `;` that ends the
+    /// broken statement, past a block opened (and closed) while skipping, or up to the `}`
+    /// closing the enclosing block. At the top level, a type or `enum` keyword starting a
+    /// line also begins a fresh declaration.
+    fn synchronize(&mut self, top_level: bool) {
+        let mut depth = 0;
+        loop {
+            match self.current.kind {
+                TokenKind::Eof => return,
+                TokenKind::Semicolon if depth == 0 => {
+                    self.next_token();
+                    return;
+                }
+                TokenKind::RBrace if depth == 0 => return,
+                TokenKind::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.next_token();
+                        return;
                     }
                 }
-                // if it looked like “int foo” but not “int foo(”, fall through
+                TokenKind::LBrace => depth += 1,
+                TokenKind::Int | TokenKind::Long | TokenKind::Char | TokenKind::Void | TokenKind::Enum
+                    if top_level && depth == 0 && self.current.span.column == 1 =>
+                {
+                    return;
+                }
+                _ => {}
             }
+            self.next_token();
+        }
+    }
+
+    /// One top-level item: an enum, a global or function definition, a block or a statement.
+    /// Items are appended to `decls` as soon as they are complete, so the globals declared
+    /// before a broken declarator are kept.
+    fn top_level(&mut self, decls: &mut Vec<Decl>) -> ParseResult {
+        let start = self.current.span;
+
+        // —— Enum constants ——
+        if self.current.kind == TokenKind::Enum {
+            return self.enum_decl(decls);
+        }
 
-            // —— Top-level anonymous block ——
-            if !self.in_function && self.current.kind == TokenKind::LBrace {
-                self.next_token(); // consume '{'
-                while self.current.kind != TokenKind::RBrace {
-                    self.stmt();
+        // —— Global variables and named functions ——
+        if let Some(base) = self.base_type() {
+            return self.global_decl(base, start, decls);
+        }
+
+        // —— Everything else (anonymous blocks included) is just a statement ——
+        let stmt = self.stmt()?;
+        decls.push(Decl::Stmt(stmt));
+        Ok(())
+    }
+
+    /// Parse the statements of a block whose `{` has been consumed, up to and including its
+    /// `}`. A broken statement is reported and skipped so the rest of the block still gets
+    /// checked.
+    fn block(&mut self) -> ParseResult<Vec<Stmt>> {
+        let mut stmts = Vec::new();
+        while !matches!(self.current.kind, TokenKind::RBrace | TokenKind::Eof) {
+            let start = self.current.span.start;
+            match self.stmt() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(diagnostic) => {
+                    self.report(diagnostic);
+                    self.synchronize(false);
+                    if self.current.span.start == start {
+                        self.next_token();
+                    }
                 }
-                self.next_token(); // consume '}'
-                continue;
             }
+        }
+        if self.current.kind == TokenKind::Eof {
+            return Err(self.error("Expected '}' before end of input"));
+        }
+        self.next_token(); // consume '}'
+        Ok(stmts)
+    }
 
-            // —— Everything else is just a statement ——
-            self.stmt();
+    /// Consume a base type (`int`, `char`, `void`, or `long`/`long long`/`long int`, which
+    /// are all the VM's 64-bit `int`), if there is one.
+    fn base_type(&mut self) -> Option<Type> {
+        let ty = match self.current.kind {
+            TokenKind::Int | TokenKind::Long => Type::Int,
+            TokenKind::Char => Type::Char,
+            TokenKind::Void => Type::Void,
+            _ => return None,
+        };
+        self.next_token();
+        if ty == Type::Int {
+            while matches!(self.current.kind, TokenKind::Int | TokenKind::Long) {
+                self.next_token();
+            }
         }
+        Some(ty)
+    }
 
-        // fake exit label for top-level Jmp(9999)
-        self.code.push(Instruction::Label(9999));
+    /// Consume the `*`s of a declarator, wrapping `base` in one pointer level per star.
+    fn pointer_type(&mut self, mut ty: Type) -> Type {
+        while self.current.kind == TokenKind::Mul {
+            self.next_token(); // consume '*'
+            ty = ty.ptr();
+        }
+        ty
     }
 
-    fn new_label(&mut self) -> usize {
-        let id = self.label_id;
-        self.label_id += 1;
-        id
+    /// Parse `enum [name] { A, B = 5, C };`, working out the value of each member.
+    fn enum_decl(&mut self, decls: &mut Vec<Decl>) -> ParseResult {
+        let start = self.current.span;
+        self.next_token(); // consume 'enum'
+        let mut name = None;
+        if let TokenKind::Id(tag) = &self.current.kind {
+            name = Some(tag.clone());
+            self.next_token();
+        }
+        let mut members = Vec::new();
+        if self.current.kind == TokenKind::LBrace {
+            self.next_token(); // consume '{'
+            let mut value = 0;
+            while self.current.kind != TokenKind::RBrace {
+                let TokenKind::Id(member) = self.current.kind.clone() else {
+                    return Err(self.error("Expected identifier in enum"));
+                };
+                self.next_token(); // consume name
+                if self.current.kind == TokenKind::Assign {
+                    self.next_token(); // consume '='
+                    let negative = self.current.kind == TokenKind::Sub;
+                    if negative {
+                        self.next_token();
+                    }
+                    value = match self.current.kind {
+                        TokenKind::Num(n) if negative => -n,
+                        TokenKind::Num(n) => n,
+                        _ => return Err(self.error("Expected integer enum initializer")),
+                    };
+                    self.next_token();
+                }
+                members.push((member, value));
+                value += 1;
+                if self.current.kind == TokenKind::Comma {
+                    self.next_token(); // consume ','
+                }
+            }
+            self.next_token(); // consume '}'
+        }
+        decls.push(Decl::Enum(EnumDecl { name, members, span: self.span_from(start) }));
+        if self.current.kind == TokenKind::Semicolon {
+            self.next_token();
+            Ok(())
+        } else {
+            // `enum { ... } x, y;` declares int globals
+            self.global_decl(Type::Int, start, decls)
+        }
     }
 
-    fn stmt(&mut self) {
-        // Variable declaration
-        if self.current.kind == TokenKind::Int || self.current.kind == TokenKind::Char {
-            println!("Parsing variable declaration");
-            self.next_token(); // consume 'int' or 'char'
+    /// Parse the declarators following a base type at the top level: either global variables
+    /// or a function definition. `start` is where the declaration began.
+    fn global_decl(&mut self, base: Type, start: Span, decls: &mut Vec<Decl>) -> ParseResult {
+        loop {
+            let ty = self.pointer_type(base.clone());
+            let TokenKind::Id(name) = self.current.kind.clone() else {
+                return Err(self.error("Expected identifier in global declaration"));
+            };
+            let span = self.current.span;
+            self.next_token(); // consume the identifier
+
+            if self.current.kind == TokenKind::LParen {
+                let function = self.function(name, ty, start)?;
+                decls.push(Decl::Function(function));
+                return Ok(());
+            }
+
+            decls.push(Decl::Global(Global { name, ty, span }));
 
-            // + skip any '*' (pointer declarators) before the identifier
-            while self.current.kind == TokenKind::Mul {
-                self.next_token(); // consume '*'
+            match self.current.kind {
+                TokenKind::Comma => self.next_token(),
+                TokenKind::Semicolon => {
+                    self.next_token();
+                    return Ok(());
+                }
+                _ => return Err(self.error("Expected ';' after global declaration")),
             }
+        }
+    }
 
-            while let TokenKind::Id(name) = &self.current.kind {
-                let var_name = name.clone();
-                self.locals.insert(var_name, self.next_local_offset);
-                self.next_local_offset += 1;
+    /// Parse a function definition from its parameter list on; `name` and the return type
+    /// have already been consumed.
+    fn function(&mut self, name: String, return_type: Type, start: Span) -> ParseResult<Function> {
+        // —— parse parameter list ——
+        self.next_token(); // consume '('
+        let mut params = Vec::new();
+        while let Some(base) = self.base_type() {
+            let ty = self.pointer_type(base);
+            if let TokenKind::Id(p) = &self.current.kind {
+                params.push((p.clone(), ty));
+                self.next_token(); // consume name
+                if self.current.kind == TokenKind::Comma {
+                    self.next_token(); // consume ','
+                }
+            } else if ty == Type::Void && self.current.kind == TokenKind::RParen {
+                break; // `f(void)`
+            } else {
+                return Err(self.error("Expected identifier in parameter list"));
+            }
+        }
+        if self.current.kind != TokenKind::RParen {
+            return Err(self.error("Expected ')' after parameters"));
+        }
+        self.next_token(); // consume ')'
 
+        // —— function body ——
+        if self.current.kind != TokenKind::LBrace {
+            return Err(self.error("Expected '{' to start function body"));
+        }
+        self.next_token(); // consume '{'
+        let body = self.block()?;
+
+        Ok(Function {
+            name,
+            return_type,
+            params,
+            body,
+            span: self.span_from(start),
+            end: self.prev_span,
+        })
+    }
+
+    fn stmt(&mut self) -> ParseResult<Stmt> {
+        let start = self.current.span;
+
+        // Variable declaration
+        if let Some(base) = self.base_type() {
+            let mut vars = Vec::new();
+            loop {
+                let ty = self.pointer_type(base.clone());
+                let TokenKind::Id(name) = self.current.kind.clone() else {
+                    return Err(self.error("Expected identifier in variable declaration"));
+                };
+                vars.push((name, ty));
                 self.next_token(); // consume identifier
 
                 // Optional: handle comma-separated declarations
                 if self.current.kind == TokenKind::Comma {
                     self.next_token(); // consume ','
-                    // + also skip '*' on subsequent pointer declarations
-                    while self.current.kind == TokenKind::Mul {
-                        self.next_token();
-                    }
                 } else {
                     break;
                 }
             }
 
-            if self.current.kind != TokenKind::Semicolon {
-                panic!("Expected semicolon after variable declaration");
+            if self.current.kind == TokenKind::Semicolon {
+                self.next_token(); // consume ';'
+            } else {
+                // keep the variables, so their later uses aren't reported as undefined too
+                let error = self.error("Expected semicolon after variable declaration");
+                self.report(error);
+                self.synchronize(false);
             }
-            self.next_token(); // consume ';'
-            return;
+            return Ok(Stmt { kind: StmtKind::Locals(vars), span: self.span_from(start) });
         }
 
-        match &self.current.kind {
+        let kind = match &self.current.kind {
             TokenKind::If => {
-                println!("Parsing 'if' statement");
                 self.next_token(); // consume 'if'
 
                 if self.current.kind != TokenKind::LParen {
-                    panic!("Expected '(' after 'if'");
+                    return Err(self.error("Expected '(' after 'if'"));
                 }
                 self.next_token(); // consume '('
 
-                self.expr(); // parse condition expression
+                let cond = self.expr()?; // parse condition expression
 
                 if self.current.kind != TokenKind::RParen {
-                    panic!("Expected ')' after 'if' condition");
+                    return Err(self.error("Expected ')' after 'if' condition"));
                 }
                 self.next_token(); // consume ')'
 
-                // ⬇️ Add instruction emission for control flow
-                let false_label = self.new_label();
-                self.code.push(Instruction::Jz(false_label)); // if cond == 0, jump to false_label
-
-                self.stmt(); // then-body
-
-                // ⬇️ Patch jump target
-                self.code.push(Instruction::Label(false_label));
+                let then = Box::new(self.stmt()?); // then-body
+                let mut otherwise = None;
+                if self.current.kind == TokenKind::Else {
+                    self.next_token(); // consume 'else'
+                    otherwise = Some(Box::new(self.stmt()?)); // else-body
+                }
+                StmtKind::If { cond, then, otherwise }
             }
 
             TokenKind::While => {
-                println!("Parsing 'while' loop");
                 self.next_token(); // consume 'while'
 
                 if self.current.kind != TokenKind::LParen {
-                    panic!("Expected '(' after 'while'");
+                    return Err(self.error("Expected '(' after 'while'"));
                 }
                 self.next_token(); // consume '('
 
-                let start_label = self.new_label();
-                let end_label = self.new_label();
-
-                self.code.push(Instruction::Label(start_label)); // loop start
-
-                self.expr(); // loop condition
+                let cond = self.expr()?; // loop condition
 
                 if self.current.kind != TokenKind::RParen {
-                    panic!("Expected ')' after 'while' condition");
+                    return Err(self.error("Expected ')' after 'while' condition"));
                 }
                 self.next_token(); // consume ')'
 
-                self.code.push(Instruction::Jz(end_label)); // break if false
-
-                self.stmt(); // loop body
-
-                self.code.push(Instruction::Jmp(start_label)); // jump back to start
-                self.code.push(Instruction::Label(end_label)); // loop end
+                let body = Box::new(self.stmt()?); // loop body
+                StmtKind::While { cond, body }
             }
             TokenKind::Return => {
-                println!("Parsing 'return' statement");
                 self.next_token(); // consume `return`
-                self.expr(); // emit the return-value
-
-                // if we're in a named function that's NOT `main`, emit Leave,
-                // otherwise (main or top-level) jump to exit label:
-                match &self.current_fn {
-                    Some(name) if name != "main" => {
-                        self.code.push(Instruction::Leave);
-                    }
-                    _ => {
-                        self.code.push(Instruction::Jmp(9999));
-                    }
-                }
+                let value = if self.current.kind == TokenKind::Semicolon {
+                    None
+                } else {
+                    Some(self.expr()?)
+                };
 
                 if self.current.kind == TokenKind::Semicolon {
                     self.next_token();
                 } else {
-                    panic!("Expected semicolon after return expression");
+                    return Err(self.error("Expected semicolon after return expression"));
                 }
+                StmtKind::Return(value)
             }
             TokenKind::LBrace => {
-                println!("Parsing block");
                 self.next_token();
-                while self.current.kind != TokenKind::RBrace {
-                    self.stmt();
-                }
-                self.next_token(); // consume '}'
+                StmtKind::Block(self.block()?)
+            }
+            TokenKind::Semicolon => {
+                self.next_token(); // empty statement
+                StmtKind::Empty
             }
             _ => {
-                println!("Parsing expression statement");
-                self.expr();
+                let expr = self.expr()?;
                 if self.current.kind == TokenKind::Semicolon {
                     self.next_token();
                 } else {
-                    panic!("Expected semicolon after expression");
+                    return Err(self.error("Expected semicolon after expression"));
                 }
+                StmtKind::Expr(expr)
             }
-        }
+        };
+        Ok(Stmt { kind, span: self.span_from(start) })
     }
 
-    fn expr(&mut self) {
-        self.expr_bp(0); // starting at lowest precedence
+    fn expr(&mut self) -> ParseResult<Expr> {
+        self.expr_bp(0) // starting at lowest precedence
     }
 
-    fn expr_bp(&mut self, min_bp: u8) {
+    /// Parse an operand for a prefix operator and wrap it in the unary expression `op`.
+    fn unary(&mut self, op: UnaryOp) -> ParseResult<ExprKind> {
+        self.next_token(); // consume the operator
+        let operand = Box::new(self.expr_bp(POSTFIX_BP)?);
+        Ok(ExprKind::Unary { op, operand })
+    }
+
+    fn expr_bp(&mut self, min_bp: u8) -> ParseResult<Expr> {
+        let start = self.current.span;
         // ——— Prefix / "nud" ———
-        let prefix_op = matches!(
-            self.current.kind,
-            TokenKind::Sub
-                | TokenKind::Not
-                | TokenKind::Mul
-                | TokenKind::And
-                | TokenKind::Sizeof
-                | TokenKind::String(_)
-                | TokenKind::CharLiteral(_)
-                | TokenKind::Num(_)
-                | TokenKind::Float(_) 
-                | TokenKind::Id(_)
-                | TokenKind::LParen
-        );
-        if prefix_op {
-            // handle prefix expressions exactly once
-
-            println!("Parsing expression starting with {:?}", self.current.kind);
-            match &self.current.kind {
-                TokenKind::Float(f) => {
-                    let v = *f;
-                    self.next_token();
-                    self.code.push(Instruction::ImmF(v));
-                }
-                TokenKind::Num(n) => {
-                    let v = *n;
-                    self.next_token();
-                    self.code.push(Instruction::Imm(v));
-                }
-                TokenKind::Sub => {
-                    self.next_token();
-                    self.expr_bp(11);
-                    self.code.push(Instruction::Neg);
-                }
-                TokenKind::Not => {
-                    self.next_token();
-                    self.expr_bp(11);
-                    self.code.push(Instruction::Not);
-                }
-                TokenKind::Mul => {
-                    self.next_token();
-                    self.expr_bp(11);
-                    self.code.push(Instruction::Deref);
-                }
-                TokenKind::And => {
-                    self.next_token();
-                    if let TokenKind::Id(name) = &self.current.kind {
-                        let &offset = self
-                            .locals
-                            .get(name)
-                            .unwrap_or_else(|| panic!("Undefined variable `{}`", name));
-                        self.code.push(Instruction::Addr(offset));
-                        self.next_token();
-                    } else {
-                        panic!("Expected identifier after '&'");
-                    }
+        let kind = match self.current.kind.clone() {
+            TokenKind::Float(f) => {
+                self.next_token();
+                ExprKind::Float(f)
+            }
+            TokenKind::Num(n) => {
+                self.next_token();
+                ExprKind::Int(n)
+            }
+            TokenKind::Sub => self.unary(UnaryOp::Neg)?,
+            TokenKind::Add => self.unary(UnaryOp::Plus)?,
+            TokenKind::Not => self.unary(UnaryOp::Not)?,
+            TokenKind::Tilde => self.unary(UnaryOp::BitNot)?,
+            TokenKind::Mul => self.unary(UnaryOp::Deref)?,
+            TokenKind::And => self.unary(UnaryOp::AddrOf)?,
+            TokenKind::Inc => self.unary(UnaryOp::PreInc)?,
+            TokenKind::Dec => self.unary(UnaryOp::PreDec)?,
+            TokenKind::Sizeof => {
+                self.next_token();
+                if self.current.kind != TokenKind::LParen {
+                    return Err(self.error("Expected '(' after sizeof"));
                 }
-                TokenKind::Sizeof => {
-                    self.next_token();
-                    if self.current.kind != TokenKind::LParen {
-                        panic!("Expected '(' after sizeof");
-                    }
-                    self.next_token();
-
-                    let mut size = match self.current.kind {
-                        TokenKind::Int => std::mem::size_of::<i64>(),
-                        TokenKind::Char => std::mem::size_of::<i8>(),
-                        _ => panic!("Invalid type in sizeof"),
-                    } as i64;
-                    self.next_token();
+                self.next_token();
 
-                    while self.current.kind == TokenKind::Mul {
-                        size = std::mem::size_of::<usize>() as i64;
-                        self.next_token();
-                    }
+                let base = self.base_type().ok_or_else(|| self.error("Invalid type in sizeof"))?;
+                let ty = self.pointer_type(base);
 
-                    if self.current.kind != TokenKind::RParen {
-                        panic!("Expected ')' after sizeof");
-                    }
-                    self.next_token();
-
-                    self.code.push(Instruction::Imm(size));
-                }
-                TokenKind::String(s) => {
-                    let lit = s.clone();
-                    self.next_token();
-                    let addr = self.emit_string_literal(&lit);
-                    self.code.push(Instruction::Imm(addr));
-                }
-                TokenKind::CharLiteral(c) => {
-                    self.code.push(Instruction::Imm(*c as i64));
-                    self.next_token();
+                if self.current.kind != TokenKind::RParen {
+                    return Err(self.error("Expected ')' after sizeof"));
                 }
-                TokenKind::Num(n) => {
-                    self.code.push(Instruction::Imm(*n));
-                    self.next_token();
+                self.next_token();
+                ExprKind::Sizeof(ty)
+            }
+            TokenKind::String(s) => {
+                self.next_token();
+                ExprKind::Str(s)
+            }
+            TokenKind::CharLiteral(c) => {
+                self.next_token();
+                ExprKind::Char(c)
+            }
+            TokenKind::Id(name) => {
+                self.next_token();
+                if self.current.kind == TokenKind::LParen {
+                    let args = self.call_args()?;
+                    ExprKind::Call { name, args }
+                } else {
+                    ExprKind::Var(name)
                 }
-                TokenKind::Id(name) => {
-                    let var_name = name.clone();
-                    self.next_token();
-
-                    if var_name == "print" {
-                        // must see '('
-                        if self.current.kind != TokenKind::LParen {
-                            panic!("Expected '(' after print");
-                        }
-                        self.next_token();
-
-                        // string literal?
-                        if let TokenKind::String(s) = &self.current.kind {
-                            let s_lit = s.clone();
-                            self.next_token(); // consume the literal
-                            if self.current.kind != TokenKind::RParen {
-                                panic!("Expected ')' after print string");
-                            }
-                            self.next_token(); // consume ')'
-                            self.code.push(Instruction::PrintStr(s_lit));
-                        } else {
-                            // otherwise parse an integer expression
-                            self.expr_bp(0);
-                            if self.current.kind != TokenKind::RParen {
-                                panic!("Expected ')' after print expr");
-                            }
-                            self.next_token(); // consume ')'
-
-                            // Check if last emitted instruction was a float
-                            if let Some(Instruction::PushF(_)) = self.code.last() {
-                                self.code.push(Instruction::PrintF);
-                            } else {
-                                self.code.push(Instruction::Print);
-                            }
-                        }
-                        return;
+            }
+            TokenKind::LParen => {
+                self.next_token();
+                if let Some(base) = self.base_type() {
+                    let ty = self.pointer_type(base);
+                    if self.current.kind != TokenKind::RParen {
+                        return Err(self.error("Expected ')' after cast type"));
                     }
-
-                    // ——— everything else is a normal call ———
-                    if self.current.kind == TokenKind::LParen {
-                        self.next_token(); // consume '('
-                        let mut args = Vec::new();
-                        while self.current.kind != TokenKind::RParen {
-                            let start = self.code.len();
-                            self.expr_bp(0);
-                            let end = self.code.len();
-                            args.push(self.code.drain(start..end).collect::<Vec<_>>());
-                            if self.current.kind == TokenKind::Comma {
-                                self.next_token();
-                            }
-                        }
-                        self.next_token(); // consume ')'
-
-                        // push args right-to-left
-                        for arg in args.into_iter().rev() {
-                            self.code.extend(arg);
-                        }
-
-                        let &addr = self
-                            .functions
-                            .get(&var_name)
-                            .unwrap_or_else(|| panic!("Unknown function `{}`", var_name));
-
-                        self.code.push(Instruction::Call(addr));
-                    } else if self.current.kind == TokenKind::Assign {
-                        self.next_token();
-                        self.expr_bp(0);
-                        let &offset = self
-                            .locals
-                            .get(&var_name)
-                            .unwrap_or_else(|| panic!("Undefined variable `{}`", var_name));
-                        self.code.push(Instruction::StoreLocal(offset));
-                    } else {
-                        let &offset = self
-                            .locals
-                            .get(&var_name)
-                            .unwrap_or_else(|| panic!("Undefined variable `{}`", var_name));
-                        self.code.push(Instruction::LoadLocal(offset));
+                    self.next_token(); // consume ')'
+                    let expr = Box::new(self.expr_bp(POSTFIX_BP)?);
+                    ExprKind::Cast { ty, expr }
+                } else {
+                    let inner = self.expr_bp(0)?;
+                    if self.current.kind != TokenKind::RParen {
+                        return Err(self.error("Expected ')' after expression"));
                     }
-                }
-                TokenKind::LParen => {
                     self.next_token();
-                    let is_type = matches!(
-                        self.current.kind,
-                        TokenKind::Int | TokenKind::Char | TokenKind::Mul
-                    );
-                    if is_type {
-                        while matches!(
-                            self.current.kind,
-                            TokenKind::Int | TokenKind::Char | TokenKind::Mul
-                        ) {
-                            self.next_token();
-                        }
-                        if self.current.kind != TokenKind::RParen {
-                            panic!("Expected ')' after cast type");
-                        }
-                        self.next_token(); // consume ')'
-                        self.expr_bp(11);
-                        self.code.push(Instruction::Cast);
-                    } else {
-                        self.expr_bp(0);
-                        if self.current.kind != TokenKind::RParen {
-                            panic!("Expected ')' after expression");
-                        }
-                        self.next_token();
-                    }
+                    inner.kind
                 }
-                other => panic!("Unexpected token in expression: {:?}", other),
             }
-        }
+            other => return Err(self.error(format!("Unexpected token in expression: {:?}", other))),
+        };
+        let mut lhs = Expr { kind, span: self.span_from(start) };
+
         // ——— Infix / "led" ———
         loop {
             let op_bp = self.get_precedence();
@@ -517,60 +614,88 @@ impl<'a> Parser<'a> {
 
             let op = self.current.kind.clone();
             self.next_token();
+            let left = Box::new(lhs);
 
-            if op != TokenKind::Cond {
-                self.expr_bp(op_bp + 1);
-            }
+            let kind = match op {
+                TokenKind::Assign => {
+                    let value = Box::new(self.expr_bp(op_bp)?); // right-associative: a = b = c
+                    ExprKind::Assign { target: left, value }
+                }
 
-            match op {
                 TokenKind::Cond => {
-                    let else_lbl = self.new_label();
-                    self.code.push(Instruction::Jz(else_lbl));
-
-                    self.expr_bp(1);
-
-                    let end_lbl = self.new_label();
-                    self.code.push(Instruction::Jmp(end_lbl));
-                    self.code.push(Instruction::Label(else_lbl));
-
+                    let then = Box::new(self.expr_bp(1)?);
                     if self.current.kind != TokenKind::Colon {
-                        panic!("Expected ':' in conditional expression");
+                        return Err(self.error("Expected ':' in conditional expression"));
                     }
                     self.next_token();
-
-                    self.expr_bp(1);
-                    self.code.push(Instruction::Label(end_lbl));
+                    let otherwise = Box::new(self.expr_bp(op_bp)?);
+                    ExprKind::Cond { cond: left, then, otherwise }
                 }
 
-                TokenKind::Add => self.code.push(Instruction::Add),
-                TokenKind::Sub => self.code.push(Instruction::Sub),
-                TokenKind::Mul => self.code.push(Instruction::Mul),
-                TokenKind::Div => self.code.push(Instruction::Div),
-                TokenKind::Mod => self.code.push(Instruction::Mod),
-
-                TokenKind::Eq => self.code.push(Instruction::Eq),
-                TokenKind::Ne => self.code.push(Instruction::Ne),
-                TokenKind::Lt => self.code.push(Instruction::Lt),
-                TokenKind::Gt => self.code.push(Instruction::Gt),
-                TokenKind::Le => self.code.push(Instruction::Le),
-                TokenKind::Ge => self.code.push(Instruction::Ge),
-
-                TokenKind::And => self.code.push(Instruction::BitAnd),
-                TokenKind::Or => self.code.push(Instruction::BitOr),
-                TokenKind::Xor => self.code.push(Instruction::BitXor),
-
-                TokenKind::Shl => self.code.push(Instruction::Shl),
-                TokenKind::Shr => self.code.push(Instruction::Shr),
+                // postfix operators
+                TokenKind::Inc => ExprKind::Unary { op: UnaryOp::PostInc, operand: left },
+                TokenKind::Dec => ExprKind::Unary { op: UnaryOp::PostDec, operand: left },
+                TokenKind::LBracket => {
+                    let index = Box::new(self.expr_bp(0)?);
+                    if self.current.kind != TokenKind::RBracket {
+                        return Err(self.error("Expected ']' after subscript"));
+                    }
+                    self.next_token();
+                    ExprKind::Index { base: left, index }
+                }
 
-                _ => panic!("Unsupported infix operator: {:?}", op),
-            }
+                _ => {
+                    let op = match op {
+                        TokenKind::Add => BinaryOp::Add,
+                        TokenKind::Sub => BinaryOp::Sub,
+                        TokenKind::Mul => BinaryOp::Mul,
+                        TokenKind::Div => BinaryOp::Div,
+                        TokenKind::Mod => BinaryOp::Mod,
+
+                        TokenKind::Eq => BinaryOp::Eq,
+                        TokenKind::Ne => BinaryOp::Ne,
+                        TokenKind::Lt => BinaryOp::Lt,
+                        TokenKind::Gt => BinaryOp::Gt,
+                        TokenKind::Le => BinaryOp::Le,
+                        TokenKind::Ge => BinaryOp::Ge,
+
+                        TokenKind::And => BinaryOp::BitAnd,
+                        TokenKind::Or => BinaryOp::BitOr,
+                        TokenKind::Xor => BinaryOp::BitXor,
+
+                        TokenKind::Shl => BinaryOp::Shl,
+                        TokenKind::Shr => BinaryOp::Shr,
+
+                        TokenKind::Lan => BinaryOp::LogAnd,
+                        TokenKind::Lor => BinaryOp::LogOr,
+
+                        _ => return Err(self.error(format!("Unsupported infix operator: {:?}", op))),
+                    };
+                    let rhs = Box::new(self.expr_bp(op_bp + 1)?);
+                    ExprKind::Binary { op, lhs: left, rhs }
+                }
+            };
+            lhs = Expr { kind, span: self.span_from(start) };
         }
+        Ok(lhs)
     }
 
-    ///  need this helper to place string literals into your data segment
-    fn emit_string_literal(&mut self, _s: &str) -> i64 {
-        // e.g. push into a Vec<u8>, track its offset, then return that offset
-        unimplemented!("string literal emission");
+    /// Parse a parenthesised argument list, in source order.
+    fn call_args(&mut self) -> ParseResult<Vec<Expr>> {
+        self.next_token(); // consume '('
+        let mut args = Vec::new();
+        if self.current.kind != TokenKind::RParen {
+            loop {
+                args.push(self.expr_bp(0)?);
+                match self.current.kind {
+                    TokenKind::Comma => self.next_token(),
+                    TokenKind::RParen => break,
+                    _ => return Err(self.error("Expected ',' or ')' after function argument")),
+                }
+            }
+        }
+        self.next_token(); // consume ')'
+        Ok(args)
     }
 
     // Simple precedence rules based on C4
@@ -588,6 +713,7 @@ impl<'a> Parser<'a> {
             TokenKind::Shl | TokenKind::Shr => 10,
             TokenKind::Add | TokenKind::Sub => 11,
             TokenKind::Mul | TokenKind::Div | TokenKind::Mod => 12,
+            TokenKind::Inc | TokenKind::Dec | TokenKind::LBracket => POSTFIX_BP,
             _ => 0,
         }
     }
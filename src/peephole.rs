@@ -0,0 +1,90 @@
+//! The peephole optimizer (`-O2`): rewrites short instruction sequences into shorter ones,
+//! including the superinstructions `AddLocal` and `CmpJz`, which the VM runs in one dispatch.
+//!
+//! Loops are dominated by two shapes. `i = i + 1;` is `LoadLocal i; Imm 1; Add; StoreLocal i;
+//! Pop`, and a loop condition like `i < n` ends in `Lt; Jz end`. The rules, applied as each
+//! instruction is appended so that their results combine:
+//!
+//! | sequence                                | becomes                        |
+//! |-----------------------------------------|--------------------------------|
+//! | `LoadLocal n; Imm c; Add; StoreLocal n` | `AddLocal n, c; LoadLocal n`   |
+//! | `LoadLocal n; Imm c; Sub; StoreLocal n` | `AddLocal n, -c; LoadLocal n`  |
+//! | `LoadLocal`/`Imm`/`ImmF`/`Addr`/`Dup`; `Pop` | nothing                   |
+//! | `LoadLocal m; AddLocal n, c; Pop`       | `AddLocal n, c`                |
+//! | `StoreLocal n; Pop; LoadLocal n`        | `StoreLocal n`                 |
+//! | `Lt` (or another comparison); `Jz l`    | `CmpJz Lt, l`                  |
+//! | `Not; Jz l` / `Not; Jnz l`              | `Jnz l` / `Jz l`               |
+//! | `Jmp l; l:`                             | `l:`                           |
+//!
+//! So `i = i + 1;` becomes a single `AddLocal`, and so does `i++;`. Like constant folding,
+//! rules only combine instructions within a straight run, never across a label.
+
+use crate::instruction::{Compare, Instruction};
+use crate::optimize::{rewrite, Rewriter};
+use crate::program::Program;
+use crate::token::Span;
+
+/// Apply the peephole rules to `program`'s code.
+pub fn peephole(program: &mut Program) {
+    rewrite(program, push);
+}
+
+/// Whether `instr` only pushes a value, with no other effect, so pushing and popping it
+/// again can be skipped.
+fn is_pure_push(instr: &Instruction) -> bool {
+    matches!(
+        instr,
+        Instruction::LoadLocal(_) | Instruction::Imm(_) | Instruction::ImmF(_) | Instruction::Addr(_)
+    )
+}
+
+fn comparison(instr: &Instruction) -> Option<Compare> {
+    Some(match instr {
+        Instruction::Eq => Compare::Eq,
+        Instruction::Ne => Compare::Ne,
+        Instruction::Lt => Compare::Lt,
+        Instruction::Gt => Compare::Gt,
+        Instruction::Le => Compare::Le,
+        Instruction::Ge => Compare::Ge,
+        _ => return None,
+    })
+}
+
+/// Append `instr` to `out`, rewriting it together with the instructions before it when a
+/// rule matches. Replacements are appended through `push` too, so rules chain.
+fn push(out: &mut Rewriter, instr: Instruction, span: Span) {
+    use Instruction::*;
+    let tail = out.tail();
+    match (tail, &instr) {
+        ([.., last], Pop) if is_pure_push(last) || *last == Dup => out.pop(1),
+        ([.., load, AddLocal(..)], Pop) if is_pure_push(load) => {
+            let add = out.code.pop().unwrap();
+            let add_span = out.spans.pop().unwrap();
+            out.pop(1);
+            out.emit(add, add_span);
+        }
+        ([.., LoadLocal(a), Imm(c), op @ (Add | Sub)], StoreLocal(b)) if a == b && (*op == Add || *c != i64::MIN) => {
+            let (slot, c) = (*b, if *op == Add { *c } else { -c });
+            out.pop(3);
+            out.emit(AddLocal(slot, c), span);
+            push(out, LoadLocal(slot), span);
+        }
+        ([.., StoreLocal(a), Pop], LoadLocal(b)) if a == b => out.pop(1),
+        ([.., cmp], Jz(id)) if comparison(cmp).is_some() => {
+            let cmp = comparison(cmp).unwrap();
+            out.pop(1);
+            out.emit(CmpJz(cmp, *id), span);
+        }
+        ([.., Not], Jz(id) | Jnz(id)) => {
+            let branch = if matches!(instr, Jz(_)) { Jnz(*id) } else { Jz(*id) };
+            out.pop(1);
+            push(out, branch, span);
+        }
+        (_, Label(id)) if out.code.last() == Some(&Jmp(*id)) => {
+            // the label starts a new run, so the jump to it is just before the barrier
+            out.pop(1);
+            out.emit(instr, span);
+        }
+        _ => out.emit(instr, span),
+    }
+}
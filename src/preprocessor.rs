@@ -0,0 +1,973 @@
+//! The `Preprocessor` runs before the lexer and turns a C source file into plain C text. It
+//! joins `\`-continued lines, removes comments, expands object-like and function-like macros
+//! (including `#` stringizing, `##` pasting and `__VA_ARGS__`), evaluates `#if`/`#ifdef`/
+//! `#ifndef`/`#elif`/`#else` conditionals and splices in `#include`d files found next to the
+//! including file or on the include search path. Include guards work as usual, and
+//! `#pragma once` is honoured. Standard headers whose functions the VM provides as builtins
+//! resolve to nothing when they are not on the search path, so stock C sources (like the
+//! original `c4.c`) can be fed in unmodified.
+//!
+//! Every directive and every line swallowed by a conditional becomes an empty line, so line
+//! numbers in the output match the input; `Preprocessed::line_origins` maps each output line
+//! back to the file and line it came from.
+
+use crate::diagnostic::Diagnostic;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// C library headers whose functions are VM builtins.
+const BUILTIN_HEADERS: &[&str] = &[
+    "stdio.h", "stdlib.h", "string.h", "memory.h", "unistd.h", "fcntl.h", "stddef.h",
+];
+
+/// Nesting limit for `#include`; catches headers that include themselves without a guard.
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Option<Vec<String>>, // `None` for object-like macros
+    body: String,
+}
+
+/// The preprocessed program text and where each of its lines came from.
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessed {
+    pub text: String,
+    pub files: Vec<String>,                // every file that contributed lines
+    pub line_origins: Vec<(usize, usize)>, // per output line: (index into `files`, line number)
+}
+
+/// One level of `#if` nesting.
+struct Conditional {
+    parent_active: bool,
+    active: bool,    // lines of the current branch are kept
+    taken: bool,     // some branch has been kept already
+    seen_else: bool,
+    line: usize,
+}
+
+/// Why a directive failed: a message about the directive's own line, or a diagnostic from
+/// inside an included file.
+enum DirectiveError {
+    Message(String),
+    Nested(Diagnostic),
+}
+
+impl From<String> for DirectiveError {
+    fn from(message: String) -> Self {
+        DirectiveError::Message(message)
+    }
+}
+
+impl From<&str> for DirectiveError {
+    fn from(message: &str) -> Self {
+        DirectiveError::Message(message.to_string())
+    }
+}
+
+/// Why a macro could not be expanded.
+enum ExpandError {
+    Unterminated(String), // the argument list of this macro runs past the end of the text
+    Invalid(String),
+}
+
+#[derive(Default)]
+pub struct Preprocessor {
+    include_paths: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+    pragma_once: HashSet<PathBuf>,
+    output: Preprocessed,
+    depth: usize,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Search `dir` for `#include` files (after the including file's directory for `"..."`).
+    pub fn add_include_path(&mut self, dir: impl AsRef<Path>) {
+        self.include_paths.push(dir.as_ref().to_path_buf());
+    }
+
+    /// Predefine an object-like macro, like `-D name=value` on a C compiler command line.
+    pub fn define(&mut self, name: &str, value: &str) {
+        let body = value.trim().to_string();
+        self.macros.insert(name.to_string(), Macro { params: None, body });
+    }
+
+    /// Remove a macro definition, if there is one.
+    pub fn undefine(&mut self, name: &str) {
+        self.macros.remove(name);
+    }
+
+    /// Whether `name` is currently defined as a macro.
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.macros.contains_key(name)
+    }
+
+    /// Preprocess the file at `path`.
+    pub fn preprocess_file(&mut self, path: impl AsRef<Path>) -> Result<Preprocessed, Diagnostic> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|e| {
+            Diagnostic::new(format!("cannot read `{}`: {}", path.display(), e), 0)
+        })?;
+        self.preprocess(&source, path)
+    }
+
+    /// Preprocess `source`; `path` names it in diagnostics and anchors `"..."` includes.
+    /// Macros defined by the source stay defined afterwards.
+    pub fn preprocess(&mut self, source: &str, path: impl AsRef<Path>) -> Result<Preprocessed, Diagnostic> {
+        self.output = Preprocessed::default();
+        self.depth = 0;
+        self.process(source, path.as_ref())?;
+        Ok(std::mem::take(&mut self.output))
+    }
+
+    fn process(&mut self, source: &str, path: &Path) -> Result<(), Diagnostic> {
+        let name = path.display().to_string();
+        let file_id = match self.output.files.iter().position(|f| *f == name) {
+            Some(id) => id,
+            None => {
+                self.output.files.push(name.clone());
+                self.output.files.len() - 1
+            }
+        };
+        let error = |message: String, line: usize| Diagnostic::new(message, line).in_file(&name);
+
+        // splice `\`-continued lines first, then remove comments from the spliced text
+        let lines = logical_lines(source);
+        let spliced: Vec<&str> = lines.iter().map(|(_, text, _)| text.as_str()).collect();
+        let text = strip_comments(&spliced.join("\n"))
+            .map_err(|k| error("unterminated comment".to_string(), lines[k - 1].0))?;
+        let lines: Vec<(usize, String, usize)> = lines
+            .iter()
+            .zip(text.split('\n'))
+            .map(|(&(line_no, _, physical), text)| (line_no, text.to_string(), physical))
+            .collect();
+        let mut conditionals: Vec<Conditional> = Vec::new();
+
+        let mut next = 0;
+        while next < lines.len() {
+            let (line_no, ref line, physical) = lines[next];
+            next += 1;
+            let active = conditionals.last().is_none_or(|c| c.active);
+
+            if let Some(directive) = line.trim_start().strip_prefix('#') {
+                self.directive(directive, path, line_no, active, &mut conditionals)
+                    .map_err(|e| match e {
+                        DirectiveError::Message(message) => error(message, line_no),
+                        DirectiveError::Nested(diagnostic) => diagnostic,
+                    })?;
+                self.emit_blank(file_id, line_no, physical);
+                continue;
+            }
+            if !active {
+                self.emit_blank(file_id, line_no, physical);
+                continue;
+            }
+
+            // a macro call may spread its arguments over several lines
+            let mut text = line.clone();
+            let mut used = physical;
+            let expanded = loop {
+                match self.expand(&text, &mut Vec::new(), (&name, line_no)) {
+                    Ok(expanded) => break expanded,
+                    Err(ExpandError::Unterminated(_))
+                        if next < lines.len() && !lines[next].1.trim_start().starts_with('#') =>
+                    {
+                        text.push(' ');
+                        text.push_str(&lines[next].1);
+                        used += lines[next].2;
+                        next += 1;
+                    }
+                    Err(ExpandError::Unterminated(mac)) => {
+                        return Err(error(
+                            format!("unterminated argument list invoking macro `{}`", mac),
+                            line_no,
+                        ));
+                    }
+                    Err(ExpandError::Invalid(message)) => return Err(error(message, line_no)),
+                }
+            };
+            self.emit(&expanded, file_id, line_no);
+            self.emit_blank(file_id, line_no + 1, used - 1);
+        }
+
+        if let Some(open) = conditionals.last() {
+            return Err(error("unterminated #if".to_string(), open.line));
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, text: &str, file_id: usize, line: usize) {
+        self.output.text.push_str(text);
+        self.output.text.push('\n');
+        self.output.line_origins.push((file_id, line));
+    }
+
+    fn emit_blank(&mut self, file_id: usize, first_line: usize, count: usize) {
+        for k in 0..count {
+            self.emit("", file_id, first_line + k);
+        }
+    }
+
+    /// Handle one directive line (without the `#`).
+    fn directive(
+        &mut self,
+        directive: &str,
+        path: &Path,
+        line: usize,
+        active: bool,
+        conditionals: &mut Vec<Conditional>,
+    ) -> Result<(), DirectiveError> {
+        let directive = directive.trim();
+        let name_len = directive
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(directive.len());
+        let (name, rest) = (&directive[..name_len], directive[name_len..].trim());
+        let file = path.display().to_string();
+        let at = (file.as_str(), line);
+
+        // —— conditionals are tracked even inside skipped regions ——
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let cond = active
+                    && match name {
+                        "ifdef" => self.is_defined(macro_name(rest)?),
+                        "ifndef" => !self.is_defined(macro_name(rest)?),
+                        _ => self.eval_condition(rest, at)?,
+                    };
+                conditionals.push(Conditional {
+                    parent_active: active,
+                    active: cond,
+                    taken: cond,
+                    seen_else: false,
+                    line,
+                });
+                return Ok(());
+            }
+            "elif" => {
+                let Some(c) = conditionals.last_mut() else {
+                    return Err("#elif without #if".into());
+                };
+                if c.seen_else {
+                    return Err("#elif after #else".into());
+                }
+                let (parent_active, taken) = (c.parent_active, c.taken);
+                let cond = parent_active && !taken && self.eval_condition(rest, at)?;
+                let c = conditionals.last_mut().unwrap();
+                c.active = cond;
+                c.taken |= cond;
+                return Ok(());
+            }
+            "else" => {
+                let Some(c) = conditionals.last_mut() else {
+                    return Err("#else without #if".into());
+                };
+                if c.seen_else {
+                    return Err("#else after #else".into());
+                }
+                c.active = c.parent_active && !c.taken;
+                c.taken = true;
+                c.seen_else = true;
+                return Ok(());
+            }
+            "endif" => {
+                if conditionals.pop().is_none() {
+                    return Err("#endif without #if".into());
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+        if !active {
+            return Ok(());
+        }
+
+        match name {
+            "" => Ok(()), // the null directive
+            "define" => Ok(self.define_directive(rest)?),
+            "undef" => {
+                self.undefine(macro_name(rest)?);
+                Ok(())
+            }
+            "include" => self.include(rest, path, at),
+            "error" => Err(format!("#error {}", rest).into()),
+            "pragma" => {
+                if rest == "once" {
+                    self.pragma_once.insert(canonical(path));
+                }
+                Ok(()) // other pragmas are ignored
+            }
+            "warning" | "line" => Ok(()),
+            other => Err(format!("unknown preprocessor directive `#{}`", other).into()),
+        }
+    }
+
+    /// `#define NAME body` or `#define NAME(params) body`.
+    fn define_directive(&mut self, rest: &str) -> Result<(), String> {
+        let name_len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let name = macro_name(&rest[..name_len])?;
+        let after = &rest[name_len..];
+
+        // function-like only when `(` follows the name directly
+        let (params, body) = if let Some(list) = after.strip_prefix('(') {
+            let close = list.find(')').ok_or("missing ')' in macro parameter list")?;
+            let mut params = Vec::new();
+            if !list[..close].trim().is_empty() {
+                for p in list[..close].split(',') {
+                    let p = p.trim();
+                    if p != "..." && !is_identifier(p) {
+                        return Err(format!("invalid macro parameter `{}`", p));
+                    }
+                    params.push(p.to_string());
+                }
+            }
+            if params.iter().rev().skip(1).any(|p| p == "...") {
+                return Err("`...` must be the last macro parameter".to_string());
+            }
+            (Some(params), &list[close + 1..])
+        } else {
+            (None, after)
+        };
+
+        let body = body.trim().to_string();
+        self.macros.insert(name.to_string(), Macro { params, body });
+        Ok(())
+    }
+
+    /// `#include "file"` or `#include <file>`.
+    fn include(&mut self, rest: &str, path: &Path, at: (&str, usize)) -> Result<(), DirectiveError> {
+        let spec = if rest.starts_with('"') || rest.starts_with('<') {
+            rest.to_string()
+        } else {
+            // `#include MACRO`
+            self.expand(rest, &mut Vec::new(), at).map_err(|_| "invalid #include".to_string())?
+        };
+        let spec = spec.trim();
+        let (name, quoted) = if let Some(inner) = spec.strip_prefix('"') {
+            (inner.strip_suffix('"').ok_or("missing closing '\"' in #include")?, true)
+        } else if let Some(inner) = spec.strip_prefix('<') {
+            (inner.strip_suffix('>').ok_or("missing closing '>' in #include")?, false)
+        } else {
+            return Err("#include expects \"file\" or <file>".into());
+        };
+
+        let mut candidates = Vec::new();
+        if quoted {
+            let dir = path.parent().filter(|d| !d.as_os_str().is_empty());
+            candidates.push(dir.unwrap_or(Path::new(".")).join(name));
+        }
+        candidates.extend(self.include_paths.iter().map(|dir| dir.join(name)));
+
+        let Some(found) = candidates.into_iter().find(|p| p.is_file()) else {
+            if BUILTIN_HEADERS.contains(&name) {
+                return Ok(()); // provided by the VM
+            }
+            return Err(format!("cannot find include file `{}`", name).into());
+        };
+        if self.pragma_once.contains(&canonical(&found)) {
+            return Ok(());
+        }
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(format!("#include nested too deeply (including `{}`)", name).into());
+        }
+        let source = fs::read_to_string(&found)
+            .map_err(|e| format!("cannot read `{}`: {}", found.display(), e))?;
+
+        self.depth += 1;
+        let result = self.process(&source, &found);
+        self.depth -= 1;
+        // errors inside the included file already name that file
+        result.map_err(DirectiveError::Nested)
+    }
+
+    /// Macro-expand `text`. Macros in `disabled` are being expanded already and are left alone,
+    /// which stops self-referential macros from recursing forever.
+    ///
+    /// A macro's replacement is spliced back into `text` and rescanned together with what
+    /// follows it, so a replacement ending in a function-like macro's name can pick up its
+    /// arguments from the source. The macro stays disabled until the scan leaves its replacement.
+    fn expand(&self, text: &str, disabled: &mut Vec<String>, at: (&str, usize)) -> Result<String, ExpandError> {
+        let mut chars: Vec<char> = text.chars().collect();
+        // replaced macros, each with the index where its replacement ends
+        let mut replaced: Vec<(String, usize)> = Vec::new();
+        let mut out = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c == '"' || c == '\'' {
+                let end = skip_literal(&chars, i);
+                out.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+            if c.is_ascii_digit() {
+                // a number like `0x1F` or `10UL` is not an identifier
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                    out.push(chars[i]);
+                    i += 1;
+                }
+                continue;
+            }
+            if !(c.is_ascii_alphabetic() || c == '_') {
+                out.push(c);
+                i += 1;
+                continue;
+            }
+
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let name: String = chars[start..i].iter().collect();
+            match name.as_str() {
+                "__LINE__" => {
+                    out.push_str(&at.1.to_string());
+                    continue;
+                }
+                "__FILE__" => {
+                    out.push_str(&quote(at.0));
+                    continue;
+                }
+                _ => {}
+            }
+            let hidden = |name: &String| {
+                disabled.contains(name) || replaced.iter().any(|(n, end)| n == name && start < *end)
+            };
+            let Some(mac) = self.macros.get(&name).filter(|_| !hidden(&name)) else {
+                out.push_str(&name);
+                continue;
+            };
+
+            let body = match &mac.params {
+                None => mac.body.clone(),
+                Some(params) => {
+                    let mut open = i;
+                    while open < chars.len() && chars[open].is_whitespace() {
+                        open += 1;
+                    }
+                    if open >= chars.len() || chars[open] != '(' {
+                        out.push_str(&name); // a function-like macro name without a call
+                        continue;
+                    }
+                    let (args, end) = collect_args(&chars, open)
+                        .ok_or_else(|| ExpandError::Unterminated(name.clone()))?;
+                    i = end;
+                    self.substitute(&name, params, args, &mac.body, disabled, at)?
+                }
+            };
+            let body: Vec<char> = body.chars().collect();
+            let grown = body.len() as isize - (i - start) as isize;
+            for (_, end) in replaced.iter_mut().filter(|(_, end)| start < *end) {
+                *end = ((*end).max(i) as isize + grown) as usize;
+            }
+            replaced.push((name, start + body.len()));
+            chars.splice(start..i, body);
+            i = start;
+        }
+        Ok(out)
+    }
+
+    /// Replace the parameters in a function-like macro's `body` with the call's arguments.
+    fn substitute(
+        &self,
+        name: &str,
+        params: &[String],
+        mut args: Vec<String>,
+        body: &str,
+        disabled: &mut Vec<String>,
+        at: (&str, usize),
+    ) -> Result<String, ExpandError> {
+        if params.is_empty() && args.len() == 1 && args[0].trim().is_empty() {
+            args.clear(); // `f()`
+        }
+        let variadic = params.last().is_some_and(|p| p == "...");
+        let fixed = params.len() - variadic as usize;
+        if args.len() < fixed || (!variadic && args.len() != fixed) {
+            return Err(ExpandError::Invalid(format!(
+                "macro `{}` expects {} argument(s), got {}",
+                name,
+                fixed,
+                args.len()
+            )));
+        }
+        if variadic {
+            let rest = args.split_off(fixed).join(",");
+            args.push(rest);
+        }
+
+        let mut values = HashMap::new();
+        for (param, arg) in params.iter().zip(&args) {
+            let param = if param == "..." { "__VA_ARGS__" } else { param.as_str() };
+            let raw = arg.trim().to_string();
+            let expanded = self.expand(&raw, disabled, at)?;
+            values.insert(param.to_string(), (raw, expanded));
+        }
+
+        let tokens = body_tokens(body);
+        let significant = |k: usize, step: isize| {
+            let mut k = k as isize + step;
+            while k >= 0 && (k as usize) < tokens.len() && tokens[k as usize].trim().is_empty() {
+                k += step;
+            }
+            tokens.get(k as usize).map(String::as_str)
+        };
+
+        let mut out = String::new();
+        let mut k = 0;
+        while k < tokens.len() {
+            let tok = tokens[k].as_str();
+            if tok == "##" {
+                // paste: drop the whitespace on both sides
+                out.truncate(out.trim_end().len());
+                k += 1;
+                while k < tokens.len() && tokens[k].trim().is_empty() {
+                    k += 1;
+                }
+                continue;
+            }
+            if tok == "#" {
+                let mut p = k + 1;
+                while p < tokens.len() && tokens[p].trim().is_empty() {
+                    p += 1;
+                }
+                if let Some((raw, _)) = tokens.get(p).and_then(|t| values.get(t)) {
+                    out.push_str(&quote(raw));
+                    k = p + 1;
+                    continue;
+                }
+            }
+            match values.get(tok) {
+                Some((raw, expanded)) => {
+                    let pasted = significant(k, -1) == Some("##") || significant(k, 1) == Some("##");
+                    out.push_str(if pasted { raw } else { expanded });
+                }
+                None => out.push_str(tok),
+            }
+            k += 1;
+        }
+        Ok(out)
+    }
+
+    /// Evaluate the controlling expression of `#if`/`#elif`.
+    fn eval_condition(&self, expr: &str, at: (&str, usize)) -> Result<bool, String> {
+        // resolve `defined X` / `defined(X)` before macro expansion
+        let chars: Vec<char> = expr.chars().collect();
+        let mut resolved = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i].is_ascii_alphabetic() || chars[i] == '_' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                if word != "defined" {
+                    resolved.push_str(&word);
+                    continue;
+                }
+                let rest: String = chars[i..].iter().collect();
+                let trimmed = rest.trim_start();
+                let (inner, consumed) = match trimmed.strip_prefix('(') {
+                    Some(after) => {
+                        let close = after.find(')').ok_or("missing ')' after `defined`")?;
+                        (after[..close].trim(), rest.len() - after.len() + close + 1)
+                    }
+                    None => {
+                        let len = trimmed
+                            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                            .unwrap_or(trimmed.len());
+                        (&trimmed[..len], rest.len() - trimmed.len() + len)
+                    }
+                };
+                let name = macro_name(inner)?;
+                resolved.push_str(if self.is_defined(name) { " 1 " } else { " 0 " });
+                i += rest[..consumed].chars().count();
+            } else {
+                resolved.push(chars[i]);
+                i += 1;
+            }
+        }
+
+        let expanded = self
+            .expand(&resolved, &mut Vec::new(), at)
+            .map_err(|_| "invalid macro call in #if".to_string())?;
+        let mut parser = CondParser { tokens: cond_tokens(&expanded)?, pos: 0 };
+        if parser.tokens.is_empty() {
+            return Err("#if with no expression".to_string());
+        }
+        let value = parser.ternary()?;
+        if parser.pos != parser.tokens.len() {
+            return Err("unexpected tokens at end of #if expression".to_string());
+        }
+        Ok(value != 0)
+    }
+}
+
+/// Replace comments with a space, keeping their newlines so every line stays put.
+/// An unterminated block comment reports the (1-based) line of `source` it starts on.
+fn strip_comments(source: &str) -> Result<String, usize> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut out = String::with_capacity(source.len());
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' || c == '\'' {
+            let end = skip_literal(&chars, i);
+            out.extend(&chars[i..end]);
+            i = end;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            let start = line;
+            i += 2;
+            loop {
+                match chars.get(i) {
+                    None => return Err(start),
+                    Some('*') if chars.get(i + 1) == Some(&'/') => break,
+                    Some('\n') => {
+                        line += 1;
+                        out.push('\n');
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            i += 2;
+            out.push(' ');
+        } else {
+            if c == '\n' {
+                line += 1;
+            }
+            out.push(c);
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+/// Split `text` into logical lines, joining `\`-continued ones. Each entry holds the first
+/// line number, the text, and how many physical lines it spans.
+fn logical_lines(text: &str) -> Vec<(usize, String, usize)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String, usize)> = None;
+    for (k, raw) in text.split('\n').enumerate() {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        let mut entry = pending.take().unwrap_or((k + 1, String::new(), 0));
+        entry.2 += 1;
+        match raw.strip_suffix('\\') {
+            Some(continued) => {
+                entry.1.push_str(continued);
+                pending = Some(entry);
+            }
+            None => {
+                entry.1.push_str(raw);
+                lines.push(entry);
+            }
+        }
+    }
+    if let Some(entry) = pending {
+        lines.push(entry);
+    }
+    // `split` yields an empty piece after a trailing newline
+    if text.ends_with('\n') {
+        lines.pop();
+    }
+    lines
+}
+
+/// Index just past the string or char literal starting at `start`. Literals end at a newline
+/// if they are not closed; the lexer reports those.
+fn skip_literal(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < chars.len() && chars[i] != quote && chars[i] != '\n' {
+        if chars[i] == '\\' {
+            i += 1;
+        }
+        i += 1;
+    }
+    (i + 1).min(chars.len())
+}
+
+/// Collect the comma-separated arguments of a macro call whose `(` is at `open`. Returns the
+/// raw arguments and the index after the closing `)`, or `None` if it never closes.
+fn collect_args(chars: &[char], open: usize) -> Option<(Vec<String>, usize)> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut depth = 1;
+    let mut i = open + 1;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' | '\'' => {
+                let end = skip_literal(chars, i);
+                current.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    args.push(current);
+                    return Some((args, i + 1));
+                }
+            }
+            ',' if depth == 1 => {
+                args.push(std::mem::take(&mut current));
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+        i += 1;
+    }
+    None
+}
+
+/// Split a macro body into identifiers, numbers, literals, `#`, `##`, whitespace runs and
+/// single characters.
+fn body_tokens(body: &str) -> Vec<String> {
+    let chars: Vec<char> = body.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        if c == '"' || c == '\'' {
+            i = skip_literal(&chars, i);
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+        } else if c.is_whitespace() {
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+        } else if c == '#' && chars.get(i + 1) == Some(&'#') {
+            i += 2;
+        } else {
+            i += 1;
+        }
+        tokens.push(chars[start..i].iter().collect());
+    }
+    tokens
+}
+
+/// `text` as a C string literal.
+fn quote(text: &str) -> String {
+    let mut s = String::from("\"");
+    for c in text.chars() {
+        if c == '"' || c == '\\' {
+            s.push('\\');
+        }
+        s.push(c);
+    }
+    s.push('"');
+    s
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The single identifier a directive like `#ifdef` or `#undef` expects.
+fn macro_name(s: &str) -> Result<&str, String> {
+    let s = s.trim();
+    if is_identifier(s) {
+        Ok(s)
+    } else if s.is_empty() {
+        Err("macro name missing".to_string())
+    } else {
+        Err(format!("`{}` is not a valid macro name", s))
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CondToken {
+    Num(i64),
+    Op(&'static str),
+}
+
+/// Tokenize a macro-expanded `#if` expression. Identifiers left over after expansion are 0.
+fn cond_tokens(expr: &str) -> Result<Vec<CondToken>, String> {
+    const OPS: &[&str] = &[
+        "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "+", "-", "*", "/", "%", "<", ">", "&",
+        "|", "^", "!", "~", "(", ")", "?", ":",
+    ];
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            if c.is_ascii_digit() {
+                tokens.push(CondToken::Num(parse_int(&word)?));
+            } else {
+                tokens.push(CondToken::Num(0));
+            }
+        } else if c == '\'' {
+            let end = skip_literal(&chars, i);
+            let inner: String = chars[i + 1..end.saturating_sub(1).max(i + 1)].iter().collect();
+            let value = match inner.as_str() {
+                "\\n" => '\n' as i64,
+                "\\t" => '\t' as i64,
+                "\\0" => 0,
+                "\\\\" => '\\' as i64,
+                "\\'" => '\'' as i64,
+                s if s.chars().count() == 1 => s.chars().next().unwrap() as i64,
+                _ => return Err(format!("unsupported character constant '{}' in #if", inner)),
+            };
+            tokens.push(CondToken::Num(value));
+            i = end;
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let op = OPS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| format!("unexpected `{}` in #if expression", c))?;
+            tokens.push(CondToken::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parse an integer literal in `#if`: decimal, `0x` hex, `0b` binary or leading-`0` octal,
+/// with optional `u`/`l` suffixes.
+fn parse_int(word: &str) -> Result<i64, String> {
+    let digits = word.trim_end_matches(['u', 'U', 'l', 'L']);
+    let lower = digits.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2)
+    } else if lower.len() > 1 && lower.starts_with('0') {
+        i64::from_str_radix(&lower[1..], 8)
+    } else {
+        lower.parse()
+    };
+    parsed.map_err(|_| format!("invalid integer `{}` in #if", word))
+}
+
+/// Precedence-climbing evaluator for `#if` expressions.
+struct CondParser {
+    tokens: Vec<CondToken>,
+    pos: usize,
+}
+
+impl CondParser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(CondToken::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected `{}` in #if expression", op))
+        }
+    }
+
+    fn ternary(&mut self) -> Result<i64, String> {
+        let cond = self.binary(1)?;
+        if self.peek_op() != Some("?") {
+            return Ok(cond);
+        }
+        self.pos += 1;
+        let then = self.ternary()?;
+        self.expect(":")?;
+        let otherwise = self.ternary()?;
+        Ok(if cond != 0 { then } else { otherwise })
+    }
+
+    fn binary(&mut self, min_prec: u8) -> Result<i64, String> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.peek_op() {
+            let prec = match op {
+                "||" => 1,
+                "&&" => 2,
+                "|" => 3,
+                "^" => 4,
+                "&" => 5,
+                "==" | "!=" => 6,
+                "<" | ">" | "<=" | ">=" => 7,
+                "<<" | ">>" => 8,
+                "+" | "-" => 9,
+                "*" | "/" | "%" => 10,
+                _ => break,
+            };
+            if prec < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(prec + 1)?;
+            lhs = match op {
+                "||" => (lhs != 0 || rhs != 0) as i64,
+                "&&" => (lhs != 0 && rhs != 0) as i64,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "<" => (lhs < rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "<<" => lhs.wrapping_shl(rhs as u32),
+                ">>" => lhs.wrapping_shr(rhs as u32),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                _ if rhs == 0 => return Err("division by zero in #if".to_string()),
+                "/" => lhs.wrapping_div(rhs),
+                _ => lhs.wrapping_rem(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(CondToken::Num(n)) => Ok(n),
+            Some(CondToken::Op("!")) => Ok((self.unary()? == 0) as i64),
+            Some(CondToken::Op("~")) => Ok(!self.unary()?),
+            Some(CondToken::Op("-")) => Ok(self.unary()?.wrapping_neg()),
+            Some(CondToken::Op("+")) => self.unary(),
+            Some(CondToken::Op("(")) => {
+                let value = self.ternary()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            _ => Err("malformed #if expression".to_string()),
+        }
+    }
+}
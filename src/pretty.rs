@@ -0,0 +1,558 @@
+//! Pretty-printing of the syntax tree, to see how the compiler understood a program.
+//!
+//! Two forms are available:
+//! - `to_c` prints the tree back as indented C. Parentheses are added only where precedence
+//!   requires them, so parsing the output again gives the same tree (spans aside).
+//! - `to_json` prints every node as a JSON object with a `"node"` tag, its fields and its
+//!   source location, for tools.
+//!
+//! `dump_ast` runs the preprocessed source through the parser and prints it in either form;
+//! it backs the `--dump-ast` command-line option.
+
+use crate::ast::{
+    BinaryOp, Decl, Expr, ExprKind, Function, Stmt, StmtKind, TranslationUnit, Type, UnaryOp,
+};
+use crate::diagnostic::Diagnostic;
+use crate::parser::Parser;
+use crate::token::Span;
+use std::fmt::Write;
+
+/// How `dump_ast` prints the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AstFormat {
+    Text,
+    Json,
+}
+
+/// Parse `source` and print its syntax tree, or return the syntax errors.
+pub fn dump_ast(source: &str, format: AstFormat) -> Result<String, Vec<Diagnostic>> {
+    let unit = Parser::new(source).parse_ast()?;
+    Ok(match format {
+        AstFormat::Text => to_c(&unit),
+        AstFormat::Json => to_json(&unit),
+    })
+}
+
+// —— C ——
+
+const INDENT: &str = "    ";
+
+// Binding powers, matching the parser's: a child printed where the parser would stop before
+// it (a lower power than its position allows) gets parentheses.
+const ASSIGN: u8 = 1;
+const COND: u8 = 2;
+const PREFIX: u8 = 13; // prefix operators and casts
+const POSTFIX: u8 = 14; // postfix operators, subscripts and calls
+const PRIMARY: u8 = 15;
+
+/// Print `unit` as C source.
+pub fn to_c(unit: &TranslationUnit) -> String {
+    let mut out = String::new();
+    for decl in &unit.decls {
+        match decl {
+            Decl::Enum(e) => {
+                out.push_str("enum ");
+                if let Some(name) = &e.name {
+                    out.push_str(name);
+                    out.push(' ');
+                }
+                let members: Vec<String> =
+                    e.members.iter().map(|(name, value)| format!("{} = {}", name, value)).collect();
+                let _ = writeln!(out, "{{ {} }};", members.join(", "));
+            }
+            Decl::Global(global) => {
+                let _ = writeln!(out, "{};", declarator(&global.ty, &global.name));
+            }
+            Decl::Function(function) => c_function(&mut out, function),
+            Decl::Stmt(stmt) => c_stmt(&mut out, stmt, 0),
+        }
+    }
+    out
+}
+
+fn c_function(out: &mut String, function: &Function) {
+    let params: Vec<String> = function.params.iter().map(|(name, ty)| declarator(ty, name)).collect();
+    let _ = writeln!(out, "{}({}) {{", declarator(&function.return_type, &function.name), params.join(", "));
+    for stmt in &function.body {
+        c_stmt(out, stmt, 1);
+    }
+    out.push_str("}\n");
+}
+
+/// `int *name`: the base type, then the name behind one `*` per pointer level.
+fn declarator(ty: &Type, name: &str) -> String {
+    let (base, stars) = split_type(ty);
+    format!("{} {}{}", base, "*".repeat(stars), name)
+}
+
+/// The base type's keyword and the number of pointer levels on top of it.
+fn split_type(ty: &Type) -> (&'static str, usize) {
+    match ty {
+        Type::Char => ("char", 0),
+        Type::Int => ("int", 0),
+        Type::Float => ("float", 0),
+        Type::Void => ("void", 0),
+        Type::Ptr(inner) => {
+            let (base, stars) = split_type(inner);
+            (base, stars + 1)
+        }
+    }
+}
+
+/// A type as written in a cast or `sizeof`: `char *`.
+pub(crate) fn type_name(ty: &Type) -> String {
+    match split_type(ty) {
+        (base, 0) => base.to_string(),
+        (base, stars) => format!("{} {}", base, "*".repeat(stars)),
+    }
+}
+
+fn c_stmt(out: &mut String, stmt: &Stmt, depth: usize) {
+    out.push_str(&INDENT.repeat(depth));
+    c_stmt_body(out, stmt, depth);
+}
+
+/// Print `stmt` from the current position on, which is already indented to `depth`.
+fn c_stmt_body(out: &mut String, stmt: &Stmt, depth: usize) {
+    match &stmt.kind {
+        StmtKind::Locals(vars) => {
+            let (base, _) = split_type(&vars[0].1);
+            let names: Vec<String> =
+                vars.iter().map(|(name, ty)| format!("{}{}", "*".repeat(split_type(ty).1), name)).collect();
+            let _ = writeln!(out, "{} {};", base, names.join(", "));
+        }
+        StmtKind::Expr(expr) => {
+            let _ = writeln!(out, "{};", c_expr(expr));
+        }
+        StmtKind::If { cond, then, otherwise } => {
+            let _ = write!(out, "if ({})", c_expr(cond));
+            c_nested(out, then, depth);
+            if let Some(otherwise) = otherwise {
+                if matches!(then.kind, StmtKind::Block(_)) {
+                    out.pop(); // `} else` on one line
+                    out.push(' ');
+                } else {
+                    out.push_str(&INDENT.repeat(depth));
+                }
+                out.push_str("else");
+                if matches!(otherwise.kind, StmtKind::If { .. }) {
+                    out.push(' ');
+                    c_stmt_body(out, otherwise, depth); // `else if` stays on one line
+                } else {
+                    c_nested(out, otherwise, depth);
+                }
+            }
+        }
+        StmtKind::While { cond, body } => {
+            let _ = write!(out, "while ({})", c_expr(cond));
+            c_nested(out, body, depth);
+        }
+        StmtKind::Return(None) => out.push_str("return;\n"),
+        StmtKind::Return(Some(value)) => {
+            let _ = writeln!(out, "return {};", c_expr(value));
+        }
+        StmtKind::Block(stmts) => {
+            out.push_str("{\n");
+            for stmt in stmts {
+                c_stmt(out, stmt, depth + 1);
+            }
+            out.push_str(&INDENT.repeat(depth));
+            out.push_str("}\n");
+        }
+        StmtKind::Empty => out.push_str(";\n"),
+    }
+}
+
+/// The body of an `if`, `else` or `while`: a block opens on the same line, anything else
+/// goes on its own line, indented one level further.
+fn c_nested(out: &mut String, body: &Stmt, depth: usize) {
+    if matches!(body.kind, StmtKind::Block(_)) {
+        out.push(' ');
+        c_stmt_body(out, body, depth);
+    } else {
+        out.push('\n');
+        c_stmt(out, body, depth + 1);
+    }
+}
+
+fn binary_power(op: BinaryOp) -> u8 {
+    match op {
+        BinaryOp::LogOr => 3,
+        BinaryOp::LogAnd => 4,
+        BinaryOp::BitOr => 5,
+        BinaryOp::BitXor => 6,
+        BinaryOp::BitAnd => 7,
+        BinaryOp::Eq | BinaryOp::Ne => 8,
+        BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge => 9,
+        BinaryOp::Shl | BinaryOp::Shr => 10,
+        BinaryOp::Add | BinaryOp::Sub => 11,
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 12,
+    }
+}
+
+fn binary_symbol(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Mod => "%",
+        BinaryOp::Eq => "==",
+        BinaryOp::Ne => "!=",
+        BinaryOp::Lt => "<",
+        BinaryOp::Gt => ">",
+        BinaryOp::Le => "<=",
+        BinaryOp::Ge => ">=",
+        BinaryOp::BitAnd => "&",
+        BinaryOp::BitOr => "|",
+        BinaryOp::BitXor => "^",
+        BinaryOp::Shl => "<<",
+        BinaryOp::Shr => ">>",
+        BinaryOp::LogAnd => "&&",
+        BinaryOp::LogOr => "||",
+    }
+}
+
+fn unary_symbol(op: UnaryOp) -> &'static str {
+    match op {
+        UnaryOp::Neg => "-",
+        UnaryOp::Plus => "+",
+        UnaryOp::Not => "!",
+        UnaryOp::BitNot => "~",
+        UnaryOp::Deref => "*",
+        UnaryOp::AddrOf => "&",
+        UnaryOp::PreInc | UnaryOp::PostInc => "++",
+        UnaryOp::PreDec | UnaryOp::PostDec => "--",
+    }
+}
+
+/// How tightly `expr` binds when printed without parentheses.
+fn power(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Assign { .. } => ASSIGN,
+        ExprKind::Cond { .. } => COND,
+        ExprKind::Binary { op, .. } => binary_power(*op),
+        ExprKind::Unary { op: UnaryOp::PostInc | UnaryOp::PostDec, .. } => POSTFIX,
+        ExprKind::Unary { .. } | ExprKind::Cast { .. } => PREFIX,
+        ExprKind::Index { .. } | ExprKind::Call { .. } => POSTFIX,
+        // a negative number can only be written as a negation, which is a prefix expression
+        ExprKind::Float(f) if f.is_sign_negative() => PREFIX,
+        _ => PRIMARY,
+    }
+}
+
+/// Print `expr`, parenthesised if it binds less tightly than `min` requires.
+fn c_operand(expr: &Expr, min: u8) -> String {
+    if power(expr) < min {
+        format!("({})", c_expr(expr))
+    } else {
+        c_expr(expr)
+    }
+}
+
+/// Print an expression as C.
+fn c_expr(expr: &Expr) -> String {
+    match &expr.kind {
+        // a literal is never negative; values that wrapped (like `0xffffffffffffffff`) are
+        // written back in hex
+        ExprKind::Int(n) if *n < 0 => format!("{:#x}", *n as u64),
+        ExprKind::Int(n) => n.to_string(),
+        ExprKind::Float(f) => format!("{:?}", f),
+        ExprKind::Char(c) => format!("'{}'", escape(&[*c as u8], b'\'')),
+        ExprKind::Str(s) => format!("\"{}\"", escape(s, b'"')),
+        ExprKind::Var(name) => name.clone(),
+        ExprKind::Call { name, args } => {
+            let args: Vec<String> = args.iter().map(c_expr).collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        ExprKind::Unary { op: op @ (UnaryOp::PostInc | UnaryOp::PostDec), operand } => {
+            format!("{}{}", c_operand(operand, POSTFIX), unary_symbol(*op))
+        }
+        ExprKind::Unary { op, operand } => {
+            let symbol = unary_symbol(*op);
+            let operand = c_operand(operand, PREFIX);
+            // keep `- -x` and `& &x` from lexing as `--x` and `&&x`
+            let joins = symbol.ends_with(['-', '+', '&']) && operand.starts_with(&symbol[symbol.len() - 1..]);
+            format!("{}{}{}", symbol, if joins { " " } else { "" }, operand)
+        }
+        ExprKind::Binary { op, lhs, rhs } => {
+            let power = binary_power(*op);
+            format!("{} {} {}", c_operand(lhs, power), binary_symbol(*op), c_operand(rhs, power + 1))
+        }
+        ExprKind::Assign { target, value } => {
+            format!("{} = {}", c_operand(target, COND), c_operand(value, ASSIGN))
+        }
+        ExprKind::Cond { cond, then, otherwise } => format!(
+            "{} ? {} : {}",
+            c_operand(cond, COND + 1),
+            c_operand(then, ASSIGN),
+            c_operand(otherwise, COND)
+        ),
+        ExprKind::Index { base, index } => format!("{}[{}]", c_operand(base, POSTFIX), c_expr(index)),
+        ExprKind::Cast { ty, expr } => format!("({}){}", type_name(ty), c_operand(expr, PREFIX)),
+        ExprKind::Sizeof(ty) => format!("sizeof({})", type_name(ty)),
+    }
+}
+
+/// The body of a C string or character literal holding `bytes`. Anything that isn't
+/// printable ASCII becomes an escape; octal is used as it can't run into a following digit
+/// the way `\x` does.
+fn escape(bytes: &[u8], quote: u8) -> String {
+    let mut out = String::new();
+    for &b in bytes {
+        match b {
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\r' => out.push_str("\\r"),
+            b'\\' => out.push_str("\\\\"),
+            _ if b == quote => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            b' '..=b'~' => out.push(b as char),
+            _ => {
+                let _ = write!(out, "\\{:03o}", b);
+            }
+        }
+    }
+    out
+}
+
+// —— JSON ——
+
+/// Print `unit` as JSON, indented two spaces per level.
+pub fn to_json(unit: &TranslationUnit) -> String {
+    let decls = unit.decls.iter().map(json_decl).collect();
+    let mut out = String::new();
+    Json::object("TranslationUnit", None, vec![("decls", Json::Array(decls))]).write(&mut out, 0);
+    out.push('\n');
+    out
+}
+
+/// A JSON value, built up from the tree and then written out in one go.
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    /// A node: its `"node"` tag, its location (if it has one) and then its fields.
+    fn object(node: &str, span: Option<Span>, fields: Vec<(&'static str, Json)>) -> Json {
+        let mut all = vec![("node", Json::string(node))];
+        if let Some(span) = span {
+            all.push(("span", json_span(span)));
+        }
+        all.extend(fields);
+        Json::Object(all)
+    }
+
+    fn string(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+
+    fn number(n: impl ToString) -> Json {
+        Json::Number(n.to_string())
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Json::Array(_) | Json::Object(_))
+    }
+
+    fn write(&self, out: &mut String, depth: usize) {
+        let pad = |out: &mut String, depth: usize| out.push_str(&"  ".repeat(depth));
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Json::Number(n) => out.push_str(n),
+            Json::String(s) => json_string(out, s),
+            Json::Array(items) if items.is_empty() => out.push_str("[]"),
+            // objects of plain values (spans, enum members) go on one line
+            Json::Object(fields) if fields.iter().all(|(_, value)| value.is_scalar()) => {
+                out.push_str("{ ");
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    json_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, depth);
+                }
+                out.push_str(" }");
+            }
+            Json::Array(items) => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    pad(out, depth + 1);
+                    item.write(out, depth + 1);
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                pad(out, depth);
+                out.push(']');
+            }
+            Json::Object(fields) => {
+                out.push_str("{\n");
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    pad(out, depth + 1);
+                    json_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, depth + 1);
+                    out.push_str(if i + 1 < fields.len() { ",\n" } else { "\n" });
+                }
+                pad(out, depth);
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn json_span(span: Span) -> Json {
+    Json::Object(vec![
+        ("line", Json::number(span.line)),
+        ("column", Json::number(span.column)),
+        ("start", Json::number(span.start)),
+        ("end", Json::number(span.end)),
+    ])
+}
+
+fn json_type(ty: &Type) -> Json {
+    Json::string(&type_name(ty))
+}
+
+fn json_vars(vars: &[(String, Type)]) -> Json {
+    let vars = vars
+        .iter()
+        .map(|(name, ty)| Json::Object(vec![("name", Json::string(name)), ("type", json_type(ty))]))
+        .collect();
+    Json::Array(vars)
+}
+
+fn json_decl(decl: &Decl) -> Json {
+    match decl {
+        Decl::Enum(e) => {
+            let members = e
+                .members
+                .iter()
+                .map(|(name, value)| {
+                    Json::Object(vec![("name", Json::string(name)), ("value", Json::number(value))])
+                })
+                .collect();
+            let name = e.name.as_deref().map_or(Json::Null, Json::string);
+            Json::object("Enum", Some(e.span), vec![("name", name), ("members", Json::Array(members))])
+        }
+        Decl::Global(global) => Json::object(
+            "Global",
+            Some(global.span),
+            vec![("name", Json::string(&global.name)), ("type", json_type(&global.ty))],
+        ),
+        Decl::Function(function) => Json::object(
+            "Function",
+            Some(function.span),
+            vec![
+                ("name", Json::string(&function.name)),
+                ("return_type", json_type(&function.return_type)),
+                ("params", json_vars(&function.params)),
+                ("body", Json::Array(function.body.iter().map(json_stmt).collect())),
+            ],
+        ),
+        Decl::Stmt(stmt) => json_stmt(stmt),
+    }
+}
+
+fn json_stmt(stmt: &Stmt) -> Json {
+    let (node, fields) = match &stmt.kind {
+        StmtKind::Locals(vars) => ("Locals", vec![("vars", json_vars(vars))]),
+        StmtKind::Expr(expr) => ("ExprStmt", vec![("expr", json_expr(expr))]),
+        StmtKind::If { cond, then, otherwise } => (
+            "If",
+            vec![
+                ("cond", json_expr(cond)),
+                ("then", json_stmt(then)),
+                ("else", otherwise.as_deref().map_or(Json::Null, json_stmt)),
+            ],
+        ),
+        StmtKind::While { cond, body } => {
+            ("While", vec![("cond", json_expr(cond)), ("body", json_stmt(body))])
+        }
+        StmtKind::Return(value) => {
+            ("Return", vec![("value", value.as_ref().map_or(Json::Null, json_expr))])
+        }
+        StmtKind::Block(stmts) => {
+            ("Block", vec![("stmts", Json::Array(stmts.iter().map(json_stmt).collect()))])
+        }
+        StmtKind::Empty => ("Empty", vec![]),
+    };
+    Json::object(node, Some(stmt.span), fields)
+}
+
+fn json_expr(expr: &Expr) -> Json {
+    let (node, fields) = match &expr.kind {
+        ExprKind::Int(n) => ("Int", vec![("value", Json::number(n))]),
+        // JSON has no infinities or NaN, so those are spelled out as strings
+        ExprKind::Float(f) if !f.is_finite() => ("Float", vec![("value", Json::string(&f.to_string()))]),
+        ExprKind::Float(f) => ("Float", vec![("value", Json::number(format!("{:?}", f)))]),
+        ExprKind::Char(c) => ("Char", vec![("value", Json::number(*c as u8 as i8))]),
+        ExprKind::Str(s) => ("Str", vec![("value", Json::String(String::from_utf8_lossy(s).into_owned()))]),
+        ExprKind::Var(name) => ("Var", vec![("name", Json::string(name))]),
+        ExprKind::Call { name, args } => (
+            "Call",
+            vec![("name", Json::string(name)), ("args", Json::Array(args.iter().map(json_expr).collect()))],
+        ),
+        ExprKind::Unary { op, operand } => (
+            "Unary",
+            vec![
+                ("op", Json::string(unary_symbol(*op))),
+                ("postfix", Json::Bool(matches!(op, UnaryOp::PostInc | UnaryOp::PostDec))),
+                ("operand", json_expr(operand)),
+            ],
+        ),
+        ExprKind::Binary { op, lhs, rhs } => (
+            "Binary",
+            vec![("op", Json::string(binary_symbol(*op))), ("lhs", json_expr(lhs)), ("rhs", json_expr(rhs))],
+        ),
+        ExprKind::Assign { target, value } => {
+            ("Assign", vec![("target", json_expr(target)), ("value", json_expr(value))])
+        }
+        ExprKind::Cond { cond, then, otherwise } => (
+            "Cond",
+            vec![("cond", json_expr(cond)), ("then", json_expr(then)), ("else", json_expr(otherwise))],
+        ),
+        ExprKind::Index { base, index } => ("Index", vec![("base", json_expr(base)), ("index", json_expr(index))]),
+        ExprKind::Cast { ty, expr } => ("Cast", vec![("type", json_type(ty)), ("expr", json_expr(expr))]),
+        ExprKind::Sizeof(ty) => ("Sizeof", vec![("type", json_type(ty))]),
+    };
+    Json::object(node, Some(expr.span), fields)
+}
+
+impl std::fmt::Display for TranslationUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&to_c(self))
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&c_expr(self))
+    }
+}
@@ -0,0 +1,58 @@
+//! A compiled program: everything the VM needs to run it, plus what tools need to describe it.
+//!
+//! The `Parser` produces one with `Parser::program` once the source has compiled. Besides the
+//! instructions and the data segment it keeps the source span of every instruction and the
+//! names behind addresses (functions, globals, string literals), which the disassembler uses.
+
+use crate::instruction::Instruction;
+use crate::token::Span;
+use crate::verify::{verify, VerifyError};
+use crate::vm::{Value, VM};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub code: Vec<Instruction>,
+    pub spans: Vec<Span>,                  // source of each instruction in `code`
+    pub functions: HashMap<String, usize>, // name → entry address
+    pub function_arity: HashMap<String, usize>,
+    pub main_label: Option<usize>,
+    pub data: Vec<u8>,                   // loaded at `DATA_BASE`
+    pub globals: HashMap<String, usize>, // name → VM address
+    pub strings: Vec<usize>,             // VM addresses of the string literals
+    pub locals: usize,                   // frame slots pushed before running top-level code
+}
+
+impl Program {
+    /// The name of the function starting at `addr`, if one does.
+    pub fn function_at(&self, addr: usize) -> Option<&str> {
+        self.functions.iter().find(|&(_, &entry)| entry == addr).map(|(name, _)| name.as_str())
+    }
+
+    /// Where execution starts: `main` if there is one, otherwise the first instruction.
+    pub fn entry(&self) -> usize {
+        self.main_label.unwrap_or(0)
+    }
+
+    /// The arity of each function by entry address, as `VM::new` and `verify` take it.
+    pub fn arity_by_entry(&self) -> HashMap<usize, usize> {
+        self.functions
+            .iter()
+            .map(|(name, &addr)| (addr, self.function_arity.get(name).copied().unwrap_or(0)))
+            .collect()
+    }
+
+    /// Check the program is well formed before running it from `entry` (see `verify`).
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        verify(&self.code, &self.arity_by_entry(), self.entry(), self.locals)
+    }
+
+    /// A VM ready to run this program from `entry`: arities registered, data loaded and the
+    /// top-level frame slots pushed.
+    pub fn vm(&self) -> VM {
+        let mut vm = VM::new(self.arity_by_entry());
+        vm.load_data(&self.data);
+        vm.stack.extend(std::iter::repeat_n(Value::Int(0), self.locals));
+        vm
+    }
+}
@@ -15,9 +15,11 @@ pub enum TokenKind {
     While,
     Return,
     Int,
+    Long,
     Char,
     Sizeof,
     Enum,
+    Void,
     Float(f64), 
 
     // Operators and punctuation
@@ -37,6 +39,7 @@ pub enum TokenKind {
     Or,      // |
     Xor,     // ^
     Not,     // !
+    Tilde,   // ~
     Inc,     // ++
     Dec,     // --
     Cond,    // ?
@@ -57,14 +60,25 @@ pub enum TokenKind {
     Colon,
 
     // Special
-    String(String),
-    CharLiteral(char),
+    String(Vec<u8>), // bytes of the literal, escapes resolved
+    CharLiteral(char), // one byte (U+0000 to U+00FF); a multi-character constant lexes as `Num`
     Eof,
 }
 
-/// Struct representing a token with line and position info.
+/// A range of source text: byte offsets `start..end` plus the 1-based line and column
+/// where it begins. `file_id` tells sources apart when several are compiled together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub file_id: usize,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Struct representing a token with its span in the source.
 #[derive(Debug, Clone)]
 pub struct Token {
     pub kind: TokenKind,
-    pub line: usize,
+    pub span: Span,
 }
\ No newline at end of file
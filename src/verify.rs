@@ -0,0 +1,196 @@
+//! A bytecode verifier that rejects malformed programs before the VM runs them.
+//!
+//! Programs don't only come from the compiler: they can be assembled by hand (`asm`) or
+//! loaded from a `.c4b` file (`binary`), and the VM trusts its input, panicking or reading
+//! the wrong stack slot when an instruction doesn't fit. `verify` walks every path from the
+//! start and from each function entry, tracking the current frame size and how many values
+//! sit on the stack above it, and checks that:
+//!
+//! - every jump goes to a label that exists (and no label is defined twice);
+//! - every `Call` and `TailCall` targets a function entry whose arity is registered;
+//! - every `LoadLocal`/`StoreLocal`/`Addr`/`AddLocal` slot is inside the frame set up by `Enter`;
+//! - no instruction pops more values than the path has pushed, on the stack or on the float
+//!   stack, and wherever paths meet (a label reached by a jump and by falling through, a loop
+//!   head) they agree on both depths and the frame size;
+//! - `Leave` and `TailCall` only run in a frame some `Call` set up, not in the code run from
+//!   the start (the top-level code, or the function `start_ip` enters), which has no caller
+//!   to return to.
+//!
+//! A program is verified once, where it is compiled or loaded (`Program::verify`); the VM
+//! trusts the code it runs and does not check it again.
+
+use crate::disasm::mnemonic;
+use crate::instruction::Instruction;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Why a program was rejected: the offending instruction's index and what is wrong with it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    pub index: usize,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "instruction {}: {}", self.index, self.message)
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// What is known about the VM's stack when an instruction is about to run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct State {
+    frame: usize,  // slots addressable by LoadLocal/StoreLocal/Addr
+    depth: usize,  // values pushed above the frame
+    floats: usize, // values on the float stack
+    called: bool,  // whether a `Call` set up the frame, so `Leave` has somewhere to return
+}
+
+/// Check `code` before running it from `start_ip`. `function_arity` maps each function's
+/// entry address to its number of arguments, as `VM::new` takes it, and `frame` is the
+/// number of slots the top-level code may address (`Program::locals`).
+pub fn verify(
+    code: &[Instruction],
+    function_arity: &HashMap<usize, usize>,
+    start_ip: usize,
+    frame: usize,
+) -> Result<(), VerifyError> {
+    let error = |index: usize, message: String| Err(VerifyError { index, message });
+
+    let mut labels = HashMap::new();
+    for (i, instr) in code.iter().enumerate() {
+        if let Instruction::Label(id) = instr
+            && labels.insert(*id, i).is_some()
+        {
+            return error(i, format!("label {} is defined twice", id));
+        }
+    }
+    if let Some((&entry, _)) = function_arity.iter().find(|&(&entry, _)| entry >= code.len()) {
+        return error(entry, "function entry is outside the code".to_string());
+    }
+
+    // The labels closing the code (the exit label `return` in `main` jumps to) lead
+    // nowhere, so paths may arrive there in any state.
+    let exit = code.len() - code.iter().rev().take_while(|i| matches!(i, Instruction::Label(_))).count();
+
+    let mut states: Vec<Option<State>> = vec![None; code.len()];
+    let mut pending: Vec<(usize, State)> = function_arity
+        .iter()
+        .map(|(&entry, &arity)| (entry, State { frame: arity, depth: 0, floats: 0, called: entry != start_ip }))
+        .collect();
+    if !function_arity.contains_key(&start_ip) {
+        pending.push((start_ip, State { frame, depth: 0, floats: 0, called: false }));
+    }
+
+    while let Some((ip, state)) = pending.pop() {
+        if ip >= exit {
+            continue;
+        }
+        match states[ip] {
+            Some(seen) if seen == state => continue,
+            Some(seen) if seen.frame != state.frame => {
+                return error(
+                    ip,
+                    format!("reached with a frame of {} slots on one path and {} on another", seen.frame, state.frame),
+                );
+            }
+            Some(seen) if seen.depth != state.depth => {
+                return error(
+                    ip,
+                    format!("stack depth is {} on one path and {} on another", seen.depth, state.depth),
+                );
+            }
+            Some(seen) if seen.floats != state.floats => {
+                return error(
+                    ip,
+                    format!("float stack depth is {} on one path and {} on another", seen.floats, state.floats),
+                );
+            }
+            Some(_) => return error(ip, "reached both from a call and from the start".to_string()),
+            None => states[ip] = Some(state),
+        }
+
+        let instr = &code[ip];
+        let (pops, pushes) = match stack_effect(instr, function_arity) {
+            Ok(effect) => effect,
+            Err(message) => return error(ip, message),
+        };
+        if pops > state.depth {
+            return error(
+                ip,
+                format!("`{:?}` needs {} value(s) but the stack holds {}", instr, pops, state.depth),
+            );
+        }
+        let mut next = State { depth: state.depth - pops + pushes, ..state };
+
+        let target = |id: &usize| match labels.get(id) {
+            Some(&at) => Ok(at),
+            None => Err(VerifyError { index: ip, message: format!("jump to missing label {}", id) }),
+        };
+        match instr {
+            Instruction::LoadLocal(slot)
+            | Instruction::StoreLocal(slot)
+            | Instruction::Addr(slot)
+            | Instruction::AddLocal(slot, _)
+                if *slot >= state.frame =>
+            {
+                return error(
+                    ip,
+                    format!("`{:?}` is outside the frame of {} slot(s)", instr, state.frame),
+                );
+            }
+            Instruction::Enter(slots) => {
+                if state.depth != 0 {
+                    return error(ip, format!("`Enter` with {} value(s) already on the stack", state.depth));
+                }
+                if *slots < state.frame {
+                    return error(ip, format!("frame of {} slot(s) has no room for {} argument(s)", slots, state.frame));
+                }
+                next.frame = *slots;
+            }
+            Instruction::Jmp(id) => {
+                pending.push((target(id)?, next));
+                continue;
+            }
+            Instruction::Jz(id) | Instruction::Jnz(id) | Instruction::CmpJz(_, id) => {
+                pending.push((target(id)?, next))
+            }
+            Instruction::PushF(_) => next.floats += 1,
+            Instruction::PrintF if state.floats == 0 => {
+                return error(ip, "`PrintF` needs a value but the float stack is empty".to_string());
+            }
+            Instruction::PrintF => next.floats -= 1,
+            Instruction::Leave | Instruction::TailCall(_) if !state.called => {
+                return error(ip, format!("`{}` outside a called function has no caller to return to", mnemonic(instr)));
+            }
+            Instruction::Leave | Instruction::Exit | Instruction::TailCall(_) => continue,
+            _ => {}
+        }
+        pending.push((ip + 1, next));
+    }
+    Ok(())
+}
+
+/// How many values `instr` pops off the stack and how many it pushes back.
+fn stack_effect(instr: &Instruction, function_arity: &HashMap<usize, usize>) -> Result<(usize, usize), String> {
+    use Instruction::*;
+    Ok(match instr {
+        Imm(_) | ImmF(_) | Addr(_) | LoadLocal(_) | Getchar => (0, 1),
+        PushF(_) | PrintF | PrintStr(_) | Cast | Label(_) | Jmp(_) | Enter(_) | AddLocal(..) => (0, 0),
+        Add | Sub | Mul | Div | Mod | Eq | Ne | Lt | Gt | Le | Ge | BitAnd | BitOr | BitXor | Shl | Shr
+        | Store | StoreChar => (2, 1),
+        Neg | Not | Deref | DerefChar | StoreLocal(_) | Putchar | Close | Malloc | Free => (1, 1),
+        Dup => (1, 2),
+        CmpJz(..) => (2, 0),
+        Pop | Print | Jz(_) | Jnz(_) | Exit | Leave => (1, 0),
+        Printf(argc) | Scanf(argc) | Open(argc) => (*argc, 1),
+        Read | Write | Memset | Memcmp => (3, 1),
+        Call(addr) | TailCall(addr) => match function_arity.get(addr) {
+            Some(&arity) if matches!(instr, Call(_)) => (arity, 1),
+            Some(&arity) => (arity, 0),
+            None => return Err(format!("`{}` does not target a known function entry", mnemonic(instr))),
+        },
+    })
+}
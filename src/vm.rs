@@ -1,24 +1,100 @@
-/// The `VM` struct implements the runtime virtual machine for executing bytecode instructions
-/// generated by the C4 Rust compiler. It maintains the operand stack, call stack, frame pointer,
-/// and function metadata necessary to support control flow, arithmetic operations, function calls,
-/// variable management, and floating-point computations. The `run` and `run_from` methods interpret
-/// the `Instruction` stream and manage execution state, while supporting both integer and floating-point
-/// values via the `Value` enum.
-
-use crate::instruction::Instruction;
+//! The `VM` struct implements the runtime virtual machine for executing bytecode instructions
+//! generated by the C4 Rust compiler. It maintains the operand stack, call stack, frame pointer,
+//! and function metadata necessary to support control flow, arithmetic operations, function calls,
+//! variable management, and floating-point computations. The `run` and `run_from` methods interpret
+//! the `Instruction` stream and manage execution state, while supporting both integer and floating-point
+//! values via the `Value` enum. Printing builtins write to a host-provided output handle and
+//! input builtins read from a host-provided input handle (stdout and stdin by default).
+
+use crate::format::{scanf, sprintf, Scanned};
+use crate::instruction::{Compare, Instruction};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// VM address at which the data segment (string literals and globals) starts. Addresses
+/// below it refer to stack slots.
+pub const DATA_BASE: usize = 0x1000_0000;
+
+/// Size in bytes of a word (`int` or pointer). Stack slot `n` lives at address `n * WORD`,
+/// so pointer arithmetic on the address of a local steps from slot to slot.
+pub const WORD: usize = 8;
+
+/// `open` flag bits understood by the VM (Linux values).
+pub const O_RDONLY: i64 = 0;
+pub const O_WRONLY: i64 = 1;
+pub const O_RDWR: i64 = 2;
+pub const O_CREAT: i64 = 0o100;
+pub const O_TRUNC: i64 = 0o1000;
+pub const O_APPEND: i64 = 0o2000;
+
+/// First descriptor handed out by `open`; 0, 1 and 2 are the standard streams.
+const FIRST_FILE_FD: i64 = 3;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Int(i64),
     Flt(f64),
 }
 
+/// How a program run ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// Execution reached the end of the program; carries the value left on top of the stack
+    /// (the value `main` returned).
+    Returned(Option<i64>),
+    /// The program called `exit(code)`, possibly from deep inside nested calls.
+    Halted(i64),
+}
+
+/// A cloneable in-memory writer. Hand one clone to `VM::set_output` and keep the other
+/// to inspect what the program printed.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far.
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+
+    /// Everything written so far, lossily decoded as UTF-8.
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 pub struct VM {
     pub stack: Vec<Value>,
     pub fp: usize, // frame pointer
     pub call_stack: Vec<usize>,
     pub function_arity: HashMap<usize, usize>, // or name → usize
     pub float_stack: Vec<f64>,
+    pub memory: Vec<u8>, // data segment, addressed from `DATA_BASE`
+    output: Box<dyn Write>,
+    input: Box<dyn BufRead>,
+    files: HashMap<i64, File>, // descriptors returned by `open`
+    next_fd: i64,
+    readable: Vec<PathBuf>, // host paths `open` may read (files or whole directories)
+    writable: Vec<PathBuf>, // host paths `open` may write
 }
 
 impl VM {
@@ -29,338 +105,244 @@ impl VM {
             fp: 0,
             call_stack: Vec::new(),
             function_arity, // ✅ now it's coming from the function argument
+            memory: Vec::new(),
+            output: Box::new(std::io::stdout()),
+            input: Box::new(BufReader::new(std::io::stdin())),
+            files: HashMap::new(),
+            next_fd: FIRST_FILE_FD,
+            readable: Vec::new(),
+            writable: Vec::new(),
         }
     }
 
-    pub fn run(&mut self, code: &[Instruction]) -> Option<i64> {
-        use Instruction::*;
-
-        // build label→index map
-        let mut labels = HashMap::new();
-        for (i, instr) in code.iter().enumerate() {
-            if let Label(id) = instr {
-                labels.insert(*id, i);
-            }
-        }
-
-        let mut ip = 0;
-        while ip < code.len() {
-            match &code[ip] {
-                PushF(f) => {
-                    self.float_stack.push(*f);
-                }
-                PrintF => {
-                    let f = self.float_stack.pop().unwrap();
-                    println!("{}", f);
-                }
-                Print => {
-                    let v = self.stack.pop().unwrap();
-                    match v {
-                        Value::Int(i) => println!("{}", i),
-                        Value::Flt(f) => println!("{}", f),
-                    }
-                }
-                PrintStr(s) => {
-                    println!("{}", s);
-                }
-                Neg => {
-                    let v = self.stack.pop().unwrap();
-                    let result = match v {
-                        Value::Int(n) => Value::Int(-n),
-                        Value::Flt(f) => Value::Flt(-f),
-                    };
-                    self.stack.push(result);
-                }
-
-                Not => {
-                    let v = self.stack.pop().unwrap();
-                    let result = match v {
-                        Value::Int(n) => Value::Int((n == 0) as i64),
-                        Value::Flt(f) => Value::Int((f == 0.0) as i64),
-                    };
-                    self.stack.push(result);
-                }
-                Deref => {
-                    let addr = self.stack.pop().unwrap();
-                    let addr = match addr {
-                        Value::Int(i) => i as usize,
-                        _ => panic!("Cannot dereference non-integer address"),
-                    };
-                    let v = self.stack[addr].clone(); // clone to avoid borrow checker issues
-                    self.stack.push(v);
-                }
-                Addr(offset) => {
-                    let addr = (self.fp + *offset) as i64;
-                    self.stack.push(Value::Int(addr));
-                }
-                Cast => { // added: no-op for C-style cast
-                    // nothing to do at runtime
-                }
-                Imm(n) => {
-                    self.stack.push(Value::Int(*n));
-                }
-                ImmF(f) => {
-                    self.stack.push(Value::Flt(*f));
-                }
-
-                Add => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    let res = match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => Value::Int(x + y),
-                        (Value::Flt(x), Value::Flt(y)) => Value::Flt(x + y),
-                        (Value::Int(x), Value::Flt(y)) => Value::Flt(x as f64 + y),
-                        (Value::Flt(x), Value::Int(y)) => Value::Flt(x + y as f64),
-                    };
-                    self.stack.push(res);
-                }
-                Sub => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => Value::Int(x - y),
-                        (Value::Flt(x), Value::Flt(y)) => Value::Flt(x - y),
-                        (Value::Int(x), Value::Flt(y)) => Value::Flt(x as f64 - y),
-                        (Value::Flt(x), Value::Int(y)) => Value::Flt(x - y as f64),
-                    });
-                }
-                Mul => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => Value::Int(x * y),
-                        (Value::Flt(x), Value::Flt(y)) => Value::Flt(x * y),
-                        (Value::Int(x), Value::Flt(y)) => Value::Flt(x as f64 * y),
-                        (Value::Flt(x), Value::Int(y)) => Value::Flt(x * y as f64),
-                    });
-                }
-                Div => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => Value::Int(x / y),
-                        (Value::Flt(x), Value::Flt(y)) => Value::Flt(x / y),
-                        (Value::Int(x), Value::Flt(y)) => Value::Flt(x as f64 / y),
-                        (Value::Flt(x), Value::Int(y)) => Value::Flt(x / y as f64),
-                    });
-                }
-                Mod => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => Value::Int(x % y),
-                        _ => panic!("Modulo is not defined for floating-point numbers"),
-                    });
-                }
-
-                Eq => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int((a == b) as i64));
-                }
-                Ne => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int((a != b) as i64));
-                }
-                Lt => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => (x < y) as i64,
-                        (Value::Flt(x), Value::Flt(y)) => (x < y) as i64,
-                        (Value::Int(x), Value::Flt(y)) => ((x as f64) < y) as i64,
-                        (Value::Flt(x), Value::Int(y)) => (x < (y as f64)) as i64,
-                    }));
-                }
-                Gt => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => (x > y) as i64,
-                        (Value::Flt(x), Value::Flt(y)) => (x > y) as i64,
-                        (Value::Int(x), Value::Flt(y)) => ((x as f64) > y) as i64,
-                        (Value::Flt(x), Value::Int(y)) => (x > (y as f64)) as i64,
-                    }));
-                }
-                Le => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => (x <= y) as i64,
-                        (Value::Flt(x), Value::Flt(y)) => (x <= y) as i64,
-                        (Value::Int(x), Value::Flt(y)) => ((x as f64) <= y) as i64,
-                        (Value::Flt(x), Value::Int(y)) => (x <= (y as f64)) as i64,
-                    }));
-                }
-                Ge => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => (x >= y) as i64,
-                        (Value::Flt(x), Value::Flt(y)) => (x >= y) as i64,
-                        (Value::Int(x), Value::Flt(y)) => ((x as f64) >= y) as i64,
-                        (Value::Flt(x), Value::Int(y)) => (x >= (y as f64)) as i64,
-                    }));
-                }
-
-                BitAnd => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => self.stack.push(Value::Int(x & y)),
-                        _ => panic!("Bitwise AND only supports integers"),
-                    }
-                }
-
-                BitOr => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => self.stack.push(Value::Int(x | y)),
-                        _ => panic!("Bitwise OR only supports integers"),
-                    }
-                }
-
-                BitXor => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => self.stack.push(Value::Int(x ^ y)),
-                        _ => panic!("Bitwise XOR only supports integers"),
-                    }
-                }
+    /// Let the program `open` `path` for reading. A directory allows every file below it.
+    /// Nothing is readable unless allowed here.
+    pub fn allow_read(&mut self, path: impl AsRef<Path>) {
+        self.readable.push(resolve_path(path.as_ref()));
+    }
 
-                Shl => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => self.stack.push(Value::Int(x << y)),
-                        _ => panic!("Shift operations only support integers"),
-                    }
-                }
+    /// Let the program `open` `path` for writing (and creating, below a directory).
+    /// Nothing is writable unless allowed here.
+    pub fn allow_write(&mut self, path: impl AsRef<Path>) {
+        self.writable.push(resolve_path(path.as_ref()));
+    }
 
-                Shr => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => self.stack.push(Value::Int(x >> y)),
-                        _ => panic!("Shift operations only support integers"),
-                    }
-                }
+    /// `open(path, flags)`: returns a new descriptor, or -1 if the path is not allowed
+    /// or cannot be opened.
+    fn open(&mut self, path: &[u8], flags: i64) -> i64 {
+        let Ok(path) = std::str::from_utf8(path) else {
+            return -1;
+        };
+        let target = resolve_path(Path::new(path));
+        let access = flags & 3;
+        let wants_read = access == O_RDONLY || access == O_RDWR;
+        let wants_write = access == O_WRONLY || access == O_RDWR;
+        let allowed = |list: &[PathBuf]| list.iter().any(|p| target.starts_with(p));
+        if (wants_read && !allowed(&self.readable)) || (wants_write && !allowed(&self.writable)) {
+            return -1;
+        }
+        // a symlink still here is one `resolve_path` couldn't follow (dangling, or created since):
+        // opening it would follow it to wherever it points, and create the file there
+        if std::fs::symlink_metadata(&target).is_ok_and(|m| m.file_type().is_symlink()) {
+            return -1;
+        }
 
-                Jmp(lbl) => {
-                    ip = labels[lbl];
-                    continue;
-                }
+        let file = OpenOptions::new()
+            .read(wants_read)
+            .write(wants_write)
+            .create(wants_write && flags & O_CREAT != 0)
+            .truncate(wants_write && flags & O_TRUNC != 0)
+            .append(wants_write && flags & O_APPEND != 0)
+            .open(&target);
+        // the path may have changed between the check and the open, so check what was opened
+        let file = file.ok().filter(|_| target.canonicalize().is_ok_and(|opened| opened == target));
+        match file {
+            Some(file) => {
+                let fd = self.next_fd;
+                self.next_fd += 1;
+                self.files.insert(fd, file);
+                fd
+            }
+            None => -1,
+        }
+    }
 
-                Jz(lbl) => {
-                    let v = self.stack.pop().unwrap();
-                    let cond = match v {
-                        Value::Int(i) => i == 0,
-                        Value::Flt(f) => f == 0.0,
-                    };
-                    if cond {
-                        ip = labels[lbl];
-                        continue;
-                    }
-                }
+    /// Send everything the program prints to `output` instead of stdout.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
 
-                Label(_) => { /* no-op */ }
+    /// Read program input from `input` instead of stdin.
+    pub fn set_input(&mut self, input: impl Read + 'static) {
+        self.input = Box::new(BufReader::new(input));
+    }
 
-                Instruction::Call(addr) => {
-                    // Get number of arguments for this function
-                    let num_args = self
-                        .function_arity
-                        .get(addr)
-                        .copied()
-                        .expect("Missing function arity");
+    /// Copy the program's data segment (see `Parser::data`) into VM memory.
+    pub fn load_data(&mut self, data: &[u8]) {
+        self.memory.clear();
+        self.memory.extend_from_slice(data);
+    }
 
-                    // Pop args from stack (right to left), reverse them
-                    let mut args = Vec::with_capacity(num_args);
-                    for _ in 0..num_args {
-                        args.push(self.stack.pop().expect("Missing argument"));
-                    }
-                    args.reverse();
+    /// Pass command-line arguments to `main(int argc, char **argv)`. The strings and the
+    /// NULL-terminated `argv` array are placed in memory, and `argc`/`argv` fill the first
+    /// two slots of the frame `main` starts in. Call after `load_data`.
+    pub fn set_args(&mut self, args: &[String]) {
+        let mut pointers = Vec::with_capacity(args.len() + 1);
+        for arg in args {
+            let addr = self.malloc(arg.len() + 1);
+            self.store_bytes(addr, arg.as_bytes());
+            pointers.push(addr);
+        }
+        pointers.push(0);
 
-                    // ✅ Push args first
-                    for arg in args {
-                        self.stack.push(arg);
-                    }
+        let argv = self.malloc(pointers.len() * 8);
+        for (k, p) in pointers.iter().enumerate() {
+            self.store(argv + 8 * k as i64, Value::Int(*p));
+        }
 
-                    // ✅ Now push old FP and return address (as Int values)
-                    self.stack.push(Value::Int(self.fp as i64));
-                    self.stack.push(Value::Int((ip + 1) as i64));
+        if self.stack.len() < self.fp + 2 {
+            self.stack.resize(self.fp + 2, Value::Int(0));
+        }
+        self.stack[self.fp] = Value::Int(args.len() as i64);
+        self.stack[self.fp + 1] = Value::Int(argv);
+    }
 
-                    // ✅ Set new FP (points to base of new frame)
-                    self.fp = self.stack.len();
+    /// Index into `memory` for VM address `addr`, checking that `len` bytes are available.
+    fn mem_index(&self, addr: i64, len: usize) -> usize {
+        (addr as usize)
+            .checked_sub(DATA_BASE)
+            .filter(|&i| i + len <= self.memory.len())
+            .unwrap_or_else(|| panic!("Invalid memory address {:#x}", addr))
+    }
 
-                    // ✅ Jump to function start
-                    ip = *addr;
-                    continue;
-                }
+    /// Load the word at `addr`, which is either a stack slot or a memory address.
+    fn load(&self, addr: i64) -> Value {
+        if (addr as usize) < DATA_BASE {
+            return self.stack[addr as usize / WORD].clone();
+        }
+        let i = self.mem_index(addr, 8);
+        Value::Int(i64::from_le_bytes(self.memory[i..i + 8].try_into().unwrap()))
+    }
 
-                Enter(n_locals) => {
-                    // Allocate space for locals with default int value
-                    for _ in 0..*n_locals {
-                        self.stack.push(Value::Int(0));
-                    }
-                }
+    /// Store a word at `addr`, which is either a stack slot or a memory address.
+    fn store(&mut self, addr: i64, v: Value) {
+        if (addr as usize) < DATA_BASE {
+            self.stack[addr as usize / WORD] = v;
+            return;
+        }
+        let i = self.mem_index(addr, 8);
+        self.memory[i..i + 8].copy_from_slice(&word_bits(&v).to_le_bytes());
+    }
 
-                LoadLocal(offset) => {
-                    // load a local (offset into frame)
-                    let v = self.stack[self.fp + *offset].clone();
-                    self.stack.push(v);
-                }
-                StoreLocal(offset) => {
-                    // store to a local
-                    let v = self.stack.pop().unwrap();
-                    self.stack[self.fp + *offset] = v;
-                }
+    /// Load the (signed) byte at `addr`.
+    fn load_char(&self, addr: i64) -> Value {
+        Value::Int(self.load_bytes(addr, 1)[0] as i8 as i64)
+    }
 
-                Leave => {
-                    // 1) pull the return value off the top
-                    let ret_val = self.stack.pop().unwrap();
+    /// Store raw bytes starting at `addr`. On the stack, byte `addr` is byte `addr % WORD`
+    /// (little-endian) of slot `addr / WORD`, so bytes pack eight to a slot as in memory.
+    fn store_bytes(&mut self, addr: i64, bytes: &[u8]) {
+        if (addr as usize) < DATA_BASE {
+            for (k, b) in bytes.iter().enumerate() {
+                let (slot, shift) = stack_byte(addr as usize + k);
+                let word = word_bits(&self.stack[slot]) & !(0xff << shift) | (*b as i64) << shift;
+                self.stack[slot] = Value::Int(word);
+            }
+            return;
+        }
+        let i = self.mem_index(addr, bytes.len());
+        self.memory[i..i + bytes.len()].copy_from_slice(bytes);
+    }
 
-                    // 2) pop any locals (everything above the saved FP)
-                    while self.stack.len() > self.fp {
-                        self.stack.pop();
-                    }
+    /// Load `len` raw bytes starting at `addr`, laid out on the stack as for `store_bytes`.
+    fn load_bytes(&self, addr: i64, len: usize) -> Vec<u8> {
+        if (addr as usize) < DATA_BASE {
+            return (0..len)
+                .map(|k| {
+                    let (slot, shift) = stack_byte(addr as usize + k);
+                    (word_bits(&self.stack[slot]) >> shift) as u8
+                })
+                .collect();
+        }
+        let i = self.mem_index(addr, len);
+        self.memory[i..i + len].to_vec()
+    }
 
-                    // 3) pop return address (must be an Int)
-                    let ret_addr = match self.stack.pop().unwrap() {
-                        Value::Int(i) => i as usize,
-                        _ => panic!("Expected integer return address"),
-                    };
+    /// Allocate `size` zeroed bytes at the end of memory and return their address.
+    fn malloc(&mut self, size: usize) -> i64 {
+        let start = self.memory.len().next_multiple_of(8);
+        self.memory.resize(start + size, 0);
+        (DATA_BASE + start) as i64
+    }
 
-                    // 4) restore old FP
-                    let old_fp = match self.stack.pop().unwrap() {
-                        Value::Int(i) => i as usize,
-                        _ => panic!("Expected integer frame pointer"),
-                    };
-                    self.fp = old_fp;
+    /// Write out what the program printed before waiting for input, so a prompt shows up
+    /// before the read that answers it.
+    fn flush_before_input(&mut self) {
+        self.output.flush().expect("Failed to flush output");
+    }
 
-                    // 5) push the return value back on
-                    self.stack.push(ret_val);
+    /// Read a single byte of program input, or `None` at end of input.
+    fn read_byte(&mut self) -> Option<u8> {
+        let b = *self.input.fill_buf().ok()?.first()?;
+        self.input.consume(1);
+        Some(b)
+    }
 
-                    // 6) jump back to caller
-                    ip = ret_addr;
-                    continue;
-                }
-            }
+    /// Pop two operands and push 1 if `a cmp b` holds, else 0.
+    fn push_comparison(&mut self, cmp: Compare) {
+        let b = self.stack.pop().unwrap();
+        let a = self.stack.pop().unwrap();
+        self.stack.push(Value::Int(compare(cmp, &a, &b) as i64));
+    }
 
-            ip += 1;
+    /// Pop `n` values pushed as call arguments; the first argument comes back first.
+    fn pop_args(&mut self, n: usize) -> Vec<Value> {
+        let mut args = Vec::with_capacity(n);
+        for _ in 0..n {
+            args.push(self.stack.pop().expect("Missing builtin argument"));
         }
+        args
+    }
 
-        match self.stack.pop() {
-            Some(Value::Int(i)) => Some(i),
-            Some(Value::Flt(f)) => Some(f as i64), // convert float to int for return
-            None => None,
+    /// Read the NUL-terminated string starting at VM address `addr`.
+    fn read_cstr(&self, addr: i64) -> Vec<u8> {
+        if (addr as usize) < DATA_BASE {
+            let end = self.stack.len() * WORD;
+            return (addr as usize..end)
+                .map(|a| self.load_bytes(a as i64, 1)[0])
+                .take_while(|&b| b != 0)
+                .collect();
         }
+        let start = (addr as usize)
+            .checked_sub(DATA_BASE)
+            .filter(|&i| i <= self.memory.len())
+            .unwrap_or_else(|| panic!("Invalid string address {:#x}", addr));
+        self.memory[start..]
+            .iter()
+            .take_while(|&&b| b != 0)
+            .copied()
+            .collect()
+    }
+
+    /// Run `code` from its first instruction.
+    pub fn run(&mut self, code: &[Instruction]) -> Option<i64> {
+        self.run_from(code, 0)
     }
 
-    /// mirror the same fp-logic in run_from
+    /// Run `code` starting at `start_ip` (usually the entry point of `main`) and return the
+    /// program's result; a call to `exit(n)` yields `n`.
     pub fn run_from(&mut self, code: &[Instruction], start_ip: usize) -> Option<i64> {
+        match self.execute(code, start_ip) {
+            Outcome::Returned(result) => result,
+            Outcome::Halted(code) => Some(code),
+        }
+    }
+
+    /// Run `code` starting at `start_ip`, reporting whether the program returned normally
+    /// or stopped itself with `exit`. The VM trusts `code`: it must have passed `verify` (see
+    /// `Program::verify`), which is checked once where a program is compiled or loaded, and
+    /// malformed code may panic or misbehave.
+    pub fn execute(&mut self, code: &[Instruction], start_ip: usize) -> Outcome {
         use Instruction::*;
 
         // build label→index map
@@ -379,22 +361,149 @@ impl VM {
                 }
                 PrintF => {
                     let f = self.float_stack.pop().unwrap();
-                    println!("{}", f);
+                    writeln!(self.output, "{}", f).expect("Failed to write output");
                 }
                 Print => {
                     let v = self.stack.pop().unwrap();
                     match v {
-                        Value::Int(i) => println!("{}", i),
-                        Value::Flt(f) => println!("{}", f),
+                        Value::Int(i) => writeln!(self.output, "{}", i),
+                        Value::Flt(f) => writeln!(self.output, "{}", f),
                     }
+                    .expect("Failed to write output");
                 }
                 PrintStr(s) => {
-                    println!("{}", s);
+                    writeln!(self.output, "{}", s).expect("Failed to write output");
+                }
+                Printf(argc) => {
+                    // the format string was pushed last, so it's on top
+                    let mut args = self.pop_args(*argc);
+                    let fmt = match args.remove(0) {
+                        Value::Int(addr) => self.read_cstr(addr),
+                        _ => panic!("printf format must be a string"),
+                    };
+                    let out = sprintf(&fmt, &args, |addr| self.read_cstr(addr));
+                    self.output.write_all(&out).expect("Failed to write output");
+                    self.stack.push(Value::Int(out.len() as i64));
+                }
+                Scanf(argc) => {
+                    let args = self.pop_args(*argc);
+                    let fmt = self.read_cstr(int_arg(&args[0]));
+                    self.flush_before_input();
+                    let result = match scanf(&fmt, &mut self.input) {
+                        None => -1,
+                        Some(values) => {
+                            for (value, dest) in values.iter().zip(&args[1..]) {
+                                let dest = int_arg(dest);
+                                match value {
+                                    Scanned::Int(n) => self.store(dest, Value::Int(*n)),
+                                    Scanned::Flt(f) => self.store(dest, Value::Flt(*f)),
+                                    Scanned::Chars(bytes) => self.store_bytes(dest, bytes),
+                                    Scanned::Str(bytes) => {
+                                        let mut bytes = bytes.clone();
+                                        bytes.push(0);
+                                        self.store_bytes(dest, &bytes);
+                                    }
+                                }
+                            }
+                            values.len().min(args.len() - 1) as i64
+                        }
+                    };
+                    self.stack.push(Value::Int(result));
+                }
+                Getchar => {
+                    self.flush_before_input();
+                    let c = self.read_byte().map_or(-1, |b| b as i64);
+                    self.stack.push(Value::Int(c));
+                }
+                Putchar => {
+                    let c = int_arg(&self.stack.pop().unwrap());
+                    self.output.write_all(&[c as u8]).expect("Failed to write output");
+                    self.stack.push(Value::Int(c as u8 as i64));
+                }
+                Open(argc) => {
+                    let args = self.pop_args(*argc);
+                    let path = self.read_cstr(int_arg(&args[0]));
+                    let fd = self.open(&path, int_arg(&args[1]));
+                    self.stack.push(Value::Int(fd));
+                }
+                Read => {
+                    let args = self.pop_args(3);
+                    let (fd, buf, n) = (int_arg(&args[0]), int_arg(&args[1]), int_arg(&args[2]));
+                    let n = n.max(0) as usize;
+                    // like POSIX `read`, up to `n` bytes of whatever is available, newlines and all
+                    let mut bytes = vec![0; n];
+                    let got = if fd == 0 {
+                        self.flush_before_input();
+                        self.input.read(&mut bytes).ok()
+                    } else {
+                        self.files.get_mut(&fd).and_then(|file| file.read(&mut bytes).ok())
+                    };
+                    let bytes = got.map(|got| {
+                        bytes.truncate(got);
+                        bytes
+                    });
+                    let result = match bytes {
+                        Some(bytes) => {
+                            self.store_bytes(buf, &bytes);
+                            bytes.len() as i64
+                        }
+                        None => -1, // bad descriptor or read error
+                    };
+                    self.stack.push(Value::Int(result));
+                }
+                Write => {
+                    let args = self.pop_args(3);
+                    let (fd, buf, n) = (int_arg(&args[0]), int_arg(&args[1]), int_arg(&args[2]));
+                    let bytes = self.load_bytes(buf, n.max(0) as usize);
+                    let written = match fd {
+                        1 => self.output.write_all(&bytes).is_ok(),
+                        2 => std::io::stderr().write_all(&bytes).is_ok(),
+                        _ => self.files.get_mut(&fd).is_some_and(|f| f.write_all(&bytes).is_ok()),
+                    };
+                    self.stack.push(Value::Int(if written { bytes.len() as i64 } else { -1 }));
+                }
+                Close => {
+                    let fd = int_arg(&self.stack.pop().unwrap());
+                    let closed = self.files.remove(&fd).is_some();
+                    self.stack.push(Value::Int(if closed { 0 } else { -1 }));
+                }
+                Exit => {
+                    let status = int_arg(&self.stack.pop().unwrap());
+                    self.output.flush().expect("Failed to flush output");
+                    return Outcome::Halted(status);
+                }
+                Malloc => {
+                    let size = int_arg(&self.stack.pop().unwrap());
+                    let addr = self.malloc(size.max(0) as usize);
+                    self.stack.push(Value::Int(addr));
+                }
+                Free => {
+                    // the bump allocator never reuses memory
+                    self.stack.pop();
+                    self.stack.push(Value::Int(0));
+                }
+                Memset => {
+                    let args = self.pop_args(3);
+                    let (dst, byte, n) = (int_arg(&args[0]), int_arg(&args[1]), int_arg(&args[2]));
+                    self.store_bytes(dst, &vec![byte as u8; n.max(0) as usize]);
+                    self.stack.push(Value::Int(dst));
+                }
+                Memcmp => {
+                    let args = self.pop_args(3);
+                    let n = int_arg(&args[2]).max(0) as usize;
+                    let a = self.load_bytes(int_arg(&args[0]), n);
+                    let b = self.load_bytes(int_arg(&args[1]), n);
+                    let diff = a
+                        .iter()
+                        .zip(&b)
+                        .find(|(x, y)| x != y)
+                        .map_or(0, |(&x, &y)| x as i64 - y as i64);
+                    self.stack.push(Value::Int(diff));
                 }
                 Neg => {
                     let v = self.stack.pop().unwrap();
                     let result = match v {
-                        Value::Int(n) => Value::Int(-n),
+                        Value::Int(n) => Value::Int(n.wrapping_neg()),
                         Value::Flt(f) => Value::Flt(-f),
                     };
                     self.stack.push(result);
@@ -414,11 +523,35 @@ impl VM {
                         Value::Int(i) => i as usize,
                         _ => panic!("Cannot dereference non-integer address"),
                     };
-                    let v = self.stack[addr].clone(); // clone to avoid borrow checker issues
+                    let v = self.load(addr as i64);
+                    self.stack.push(v);
+                }
+                DerefChar => {
+                    let addr = int_arg(&self.stack.pop().unwrap());
+                    let v = self.load_char(addr);
                     self.stack.push(v);
                 }
+                Store => {
+                    let v = self.stack.pop().unwrap();
+                    let addr = int_arg(&self.stack.pop().unwrap());
+                    self.store(addr, v.clone());
+                    self.stack.push(v);
+                }
+                StoreChar => {
+                    let c = int_arg(&self.stack.pop().unwrap()) as u8;
+                    let addr = int_arg(&self.stack.pop().unwrap());
+                    self.store_bytes(addr, &[c]);
+                    self.stack.push(Value::Int(c as i8 as i64));
+                }
+                Dup => {
+                    let v = self.stack.last().unwrap().clone();
+                    self.stack.push(v);
+                }
+                Pop => {
+                    self.stack.pop();
+                }
                 Addr(offset) => {
-                    let addr = (self.fp + *offset) as i64;
+                    let addr = ((self.fp + *offset) * WORD) as i64;
                     self.stack.push(Value::Int(addr));
                 }
                 Cast => { // added: no-op for C-style cast
@@ -435,7 +568,7 @@ impl VM {
                     let b = self.stack.pop().unwrap();
                     let a = self.stack.pop().unwrap();
                     let res = match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => Value::Int(x + y),
+                        (Value::Int(x), Value::Int(y)) => Value::Int(x.wrapping_add(y)),
                         (Value::Flt(x), Value::Flt(y)) => Value::Flt(x + y),
                         (Value::Int(x), Value::Flt(y)) => Value::Flt(x as f64 + y),
                         (Value::Flt(x), Value::Int(y)) => Value::Flt(x + y as f64),
@@ -446,7 +579,7 @@ impl VM {
                     let b = self.stack.pop().unwrap();
                     let a = self.stack.pop().unwrap();
                     self.stack.push(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => Value::Int(x - y),
+                        (Value::Int(x), Value::Int(y)) => Value::Int(x.wrapping_sub(y)),
                         (Value::Flt(x), Value::Flt(y)) => Value::Flt(x - y),
                         (Value::Int(x), Value::Flt(y)) => Value::Flt(x as f64 - y),
                         (Value::Flt(x), Value::Int(y)) => Value::Flt(x - y as f64),
@@ -456,7 +589,7 @@ impl VM {
                     let b = self.stack.pop().unwrap();
                     let a = self.stack.pop().unwrap();
                     self.stack.push(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => Value::Int(x * y),
+                        (Value::Int(x), Value::Int(y)) => Value::Int(x.wrapping_mul(y)),
                         (Value::Flt(x), Value::Flt(y)) => Value::Flt(x * y),
                         (Value::Int(x), Value::Flt(y)) => Value::Flt(x as f64 * y),
                         (Value::Flt(x), Value::Int(y)) => Value::Flt(x * y as f64),
@@ -481,56 +614,12 @@ impl VM {
                     });
                 }
 
-                Eq => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int((a == b) as i64));
-                }
-                Ne => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int((a != b) as i64));
-                }
-                Lt => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => (x < y) as i64,
-                        (Value::Flt(x), Value::Flt(y)) => (x < y) as i64,
-                        (Value::Int(x), Value::Flt(y)) => ((x as f64) < y) as i64,
-                        (Value::Flt(x), Value::Int(y)) => (x < (y as f64)) as i64,
-                    }));
-                }
-                Gt => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => (x > y) as i64,
-                        (Value::Flt(x), Value::Flt(y)) => (x > y) as i64,
-                        (Value::Int(x), Value::Flt(y)) => ((x as f64) > y) as i64,
-                        (Value::Flt(x), Value::Int(y)) => (x > (y as f64)) as i64,
-                    }));
-                }
-                Le => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => (x <= y) as i64,
-                        (Value::Flt(x), Value::Flt(y)) => (x <= y) as i64,
-                        (Value::Int(x), Value::Flt(y)) => ((x as f64) <= y) as i64,
-                        (Value::Flt(x), Value::Int(y)) => (x <= (y as f64)) as i64,
-                    }));
-                }
-                Ge => {
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(Value::Int(match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => (x >= y) as i64,
-                        (Value::Flt(x), Value::Flt(y)) => (x >= y) as i64,
-                        (Value::Int(x), Value::Flt(y)) => ((x as f64) >= y) as i64,
-                        (Value::Flt(x), Value::Int(y)) => (x >= (y as f64)) as i64,
-                    }));
-                }
+                Eq => self.push_comparison(Compare::Eq),
+                Ne => self.push_comparison(Compare::Ne),
+                Lt => self.push_comparison(Compare::Lt),
+                Gt => self.push_comparison(Compare::Gt),
+                Le => self.push_comparison(Compare::Le),
+                Ge => self.push_comparison(Compare::Ge),
 
                 BitAnd => {
                     let b = self.stack.pop().unwrap();
@@ -563,7 +652,9 @@ impl VM {
                     let b = self.stack.pop().unwrap();
                     let a = self.stack.pop().unwrap();
                     match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => self.stack.push(Value::Int(x << y)),
+                        (Value::Int(x), Value::Int(y)) => {
+                            self.stack.push(Value::Int(x.wrapping_shl(y as u32)))
+                        }
                         _ => panic!("Shift operations only support integers"),
                     }
                 }
@@ -572,7 +663,9 @@ impl VM {
                     let b = self.stack.pop().unwrap();
                     let a = self.stack.pop().unwrap();
                     match (a, b) {
-                        (Value::Int(x), Value::Int(y)) => self.stack.push(Value::Int(x >> y)),
+                        (Value::Int(x), Value::Int(y)) => {
+                            self.stack.push(Value::Int(x.wrapping_shr(y as u32)))
+                        }
                         _ => panic!("Shift operations only support integers"),
                     }
                 }
@@ -594,6 +687,18 @@ impl VM {
                     }
                 }
 
+                Jnz(lbl) => {
+                    let v = self.stack.pop().unwrap();
+                    let cond = match v {
+                        Value::Int(i) => i != 0,
+                        Value::Flt(f) => f != 0.0,
+                    };
+                    if cond {
+                        ip = labels[lbl];
+                        continue;
+                    }
+                }
+
                 Label(_) => { /* no-op */ }
 
                 Instruction::Call(addr) => {
@@ -603,12 +708,8 @@ impl VM {
                         .get(addr)
                         .expect("Missing function arity");
 
-                    // pop them off (right-to-left), then reverse to left-to-right
-                    let mut args = Vec::with_capacity(num_args);
-                    for _ in 0..num_args {
-                        args.push(self.stack.pop().unwrap());
-                    }
-                    args.reverse();
+                    // arguments were pushed right-to-left, so they pop off first-to-last
+                    let args = self.pop_args(num_args);
 
                     // first push the old frame pointer and return address
                     self.stack.push(Value::Int(self.fp as i64));
@@ -627,6 +728,22 @@ impl VM {
                     continue;
                 }
 
+                TailCall(addr) => {
+                    let num_args = *self
+                        .function_arity
+                        .get(addr)
+                        .expect("Missing function arity");
+                    let args = self.pop_args(num_args);
+
+                    // drop our frame but keep the saved frame pointer and return address
+                    // under it, so the callee returns straight to our caller
+                    self.stack.truncate(self.fp);
+                    self.stack.extend(args);
+
+                    ip = *addr;
+                    continue;
+                }
+
                 Enter(n_locals) => {
                     // Allocate space for locals with default int value
                     for _ in 0..*n_locals {
@@ -640,8 +757,8 @@ impl VM {
                     self.stack.push(v);
                 }
                 StoreLocal(offset) => {
-                    // store to a local
-                    let v = self.stack.pop().unwrap();
+                    // store to a local, leaving the value as the assignment's result
+                    let v = self.stack.last().unwrap().clone();
                     self.stack[self.fp + *offset] = v;
                 }
 
@@ -674,13 +791,95 @@ impl VM {
                     ip = ret_addr;
                     continue;
                 }
+
+                AddLocal(offset, n) => {
+                    let slot = &mut self.stack[self.fp + *offset];
+                    *slot = match *slot {
+                        Value::Int(x) => Value::Int(x.wrapping_add(*n)),
+                        Value::Flt(x) => Value::Flt(x + *n as f64),
+                    };
+                }
+
+                CmpJz(cmp, lbl) => {
+                    let b = self.stack.pop().unwrap();
+                    let a = self.stack.pop().unwrap();
+                    if !compare(*cmp, &a, &b) {
+                        ip = labels[lbl];
+                        continue;
+                    }
+                }
             }
             ip += 1;
         }
-        match self.stack.pop() {
+        self.output.flush().expect("Failed to flush output");
+        Outcome::Returned(match self.stack.pop() {
             Some(Value::Int(i)) => Some(i),
             Some(Value::Flt(f)) => Some(f as i64), // convert float to int for return
             None => None,
+        })
+    }
+}
+
+/// `a cmp b`, for the `Eq`...`Ge` instructions and `CmpJz`.
+fn compare(cmp: Compare, a: &Value, b: &Value) -> bool {
+    let (x, y) = match (a, b) {
+        (Value::Int(x), Value::Int(y)) => {
+            return match cmp {
+                Compare::Eq => x == y,
+                Compare::Ne => x != y,
+                Compare::Lt => x < y,
+                Compare::Gt => x > y,
+                Compare::Le => x <= y,
+                Compare::Ge => x >= y,
+            };
+        }
+        (Value::Int(x), Value::Flt(y)) => (*x as f64, *y),
+        (Value::Flt(x), Value::Int(y)) => (*x, *y as f64),
+        (Value::Flt(x), Value::Flt(y)) => (*x, *y),
+    };
+    match cmp {
+        // `Eq`/`Ne` compare values, so an int never equals a float
+        Compare::Eq => a == b,
+        Compare::Ne => a != b,
+        Compare::Lt => x < y,
+        Compare::Gt => x > y,
+        Compare::Le => x <= y,
+        Compare::Ge => x >= y,
+    }
+}
+
+/// The 8 bytes of a word as stored in memory: a float keeps its bit pattern.
+fn word_bits(v: &Value) -> i64 {
+    match v {
+        Value::Int(n) => *n,
+        Value::Flt(f) => f.to_bits() as i64,
+    }
+}
+
+/// The stack slot holding byte address `addr`, and the shift of that byte within the slot.
+fn stack_byte(addr: usize) -> (usize, usize) {
+    (addr / WORD, addr % WORD * 8)
+}
+
+/// Interpret a builtin argument as an integer (addresses, sizes, descriptors).
+fn int_arg(v: &Value) -> i64 {
+    match v {
+        Value::Int(i) => *i,
+        Value::Flt(f) => *f as i64,
+    }
+}
+
+/// Make `path` absolute and resolve `.`, `..` and symlinks so allow-list checks can't be
+/// sidestepped. A file that doesn't exist yet is resolved through its parent directory.
+fn resolve_path(path: &Path) -> PathBuf {
+    if let Ok(p) = path.canonicalize() {
+        return p;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            resolve_path(parent).join(name)
         }
+        _ => path.to_path_buf(),
     }
 }
@@ -0,0 +1,243 @@
+/// Tests for the textual assembler: hand-written programs exercising the VM's calling
+/// convention directly, golden listings of compiled programs in `tests/golden`, and
+/// compile → print → assemble round trips.
+mod common;
+
+use c4_rust::asm::{assemble, to_asm};
+use c4_rust::program::Program;
+use c4_rust::vm::SharedBuffer;
+use common::{compile, read_source};
+
+/// Run `program` from its entry point, returning its result and what it printed.
+fn run(program: &Program) -> (Option<i64>, String) {
+    let output = SharedBuffer::new();
+    let mut vm = program.vm();
+    vm.set_output(output.clone());
+    let result = vm.run_from(&program.code, program.entry());
+    (result, output.to_string_lossy())
+}
+
+fn run_asm(text: &str) -> Option<i64> {
+    let program = assemble(text).unwrap_or_else(|e| panic!("{}", e.message));
+    run(&program).0
+}
+
+#[test]
+fn test_enter_leave_and_call() {
+    // args are pushed right to left, so the first one ends up on top and is LoadLocal 0
+    let text = "
+.func sub 2
+    Enter 2
+    LoadLocal 0     ; first argument
+    LoadLocal 1     ; second argument
+    Sub
+    Leave
+.func main 0
+    Enter 0
+    Imm 8
+    Imm 50
+    Call sub
+    Jmp L9999
+L9999:
+";
+    assert_eq!(run_asm(text), Some(42));
+}
+
+#[test]
+fn test_enter_reserves_zeroed_locals() {
+    let text = "
+.func count 1
+    Enter 3               ; the argument and two locals
+    LoadLocal 1
+    Imm 5
+    Add
+    StoreLocal 1
+    Pop
+    LoadLocal 2           ; still zero
+    LoadLocal 1
+    Add
+    LoadLocal 0
+    Mul
+    Leave
+.func main 0
+    Enter 0
+    Imm 3
+    Call count
+    Jmp done
+done:
+";
+    assert_eq!(run_asm(text), Some(15));
+}
+
+#[test]
+fn test_nested_calls_restore_frames() {
+    // `outer` keeps a local across the call to `inner`, which has a bigger frame
+    let text = "
+.func main 0
+    Enter 1
+    Imm 7
+    StoreLocal 0
+    Pop
+    Imm 2
+    Call outer
+    LoadLocal 0
+    Add
+    Jmp end
+.func inner 1
+    Enter 4
+    Imm 100
+    StoreLocal 3
+    Pop
+    LoadLocal 0
+    LoadLocal 0
+    Mul
+    Leave
+.func outer 1
+    Enter 2
+    Imm 10
+    StoreLocal 1
+    Pop
+    LoadLocal 0
+    Call inner
+    LoadLocal 1
+    Add
+    Leave
+end:
+";
+    assert_eq!(run_asm(text), Some(4 + 10 + 7));
+}
+
+#[test]
+fn test_tail_call_reuses_the_frame() {
+    // sum(n, acc) = n ? sum(n - 1, acc + n) : acc, with the first argument pushed last
+    let text = "
+.func sum 2
+    Enter 2
+    LoadLocal 0
+    Jz done
+    LoadLocal 1
+    LoadLocal 0
+    Add
+    LoadLocal 0
+    Imm 1
+    Sub
+    TailCall sum
+done:
+    LoadLocal 1
+    Leave
+.func main 0
+    Enter 0
+    Imm 0
+    Imm 100000
+    Call sum
+    Jmp L9999
+L9999:
+";
+    assert_eq!(run_asm(text), Some(5_000_050_000));
+}
+
+#[test]
+fn test_data_symbols_and_labels() {
+    let text = r#"
+.string msg "sum=%d\n"   ; a ; inside a string is not a comment
+.global total
+.locals 1
+    Imm 0
+    StoreLocal 0
+    Pop
+top:
+    LoadLocal 0
+    Imm 4
+    Lt
+    Jz out
+    Imm total
+    Imm total
+    Deref
+    LoadLocal 0
+    Add
+    Store
+    Pop
+    LoadLocal 0
+    Imm 1
+    Add
+    StoreLocal 0
+    Pop
+    Jmp top
+out:
+    Imm total
+    Deref
+    Imm msg
+    Printf 2
+    Pop
+    Imm 0x10
+"#;
+    let program = assemble(text).unwrap();
+    assert_eq!(program.globals["total"], 0x1000_0008);
+    assert_eq!(program.strings, vec![0x1000_0000]);
+    assert_eq!(run(&program), (Some(16), "sum=6\n".to_string()));
+}
+
+#[test]
+fn test_errors_point_at_the_line() {
+    let cases = [
+        ("    Jmp nowhere\n    Frob\n", 2, "Unknown instruction `Frob`"),
+        ("    Imm 1\n    Call missing\n", 2, "Unknown function `missing`"),
+        ("\n\n    Imm nothing\n", 3, "Unknown symbol `nothing`"),
+        ("    Add 3\n", 1, "`Add` takes no operand"),
+        ("    Enter -1\n", 1, "Expected a count, found `-1`"),
+        (".func f 0\n.func f 1\n", 2, "Function `f` is defined twice"),
+        (".string s hello\n", 1, "Expected a string in double quotes, found `hello`"),
+        (".section text\n", 1, "Unknown directive `.section`"),
+    ];
+    for (text, line, message) in cases {
+        let error = assemble(text).unwrap_err();
+        assert_eq!((error.line, error.message.as_str()), (line, message), "{:?}", text);
+    }
+}
+
+#[test]
+fn test_golden_listings() {
+    for name in ["fib", "strings"] {
+        let source = std::fs::read_to_string(format!("tests/golden/{}.c", name)).unwrap();
+        let golden = std::fs::read_to_string(format!("tests/golden/{}.s", name)).unwrap();
+        let compiled = compile(&source);
+        assert_eq!(to_asm(&compiled).unwrap(), golden, "tests/golden/{}.s is out of date", name);
+
+        let assembled = assemble(&golden).unwrap();
+        assert_eq!(run(&assembled), run(&compiled), "{}", name);
+    }
+    assert_eq!(run(&assemble(&std::fs::read_to_string("tests/golden/fib.s").unwrap()).unwrap()).0, Some(55));
+}
+
+#[test]
+fn test_round_trip_preserves_the_program() {
+    let sources = [
+        read_source("input/hello.c"),
+        read_source("input/test_all_features.c"),
+        "int x; { x = 3; print(\"tab\\there\\n\"); return x * 2; }".to_string(),
+        "int main() { print(1.5); printf(\"\\x01\\\"q\\\"\\\\\"); return 0; }".to_string(),
+        "int down(int n) { if (n) return down(n - 1); return 7; } int main() { return down(3); }".to_string(),
+    ];
+    for source in &sources {
+        let mut original = compile(source);
+        let mut reassembled = assemble(&to_asm(&original).unwrap()).unwrap();
+        assert_eq!(to_asm(&reassembled).unwrap(), to_asm(&original).unwrap());
+        // only the spans differ: they point into the assembly instead of the C source
+        original.spans.clear();
+        reassembled.spans.clear();
+        assert_eq!(reassembled, original, "{}", source);
+    }
+}
+
+#[test]
+fn test_strings_round_trip_byte_for_byte() {
+    // U+0085 is a control character two bytes long, and 0xff isn't UTF-8 at all
+    let program = assemble(".string s \"\\xc2\\x85|\\xff|\\x01|é\"\n").unwrap();
+    let text = to_asm(&program).unwrap();
+    assert_eq!(text, ".string str.0 \"\\xc2\\x85|\\xff|\\x01|é\"\n");
+    assert_eq!(assemble(&text).unwrap().data, program.data);
+
+    let mut broken = program.clone();
+    broken.strings = vec![16];
+    assert_eq!(to_asm(&broken).unwrap_err().message, "string literal at 0x10 is outside the data section");
+}
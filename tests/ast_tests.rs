@@ -0,0 +1,89 @@
+/// Tests for the syntax tree the parser builds: its shape, operator precedence, and the
+/// spans it records for later passes.
+use c4_rust::ast::{BinaryOp, Decl, Expr, ExprKind, StmtKind, TranslationUnit, Type, UnaryOp};
+use c4_rust::codegen::CodeGen;
+use c4_rust::parser::Parser;
+
+fn parse(source: &str) -> TranslationUnit {
+    Parser::new(source).parse_ast().expect("source should parse")
+}
+
+/// The expression of the single statement `<source>;` inside `main`.
+fn expr(source: &str) -> Expr {
+    let unit = parse(&format!("int main() {{ int a, b, *p; {}; }}", source));
+    let Decl::Function(main) = &unit.decls[0] else { panic!("expected a function") };
+    let StmtKind::Expr(expr) = &main.body[1].kind else { panic!("expected an expression statement") };
+    expr.clone()
+}
+
+#[test]
+fn test_top_level_items() {
+    let unit = parse("enum { A, B = 5, C };\nchar *name, c;\nint twice(int x) { return x * 2; }\n{ int t; }\n");
+    assert_eq!(unit.decls.len(), 5);
+    let Decl::Enum(e) = &unit.decls[0] else { panic!() };
+    assert_eq!(e.members, vec![("A".to_string(), 0), ("B".to_string(), 5), ("C".to_string(), 6)]);
+    let Decl::Global(name) = &unit.decls[1] else { panic!() };
+    assert_eq!((name.name.as_str(), &name.ty), ("name", &Type::Char.ptr()));
+    let Decl::Global(c) = &unit.decls[2] else { panic!() };
+    assert_eq!((c.name.as_str(), &c.ty), ("c", &Type::Char));
+    let Decl::Function(f) = &unit.decls[3] else { panic!() };
+    assert_eq!(f.name, "twice");
+    assert_eq!(f.params, vec![("x".to_string(), Type::Int)]);
+    assert!(matches!(f.body[0].kind, StmtKind::Return(Some(_))));
+    let Decl::Stmt(block) = &unit.decls[4] else { panic!() };
+    assert!(matches!(&block.kind, StmtKind::Block(stmts) if stmts.len() == 1));
+}
+
+#[test]
+fn test_precedence_and_associativity() {
+    // a = b = 1 + 2 * 3  parses as  a = (b = (1 + (2 * 3)))
+    let ExprKind::Assign { target, value } = expr("a = b = 1 + 2 * 3").kind else { panic!() };
+    assert_eq!(target.kind, ExprKind::Var("a".to_string()));
+    let ExprKind::Assign { value, .. } = value.kind else { panic!() };
+    let ExprKind::Binary { op: BinaryOp::Add, rhs, .. } = value.kind else { panic!() };
+    assert!(matches!(rhs.kind, ExprKind::Binary { op: BinaryOp::Mul, .. }));
+
+    // prefix operators bind looser than postfix ones: *p++ is *(p++)
+    let ExprKind::Unary { op: UnaryOp::Deref, operand } = expr("*p++").kind else { panic!() };
+    assert!(matches!(operand.kind, ExprKind::Unary { op: UnaryOp::PostInc, .. }));
+
+    // casts, sizeof, subscripts, calls and the conditional operator
+    assert!(matches!(expr("(char *)p").kind, ExprKind::Cast { ty: Type::Ptr(_), .. }));
+    assert_eq!(expr("sizeof(int *)").kind, ExprKind::Sizeof(Type::Int.ptr()));
+    assert!(matches!(expr("p[a + 1]").kind, ExprKind::Index { .. }));
+    assert!(matches!(expr("f(1, \"s\")").kind, ExprKind::Call { ref args, .. } if args.len() == 2));
+    assert!(matches!(expr("a && b ? a : b || 1").kind, ExprKind::Cond { .. }));
+}
+
+#[test]
+fn test_spans_cover_the_source_of_each_node() {
+    let source = "int main() {\n  int x;\n  x = (1 + 2) * foo(3);\n}\n";
+    let unit = parse(source);
+    let Decl::Function(main) = &unit.decls[0] else { panic!() };
+    assert_eq!(&source[main.span.start..main.span.end], &source[..source.len() - 1]);
+    assert_eq!(&source[main.end.start..main.end.end], "}");
+
+    let stmt = &main.body[1];
+    assert_eq!(&source[stmt.span.start..stmt.span.end], "x = (1 + 2) * foo(3);");
+    assert_eq!((stmt.span.line, stmt.span.column), (3, 3));
+    let StmtKind::Expr(assign) = &stmt.kind else { panic!() };
+    let ExprKind::Assign { value, .. } = &assign.kind else { panic!() };
+    assert_eq!(&source[value.span.start..value.span.end], "(1 + 2) * foo(3)");
+    let ExprKind::Binary { lhs, rhs, .. } = &value.kind else { panic!() };
+    assert_eq!(&source[lhs.span.start..lhs.span.end], "(1 + 2)");
+    assert_eq!(&source[rhs.span.start..rhs.span.end], "foo(3)");
+}
+
+#[test]
+fn test_name_errors_come_from_codegen() {
+    // the tree parses; only generating code finds the undefined names
+    let unit = parse("int main() { return missing + nothing(); }");
+    let mut codegen = CodeGen::new();
+    codegen.generate(&unit);
+    let messages: Vec<&str> = codegen.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages, vec!["Undefined variable `missing`"]);
+
+    // syntax errors are reported by `parse_ast` itself
+    let errors = Parser::new("int main() { return 1 +; }").parse_ast().unwrap_err();
+    assert_eq!(errors[0].message, "Unexpected token in expression: Semicolon");
+}
@@ -1,11 +1,17 @@
+// the original tests are kept as they were written, unused frame size and all
+#![allow(unused_variables, clippy::empty_line_after_doc_comments)]
 /// This file contains integration tests that validate the behavior of the C4 Rust compiler and virtual machine.
 /// Each test feeds a small C-like code snippet through the parser and VM, asserting the correct execution output.
 /// The tests cover a wide range of language features including arithmetic, control flow, function calls, pointer operations,
 /// comparisons, bitwise and shift operations, floating-point support, and printing.
 /// These tests ensure the correctness and completeness of the C4 compiler backend.
 
+mod common;
+
+use c4_rust::instruction::Instruction;
 use c4_rust::parser::Parser;
 use c4_rust::vm::VM;
+use common::compile;
 
 fn run_and_return(source: &str) -> i64 {
     let mut parser = Parser::new(source);
@@ -14,6 +20,7 @@ fn run_and_return(source: &str) -> i64 {
         let arity = *parser.function_arity.get(name).unwrap_or(&0);
         (*addr, arity)
     }).collect());
+    vm.load_data(&parser.data);
     
     // Simulate a function frame with N local variables
     let num_locals = parser.locals.len();
@@ -253,6 +260,10 @@ fn test_conditional_operator() {
     assert_eq!(run_and_return(src), 456);
     let src = r#"{ return 1 ? 123 : 456; }"#;
     assert_eq!(run_and_return(src), 123);
+
+    // a pointer on either side makes the whole expression a pointer
+    let src = r#"{ char *s; s = "hi"; return *(1 ? s : 0) + *(0 ? 0 : s + 1); }"#;
+    assert_eq!(run_and_return(src), (b'h' + b'i') as i64);
 }
 
 #[test]
@@ -372,3 +383,473 @@ for _ in 0..parser.locals.len() {
     let result = vm.run_from(&parser.code, main_ip);
     assert_eq!(result, Some(0));
 }
+
+#[test]
+fn test_printf_returns_bytes_written() {
+    let src = r#"
+    {
+        return printf("%s=%d\n", "answer", 42);
+    }
+    "#;
+    assert_eq!(run_and_return(src), 10);
+}
+
+#[test]
+fn test_char_constants_are_signed() {
+    assert_eq!(run_and_return(r"{ return '\xff'; }"), -1);
+    assert_eq!(run_and_return(r"{ return '\200' + '\177'; }"), -1);
+    // a multi-character constant is an int made of its bytes, the first one highest
+    assert_eq!(run_and_return(r"{ return 'ab'; }"), 0x6162);
+}
+
+fn run_with_output(source: &str) -> (i64, String) {
+    run_with_io(source, "")
+}
+
+fn run_with_io(source: &str, input: &str) -> (i64, String) {
+    let program = compile(source);
+    let mut vm = program.vm();
+    let output = c4_rust::vm::SharedBuffer::new();
+    vm.set_output(output.clone());
+    vm.set_input(std::io::Cursor::new(input.as_bytes().to_vec()));
+
+    let result = vm.run_from(&program.code, program.entry());
+    (result.expect("VM did not return a value"), output.to_string_lossy())
+}
+
+#[test]
+fn test_print_output_is_captured() {
+    let src = r#"
+    {
+        print("hello world");
+        print(2 + 3);
+        print(1.5);
+        return 0;
+    }
+    "#;
+    assert_eq!(run_with_output(src), (0, "hello world\n5\n1.5\n".to_string()));
+}
+
+#[test]
+fn test_printf_output_is_captured() {
+    let src = r#"
+    int main() {
+        printf("%d-%s", 7, "up");
+        printf("[%3c]\n", 'x');
+        return 1;
+    }
+    "#;
+    assert_eq!(run_with_output(src), (1, "7-up[  x]\n".to_string()));
+}
+
+#[test]
+fn test_getchar_and_putchar_echo_input() {
+    let src = r#"
+    int main() {
+        int c;
+        int n;
+        n = 0;
+        c = getchar();
+        while (c != -1) {
+            putchar(c);
+            n = n + 1;
+            c = getchar();
+        }
+        return n;
+    }
+    "#;
+    assert_eq!(run_with_io(src, "abc\n"), (4, "abc\n".to_string()));
+}
+
+#[test]
+fn test_scanf_reads_ints_chars_and_words() {
+    let src = r#"
+    int main() {
+        int a;
+        int b;
+        int c;
+        char *word;
+        word = malloc(16);
+        int n;
+        n = scanf("%d, %d %c %s", &a, &b, &c, word);
+        printf("%d %d %c %s\n", a, b, c, word);
+        return n;
+    }
+    "#;
+    assert_eq!(run_with_io(src, "12, -5 x hello world"), (4, "12 -5 x hello\n".to_string()));
+}
+
+#[test]
+fn test_scanf_reports_eof_and_mismatch() {
+    let src = r#"
+    int main() {
+        int a;
+        return scanf("%d", &a);
+    }
+    "#;
+    assert_eq!(run_with_io(src, "").0, -1);
+    assert_eq!(run_with_io(src, "abc").0, 0);
+}
+
+#[test]
+fn test_read_from_stdin_into_buffer() {
+    let src = r#"
+    int main() {
+        char *buf;
+        buf = malloc(32);
+        int n;
+        n = read(0, buf, 12);
+        printf("[%.*s]", n, buf);
+        n = read(0, buf, 31);
+        printf("[%.*s]", n, buf);
+        return n;
+    }
+    "#;
+    assert_eq!(run_with_io(src, "line one\nline two\n"), (6, "[line one\nlin][e two\n]".to_string()));
+}
+
+#[test]
+fn test_bytes_read_into_locals_pack_eight_to_a_slot() {
+    let src = r#"
+    int main() {
+        int null; // slot 0: its address would be NULL
+        int lo;
+        int hi;
+        int after;
+        char *p;
+        int n;
+        after = 7;
+        p = &lo;
+        n = read(0, p, 13);
+        printf("%d %c%c%c %d\n", n, p[1], p[8], *(p + 9), after);
+        p[12] = 0;
+        printf("%s|", p);
+        scanf("%s", p);
+        printf("%.12s %c %d\n", p, p[11], after);
+        return hi;
+    }
+    "#;
+    let (hi, output) = run_with_io(src, "hello, world\nabcdefghijkl");
+    assert_eq!(output, "13 eor 7\nhello, world|abcdefghijkl l 7\n");
+    // `hi` holds bytes 8..16 of the buffer as one little-endian word
+    assert_eq!(hi, i64::from_le_bytes(*b"ijkl\0\0\0\0"));
+}
+
+#[test]
+fn test_output_is_flushed_before_reading_input() {
+    use std::cell::RefCell;
+    use std::io::{Read, Write};
+    use std::rc::Rc;
+
+    /// Shows what was written only once it is flushed.
+    struct Terminal {
+        pending: Vec<u8>,
+        shown: Rc<RefCell<Vec<u8>>>,
+    }
+    impl Write for Terminal {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.pending.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.shown.borrow_mut().append(&mut self.pending);
+            Ok(())
+        }
+    }
+    /// Notes what the terminal showed each time the program waited for input.
+    struct Keyboard {
+        shown: Rc<RefCell<Vec<u8>>>,
+        prompts: Rc<RefCell<Vec<String>>>,
+    }
+    impl Read for Keyboard {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            let shown = String::from_utf8_lossy(&self.shown.borrow()).into_owned();
+            let mut prompts = self.prompts.borrow_mut();
+            if prompts.last() != Some(&shown) {
+                prompts.push(shown);
+            }
+            Ok(0)
+        }
+    }
+
+    let src = r#"
+    int main() {
+        int x;
+        char *buf;
+        buf = malloc(4);
+        printf("a? ");
+        getchar();
+        printf("b? ");
+        scanf("%d", &x);
+        printf("c? ");
+        return read(0, buf, 4);
+    }
+    "#;
+    let mut parser = Parser::new(src);
+    parser.parse();
+    let program = parser.program();
+    let mut vm = program.vm();
+    let shown = Rc::new(RefCell::new(Vec::new()));
+    let prompts = Rc::new(RefCell::new(Vec::new()));
+    vm.set_output(Terminal { pending: Vec::new(), shown: shown.clone() });
+    vm.set_input(Keyboard { shown, prompts: prompts.clone() });
+    assert_eq!(vm.run_from(&program.code, program.entry()), Some(0));
+    assert_eq!(*prompts.borrow(), ["a? ", "a? b? ", "a? b? c? "]);
+}
+
+#[test]
+fn test_exit_halts_from_nested_call() {
+    let src = r#"
+    int fail(int code) {
+        printf("failing\n");
+        exit(code);
+        printf("unreachable\n");
+        return 0;
+    }
+
+    int main() {
+        fail(3);
+        printf("unreachable\n");
+        return 0;
+    }
+    "#;
+    let program = compile(src);
+    let mut vm = program.vm();
+    let output = c4_rust::vm::SharedBuffer::new();
+    vm.set_output(output.clone());
+
+    let outcome = vm.execute(&program.code, program.entry());
+    assert_eq!(outcome, c4_rust::vm::Outcome::Halted(3));
+    assert_eq!(output.to_string_lossy(), "failing\n");
+}
+
+#[test]
+fn test_main_return_is_a_normal_outcome() {
+    let src = "int main() { return 7; }";
+    let program = compile(src);
+    let outcome = program.vm().execute(&program.code, program.entry());
+    assert_eq!(outcome, c4_rust::vm::Outcome::Returned(Some(7)));
+}
+
+#[test]
+fn test_set_args_fills_argc_and_argv() {
+    let src = r#"
+    int main(int argc, char **argv) {
+        char *first;
+        first = *argv;
+        printf("%d:%s", argc, first);
+        return argc;
+    }
+    "#;
+    let program = compile(src);
+    let mut vm = program.vm();
+    vm.set_args(&["prog.c".to_string(), "-s".to_string()]);
+    let output = c4_rust::vm::SharedBuffer::new();
+    vm.set_output(output.clone());
+
+    assert_eq!(vm.run_from(&program.code, program.entry()), Some(2));
+    assert_eq!(output.to_string_lossy(), "2:prog.c");
+}
+
+#[test]
+fn test_arguments_bind_in_order() {
+    let src = r#"
+    int sub(int a, int b) { return a - b; }
+    int main() { return sub(10, 3); }
+    "#;
+    assert_eq!(run_with_output(src).0, 7);
+}
+
+#[test]
+fn test_globals_enums_and_else() {
+    let src = r#"
+    enum { Red, Green = 5, Blue };
+    int count;
+    char *name;
+
+    void bump() { count = count + Blue; }
+
+    int main() {
+        bump(); bump();
+        name = "c4";
+        if (count == 12) printf("%s ok", name); else printf("bad");
+        return Green;
+    }
+    "#;
+    assert_eq!(run_with_output(src), (5, "c4 ok".to_string()));
+}
+
+#[test]
+fn test_pointer_arithmetic_scales_by_element_size() {
+    let src = r#"
+    int main() {
+        int *w, *start;
+        char *s;
+        start = w = malloc(4 * sizeof(int));
+        *w++ = 10; *w++ = 20;
+        w[0] = 30;
+        s = "hello";
+        printf("%d %d %c %d", start[1], *(start + 2), s[4], w - start);
+        return 0;
+    }
+    "#;
+    assert_eq!(run_with_output(src), (0, "20 30 o 2".to_string()));
+}
+
+#[test]
+fn test_increment_decrement_and_chained_assignment() {
+    let src = r#"
+    int main() {
+        int a, b, c;
+        a = b = 5;
+        c = a++ + ++b;
+        --a;
+        return a * 100 + b * 10 + c;
+    }
+    "#;
+    // a: 5 -> 6 -> 5, b: 6, c: 5 + 6
+    assert_eq!(run_with_output(src).0, 571);
+}
+
+#[test]
+fn test_logical_operators_short_circuit() {
+    let src = r#"
+    int calls;
+    int touch() { calls = calls + 1; return 1; }
+    int main() {
+        int r;
+        r = (0 && touch()) + (1 || touch()) * 10 + (2 && 3) * 100;
+        return r * 10 + calls;
+    }
+    "#;
+    assert_eq!(run_with_output(src).0, 1100); // touch() never runs
+}
+
+#[test]
+fn test_char_stores_and_memory_builtins() {
+    let src = r#"
+    int main() {
+        char *buf;
+        buf = malloc(8);
+        memset(buf, 'x', 3);
+        buf[1] = 'y';
+        buf[3] = 0;
+        printf("%s", buf);
+        return memcmp(buf, "xyx", 3) == 0 && memcmp(buf, "xyz", 3) < 0;
+    }
+    "#;
+    assert_eq!(run_with_output(src), (1, "xyx".to_string()));
+}
+
+#[test]
+fn test_block_comments_are_ignored() {
+    let source = r#"
+    /* A header comment
+       spanning several lines. */
+    int main() {
+        int x; /* trailing */
+        x = 6 /* between operands */ * 7;
+        return x; /**/
+    }
+    "#;
+    let program = compile(source);
+    assert_eq!(program.vm().run_from(&program.code, program.entry()), Some(42));
+}
+
+#[test]
+#[should_panic(expected = "line 3:3: unterminated comment")]
+fn test_unterminated_comment_is_reported() {
+    let mut parser = Parser::new("int main() {\n  return 0;\n  /* oops\n}\n");
+    parser.parse();
+}
+
+#[test]
+fn test_numeric_literal_forms() {
+    let source = r#"
+    {
+        return 0x10 + 010 + 0b11 + 1UL + (1e2 > 99.5);
+    }
+    "#;
+    assert_eq!(run_and_return(source), 16 + 8 + 3 + 1 + 1);
+}
+
+#[test]
+fn test_escape_sequences_and_string_concatenation() {
+    let source = r#"
+    {
+        char *s;
+        s = "A\x42" "\103\0D";
+        return *s + *(s + 1) + *(s + 2) + *(s + 3) + '\x10' + '\n';
+    }
+    "#;
+    assert_eq!(run_and_return(source), 65 + 66 + 67 + 16 + 10);
+}
+
+#[test]
+#[should_panic(expected = "line 3:15: Undefined variable `count`")]
+fn test_parse_errors_point_at_the_offending_token() {
+    let mut parser = Parser::new("int main() {\n    int counter;\n    counter = count + 1;\n}\n");
+    parser.parse();
+}
+
+#[test]
+fn test_instructions_map_back_to_source() {
+    let source = "int f(int a, int b) { return a - b; }\nint main() {\n  int x;\n  x = 41;\n  return f(x + 1,\n    0);\n}\n";
+    let mut parser = Parser::new(source);
+    parser.parse();
+    assert_eq!(parser.spans.len(), parser.code.len());
+    let lines_of = |wanted: fn(&Instruction) -> bool| -> Vec<usize> {
+        parser.code.iter().zip(&parser.spans)
+            .filter(|(instr, _)| wanted(instr))
+            .map(|(_, span)| span.line)
+            .collect()
+    };
+    assert_eq!(lines_of(|i| matches!(i, Instruction::Imm(41))), vec![4]);
+    assert_eq!(lines_of(|i| matches!(i, Instruction::Add)), vec![5]);
+    assert_eq!(lines_of(|i| matches!(i, Instruction::Imm(0))), vec![1, 6, 7]);
+    let imm = parser.code.iter().position(|i| matches!(i, Instruction::Imm(41))).unwrap();
+    assert_eq!(&source[parser.spans[imm].start..parser.spans[imm].end], "41");
+}
+
+#[test]
+fn test_tail_calls_run_in_constant_stack_space() {
+    let source = r#"
+        int count(int n, int acc) {
+            if (n == 0) exit(acc / 1000);
+            return count(n - 1, acc + 2);
+        }
+
+        int main() {
+            return count(1000000, 0);
+        }
+    "#;
+    let mut parser = Parser::new(source);
+    parser.parse();
+    let program = parser.program();
+    assert!(program.code.iter().any(|i| matches!(i, Instruction::TailCall(_))));
+
+    let mut vm = program.vm();
+    assert_eq!(vm.run_from(&program.code, program.entry()), Some(2000));
+    // `exit` stops the run at the bottom of the recursion, with every frame still in place
+    assert!(vm.stack.len() < 16, "stack grew to {} values", vm.stack.len());
+}
+
+#[test]
+fn test_only_returned_calls_are_tail_calls() {
+    let source = r#"
+        int id(int x) { return x; }
+        int wide(int a) { int b; int c; b = a + 1; c = b * 2; return id(c); }
+        int plus_one(int a) { return id(a) + 1; }
+        int escapes(int a) { int *p; p = &a; return id(*p); }
+        int main() { return id(wide(1)) * 10 + plus_one(2) + escapes(0); }
+    "#;
+    let mut parser = Parser::new(source);
+    parser.parse();
+    let program = parser.program();
+    let tail_calls = program.code.iter().filter(|i| matches!(i, Instruction::TailCall(_))).count();
+    // only `wide` qualifies: `plus_one` adds to the result, `escapes` hands out `&a`, and
+    // `main` has no caller to return to
+    assert_eq!(tail_calls, 1);
+    let mut vm = program.vm();
+    assert_eq!(vm.run_from(&program.code, program.entry()), Some(43));
+}
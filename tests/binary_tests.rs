@@ -0,0 +1,113 @@
+/// Tests for the `.c4b` binary format: programs survive a write/read round trip, and files
+/// that are truncated, corrupted or inconsistent are rejected rather than loaded.
+mod common;
+
+use c4_rust::binary::{MAGIC, VERSION};
+use c4_rust::instruction::{Compare, Instruction};
+use c4_rust::program::Program;
+use common::{compile, read_source};
+use std::io::ErrorKind;
+
+fn to_bytes(program: &Program) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    program.write_to(&mut bytes).unwrap();
+    bytes
+}
+
+fn read(bytes: &[u8]) -> std::io::Result<Program> {
+    Program::read_from(&mut &bytes[..])
+}
+
+/// The message `read` rejects `bytes` with.
+fn rejection(bytes: &[u8]) -> String {
+    let error = read(bytes).expect_err("corrupt file was accepted");
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    error.to_string()
+}
+
+#[test]
+fn test_round_trip_compiled_programs() {
+    for path in ["input/hello.c", "input/test_all_features.c", "input/c4.c"] {
+        let program = compile(&read_source(path));
+        assert_eq!(read(&to_bytes(&program)).unwrap(), program, "{}", path);
+    }
+}
+
+#[test]
+fn test_round_trip_every_instruction() {
+    use Instruction::*;
+    let code = vec![
+        Imm(0), Imm(-1), Imm(i64::MIN), Imm(i64::MAX), ImmF(-2.5), PushF(f64::INFINITY),
+        PrintStr("tab\t\u{e9}".to_string()), Addr(3), Printf(2), Scanf(1), Open(3),
+        Label(7), Jmp(7), Jz(7), Jnz(7), LoadLocal(300), StoreLocal(1), Call(0), Enter(2),
+        Add, Sub, Mul, Div, Mod, Neg, Not, Deref, Cast, DerefChar, Store, StoreChar, Dup, Pop,
+        Eq, Ne, Lt, Gt, Le, Ge, BitAnd, BitOr, BitXor, Shl, Shr, Print, PrintF, Getchar,
+        Putchar, Read, Write, Close, Exit, Malloc, Free, Memset, Memcmp, Leave,
+        AddLocal(2, -9), CmpJz(Compare::Ge, 7), TailCall(0),
+    ];
+    let program = Program { code, ..Program::default() };
+    assert_eq!(read(&to_bytes(&program)).unwrap(), program);
+}
+
+#[test]
+fn test_debug_info_is_optional() {
+    let mut program = compile("int main() { return 1; }");
+    let with_debug = to_bytes(&program);
+    program.spans.clear();
+    let stripped = to_bytes(&program);
+    assert!(stripped.len() < with_debug.len());
+    assert_eq!(read(&stripped).unwrap(), program);
+}
+
+#[test]
+fn test_header_is_checked() {
+    let bytes = to_bytes(&compile("int main() { return 1; }"));
+    assert!(bytes.starts_with(MAGIC));
+    assert_eq!(rejection(b"int main() {}"), "not a c4b file (bad magic number)");
+
+    let mut future = bytes.clone();
+    future[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert_eq!(rejection(&future), format!("unsupported c4b version {} (expected {})", VERSION + 1, VERSION));
+}
+
+#[test]
+fn test_truncated_and_corrupted_files_are_rejected() {
+    let bytes = to_bytes(&compile(&read_source("input/test_all_features.c")));
+    for len in MAGIC.len()..bytes.len() {
+        rejection(&bytes[..len]);
+    }
+    for i in 8..bytes.len() {
+        let mut corrupted = bytes.clone();
+        corrupted[i] ^= 0x20;
+        rejection(&corrupted);
+    }
+}
+
+#[test]
+fn test_dangling_references_are_rejected() {
+    let base = compile("int g; int f() { return 0; } int main() { if (g) return f(); return 1; }");
+    let mut cases: Vec<(Program, &str)> = Vec::new();
+
+    let mut program = base.clone();
+    let call = program.code.iter().position(|i| matches!(i, Instruction::Call(_))).unwrap();
+    program.code[call] = Instruction::Call(10_000);
+    cases.push((program, "calls address 10000 outside the code"));
+
+    let mut program = base.clone();
+    let jz = program.code.iter().position(|i| matches!(i, Instruction::Jz(_))).unwrap();
+    program.code[jz] = Instruction::Jz(12_345);
+    cases.push((program, "jumps to missing label 12345"));
+
+    let mut program = base.clone();
+    program.functions.insert("f".to_string(), 10_000);
+    cases.push((program, "function `f` starts at 10000 outside the code"));
+
+    let mut program = base.clone();
+    program.globals.insert("g".to_string(), 0x2000_0000);
+    cases.push((program, "global `g` is outside the data section"));
+
+    for (program, message) in cases {
+        let error = rejection(&to_bytes(&program));
+        assert!(error.contains(message), "{}", error);
+    }
+}
@@ -0,0 +1,228 @@
+/// End-to-end tests of the `c4_rust` executable: they compile a C file from a scratch
+/// directory, run it, and check the process output and exit status.
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn write_source(name: &str, source: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("c4_rust_cli_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, source).unwrap();
+    path
+}
+
+fn c4_rust(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_c4_rust"))
+        .args(args)
+        .output()
+        .expect("failed to run c4_rust")
+}
+
+#[test]
+fn test_main_return_value_is_exit_status() {
+    let src = write_source("ret.c", "int main() { printf(\"hi\\n\"); return 42; }");
+    let out = c4_rust(&[src.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(42));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "hi\n");
+}
+
+#[test]
+fn test_exit_call_is_exit_status() {
+    let src = write_source(
+        "exit.c",
+        "int f() { exit(3); return 0; } int main() { f(); return 0; }",
+    );
+    let out = c4_rust(&[src.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(3));
+}
+
+#[test]
+fn test_extra_arguments_reach_main() {
+    let src = write_source(
+        "args.c",
+        r#"
+        int main(int argc, char **argv) {
+            printf("argc=%d first=%s\n", argc, *argv);
+            return argc;
+        }
+        "#,
+    );
+    let path = src.to_str().unwrap();
+    let out = c4_rust(&[path, "one", "two"]);
+    assert_eq!(out.status.code(), Some(3));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        format!("argc=3 first={}\n", path)
+    );
+}
+
+#[test]
+fn test_preprocessor_options_and_errors() {
+    let src = write_source(
+        "defines.c",
+        "#ifndef BASE\n#error BASE is required\n#endif\nint main() { return BASE + EXTRA; }",
+    );
+    let path = src.to_str().unwrap();
+    let out = c4_rust(&["-D", "BASE=40", "-DEXTRA", path]);
+    assert_eq!(out.status.code(), Some(41));
+
+    let out = c4_rust(&[path]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        format!(
+            "error: #error BASE is required\n --> {}:2\n  |\n2 | #error BASE is required\n  | {}\n\n",
+            path,
+            "^".repeat(23)
+        )
+    );
+}
+
+#[test]
+fn test_unmodified_c4_runs_hello() {
+    let root = env!("CARGO_MANIFEST_DIR");
+    let out = c4_rust(&[
+        "--allow-read",
+        &format!("{}/input", root),
+        &format!("{}/input/c4.c", root),
+        &format!("{}/input/hello.c", root),
+    ]);
+    assert_eq!(out.status.code(), Some(42));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "Hello, world!\nexit(42) cycle = 9\n"
+    );
+}
+
+#[test]
+fn test_compile_errors_are_rendered() {
+    let src = write_source("typo.c", "int main() {\n  int total;\n  return totl;\n}\n");
+    let path = src.to_str().unwrap();
+    let out = c4_rust(&[path]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        format!(
+            "error: Undefined variable `totl`\n --> {}:3:10\n  |\n3 |   return totl;\n  |          ^^^^\n  = help: did you mean `total`?\n\n",
+            path
+        )
+    );
+}
+
+#[test]
+fn test_dump_ast() {
+    let src = write_source("dump.c", "#define N 2\nint main() { return N*(1+2); }\n");
+    let path = src.to_str().unwrap();
+    let out = c4_rust(&["--dump-ast", path]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "int main() {\n    return 2 * (1 + 2);\n}\n");
+
+    let out = c4_rust(&["--dump-ast=json", path]);
+    assert_eq!(out.status.code(), Some(0));
+    let json = String::from_utf8_lossy(&out.stdout);
+    assert!(json.contains(r#""node": "Binary""#), "{}", json);
+    assert!(json.contains(r#""span": { "line": 2, "column": 21, "start": 21, "end": 28 }"#), "{}", json);
+
+    // syntax errors are rendered as when compiling
+    let src = write_source("dump_error.c", "int main() { return 1 +; }\n");
+    let out = c4_rust(&["--dump-ast", src.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("error: Unexpected token in expression"));
+}
+
+#[test]
+fn test_disasm_command() {
+    let src = write_source("disasm.c", "int main() {\n  printf(\"%d\\n\", 6 * 7);\n  return 0;\n}\n");
+    let out = c4_rust(&["disasm", src.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    let listing = String::from_utf8_lossy(&out.stdout);
+    assert!(listing.starts_with("; data: 4 bytes at 0x10000000\n;   0x10000000  string \"%d\\n\"\n"), "{}", listing);
+    assert!(listing.contains("\nmain/0:\n      ;    1 | int main() {\n    0  Enter 0 "), "{}", listing);
+    assert!(listing.contains("      ;    2 |   printf(\"%d\\n\", 6 * 7);\n"), "{}", listing);
+    assert!(listing.contains("  Printf 2\n"), "{}", listing);
+    assert!(out.stderr.is_empty(), "nothing is run");
+}
+
+#[test]
+fn test_compile_then_run_bytecode() {
+    let src = write_source(
+        "saved.c",
+        "int main(int argc, char **argv) { printf(\"%s\\n\", argv[1]); return argc + 40; }",
+    );
+    let binary = src.with_extension("c4b");
+    let out = c4_rust(&["compile", "-o", binary.to_str().unwrap(), src.to_str().unwrap()]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    fs::remove_file(&src).unwrap(); // running must not need the source
+
+    let out = c4_rust(&["run", binary.to_str().unwrap(), "saved"]);
+    assert_eq!(out.status.code(), Some(42), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "saved\n");
+
+    let listing = c4_rust(&["disasm", binary.to_str().unwrap()]);
+    assert!(String::from_utf8_lossy(&listing.stdout).contains("main/2:\n"));
+
+    let mut bytes = fs::read(&binary).unwrap();
+    bytes.truncate(bytes.len() - 3);
+    let truncated = write_source("truncated.c4b", "");
+    fs::write(&truncated, bytes).unwrap();
+    let out = c4_rust(&["run", truncated.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("checksum mismatch"));
+}
+
+#[test]
+fn test_malformed_bytecode_is_rejected_before_anything_else() {
+    // `main` has no caller for `Leave` to return to
+    let program = c4_rust::asm::assemble(".func main 0\n    Enter 0\n    Imm 1\n    Leave\n").unwrap();
+    let binary = write_source("malformed.c4b", "");
+    program.write_to(&mut fs::File::create(&binary).unwrap()).unwrap();
+    for command in ["run", "disasm"] {
+        let out = c4_rust(&[command, "-O2", binary.to_str().unwrap()]);
+        assert_eq!(out.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&out.stderr).contains("malformed program: instruction 2"));
+    }
+}
+
+#[test]
+fn test_optimization_level() {
+    let src = write_source("fold.c", "int main() { return 3 + 4 * 2 - 5; }");
+    let listing = |level: &str| {
+        let out = c4_rust(&["disasm", level, src.to_str().unwrap()]);
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+    assert!(listing("-O0").contains("Mul"));
+    assert!(listing("-O1").contains("Imm 6\n"));
+    assert_eq!(c4_rust(&["-O", src.to_str().unwrap()]).status.code(), Some(6));
+}
+
+#[test]
+fn test_dead_code_warnings() {
+    let src = write_source("dead.c", "int unused() { return 1; }\nint main() {\n  return 2;\n  print(3);\n}\n");
+    let path = src.to_str().unwrap();
+    let out = c4_rust(&["-Wdead-code", path]);
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        format!(
+            "warning: function `unused` is never called\n --> {0}:1:1\n  |\n1 | int unused() {{ return 1; }}\n  \
+             | ^^^^^^^^^^^^^^^^^^^^^^^^^^\n\nwarning: unreachable code\n --> {0}:4:3\n  |\n4 |   print(3);\n  \
+             |   ^^^^^^^^^\n\n\nProgram result: 2\n",
+            path
+        )
+    );
+    // without the flag the dead code is dropped silently
+    let out = c4_rust(&["-O1", path]);
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("warning"));
+}
+
+#[test]
+fn test_lexer_warnings_are_shown() {
+    let src = write_source("multichar.c", "int main() {\n  return 'ab' - 24930;\n}\n");
+    let out = c4_rust(&[src.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.starts_with("warning: multi-character character constant\n"), "{}", stderr);
+    assert!(stderr.contains("2 |   return 'ab' - 24930;\n  |          ^^^^\n"), "{}", stderr);
+}
@@ -0,0 +1,20 @@
+//! Helpers shared by the integration tests. Each test crate uses only some of them.
+#![allow(dead_code)]
+
+use c4_rust::parser::Parser;
+use c4_rust::preprocessor::Preprocessor;
+use c4_rust::program::Program;
+use std::path::Path;
+
+/// Compile `source` (which must be valid) into a program.
+pub fn compile(source: &str) -> Program {
+    let mut parser = Parser::new(source);
+    parser.parse();
+    parser.program()
+}
+
+/// The preprocessed text of the C file at `path`, relative to the crate root.
+pub fn read_source(path: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+    Preprocessor::new().preprocess_file(&path).unwrap_or_else(|e| panic!("{}", e)).text
+}
@@ -0,0 +1,123 @@
+/// Tests for diagnostic rendering: headers, source snippets with carets, notes and help,
+/// colors, and mapping preprocessed lines back to the files they came from.
+use c4_rust::diagnostic::{Diagnostic, Renderer};
+use c4_rust::parser::Parser;
+use c4_rust::preprocessor::Preprocessor;
+use c4_rust::token::Span;
+
+fn parse_errors(source: &str) -> Vec<Diagnostic> {
+    Parser::new(source).try_parse().expect_err("source should not compile")
+}
+
+#[test]
+fn test_undefined_variable_suggests_a_local() {
+    let source = "int main() {\n    int counter;\n    counter = count + 1;\n    return counter;\n}\n";
+    let errors = parse_errors(source);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].help(), Some("did you mean `counter`?"));
+    assert_eq!(
+        Renderer::new("prog.c", source).render(&errors[0]),
+        "error: Undefined variable `count`\n \
+         --> prog.c:3:15\n  \
+         |\n\
+         3 |     counter = count + 1;\n  \
+         |               ^^^^^\n  \
+         = help: did you mean `counter`?\n"
+    );
+}
+
+#[test]
+fn test_unknown_function_suggests_a_function() {
+    let errors = parse_errors("int square(int x) { return x * x; }\nint main() { return sqaure(3); }\n");
+    assert_eq!(errors[0].message, "Unknown function `sqaure`");
+    assert_eq!(errors[0].help(), Some("did you mean `square`?"));
+
+    // nothing close enough: no help
+    let errors = parse_errors("int main() { int alpha; return zzz; }");
+    assert_eq!(errors[0].help(), None);
+}
+
+#[test]
+fn test_notes_and_tabs() {
+    let source = "int main() {\n\treturn 1 +;\n}\n";
+    let errors = parse_errors(source);
+    let diagnostic = errors[0].clone().with_note("expressions need an operand after `+`");
+    assert_eq!(
+        Renderer::new("t.c", source).render(&diagnostic),
+        "error: Unexpected token in expression: Semicolon\n \
+         --> t.c:2:12\n  \
+         |\n\
+         2 | \treturn 1 +;\n  \
+         | \t          ^\n  \
+         = note: expressions need an operand after `+`\n"
+    );
+}
+
+#[test]
+fn test_carets_count_characters_not_bytes() {
+    let source = "/* é */ s = \"héé\";";
+    let span = Span { file_id: 0, start: 13, end: 20, line: 1, column: 13 };
+    assert_eq!(&source[span.start..span.end], "\"héé\"");
+    assert_eq!(
+        Renderer::new("u.c", source).render(&Diagnostic::at("bad string", span)),
+        "error: bad string\n \
+         --> u.c:1:13\n  \
+         |\n\
+         1 | /* é */ s = \"héé\";\n  \
+         |             ^^^^^\n"
+    );
+}
+
+#[test]
+fn test_color_output() {
+    let source = "x";
+    let span = Span { file_id: 0, start: 0, end: 1, line: 1, column: 1 };
+    let out = Renderer::new("c.c", source).color(true).render(&Diagnostic::at("bad", span));
+    assert!(out.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: bad\x1b[0m\n"), "{:?}", out);
+    assert!(out.contains("\x1b[1;31m^\x1b[0m"), "{:?}", out);
+}
+
+#[test]
+fn test_lines_without_spans_and_other_files() {
+    let source = "int x;\n  #bogus thing\n";
+    let diagnostic = Diagnostic::new("unknown directive", 2);
+    assert_eq!(
+        Renderer::new("p.c", source).render(&diagnostic),
+        "error: unknown directive\n --> p.c:2\n  |\n2 |   #bogus thing\n  |   ^^^^^^^^^^^^\n"
+    );
+    // a diagnostic about some other file only gets a header
+    let elsewhere = diagnostic.in_file("inc.h");
+    assert_eq!(
+        Renderer::new("p.c", source).render(&elsewhere),
+        "error: unknown directive\n --> inc.h:2\n"
+    );
+}
+
+#[test]
+fn test_preprocessed_locations_map_to_original_files() {
+    let dir = std::env::temp_dir().join(format!("c4_rust_diag_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("lib.h"), "// helpers\nint twice(int v) { return v + w; }\n").unwrap();
+    let main = dir.join("main.c");
+    std::fs::write(&main, "#include \"lib.h\"\nint main() { return twice(2); }\n").unwrap();
+
+    let preprocessed = Preprocessor::new().preprocess_file(&main).unwrap();
+    let errors = parse_errors(&preprocessed.text);
+    let out = Renderer::preprocessed(&preprocessed).render(&errors[0]);
+    let header = format!(" --> {}:2:31\n", dir.join("lib.h").display());
+    assert!(out.contains(&header), "{}", out);
+    assert!(out.contains("int twice(int v) { return v + w; }"), "{}", out);
+    assert!(out.contains("= help: did you mean `v`?"), "{}", out);
+}
+
+#[test]
+fn test_lexer_errors_are_collected() {
+    let errors = parse_errors("int main() { return 08; }\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, "invalid digit '8' in octal literal `08`");
+
+    // running out of input after an unterminated comment is not reported again
+    let errors = parse_errors("int main() {\n  /* oops\n  return 0;\n}\n");
+    let messages: Vec<&str> = errors.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages, vec!["unterminated comment"]);
+}
@@ -0,0 +1,88 @@
+/// Tests for the bytecode disassembler: function headers, resolved operands, the data
+/// section and interleaved source lines.
+mod common;
+
+use c4_rust::disasm::disassemble;
+use common::compile;
+
+#[test]
+fn test_listing_with_source_lines() {
+    let source = "char *msg;\nint twice(int n) {\n  return n + n;\n}\nint main() {\n  msg = \"hi\\n\";\n  return twice(2);\n}\n";
+    assert_eq!(
+        disassemble(&compile(source), Some(source)),
+        "\
+; data: 12 bytes at 0x10000000
+;   0x10000000  global msg
+;   0x10000008  string \"hi\\n\"
+
+twice/1:
+      ;    2 | int twice(int n) {
+    0  Enter 1                     ; frame: 1 slot
+      ;    3 |   return n + n;
+    1  LoadLocal 0
+    2  LoadLocal 0
+    3  Add
+    4  Leave
+      ;    4 | }
+    5  Imm 0
+    6  Leave
+
+main/0:
+      ;    5 | int main() {
+    7  Enter 0                     ; frame: 0 slots
+      ;    6 |   msg = \"hi\\n\";
+    8  Imm 268435456               ; &msg
+    9  Imm 268435464               ; \"hi\\n\"
+   10  Store
+   11  Pop
+      ;    7 |   return twice(2);
+   12  Imm 2
+   13  Call 0                      ; twice/1
+   14  Jmp L9999                   ; -> 17 (exit)
+      ;    8 | }
+   15  Imm 0
+   16  Jmp L9999                   ; -> 17 (exit)
+   17  L9999:
+"
+    );
+}
+
+#[test]
+fn test_display_resolves_jumps_without_source() {
+    let program = compile("int main() {\n  int i;\n  while (i < 3) i++;\n  print(\"done\");\n  return i;\n}\n");
+    let listing = program.to_string();
+    assert!(listing.starts_with("main/0:\n      ; line 1\n    0  Enter 1 "), "{}", listing);
+
+    // every jump names the index of its label
+    let index_of = |label: &str| {
+        listing
+            .lines()
+            .find(|line| line.trim_end().ends_with(&format!(" {}:", label)))
+            .and_then(|line| line.split_whitespace().next())
+            .unwrap_or_else(|| panic!("no {} in\n{}", label, listing))
+            .to_string()
+    };
+    let jumps: Vec<&str> = listing.lines().filter(|line| line.contains("Jz") || line.contains("Jmp")).collect();
+    assert_eq!(jumps.len(), 4, "{}", listing);
+    for jump in jumps {
+        let label = jump.split_whitespace().nth(2).unwrap();
+        assert!(jump.contains(&format!("; -> {}", index_of(label))), "{}", jump);
+    }
+    assert!(listing.contains("PrintStr \"done\"\n"), "{}", listing);
+    assert!(listing.contains("      ; line 3\n"), "{}", listing);
+}
+
+#[test]
+fn test_top_level_code_gets_a_header() {
+    let listing = compile("int x;\n{ x = 2; }\n").to_string();
+    assert!(listing.contains("\n<top level>:\n"), "{}", listing);
+    assert!(listing.contains("Imm 268435456               ; &x\n"), "{}", listing);
+}
+
+#[test]
+fn test_strings_outside_the_data_section() {
+    let mut program = compile("int main() { printf(\"hi\"); return 0; }");
+    program.strings.push(16);
+    let listing = program.to_string();
+    assert!(listing.contains(";   0x10  string outside the data section\n"), "{}", listing);
+}
@@ -0,0 +1,161 @@
+/// Tests for parser error recovery: a source with several independent mistakes reports each
+/// of them once, in order, without follow-on errors caused by the earlier ones.
+use c4_rust::parser::Parser;
+
+/// The (line, message) of every diagnostic for `source`.
+fn errors(source: &str) -> Vec<(usize, String)> {
+    Parser::new(source)
+        .try_parse()
+        .expect_err("source should not compile")
+        .into_iter()
+        .map(|d| (d.line, d.message))
+        .collect()
+}
+
+fn error(line: usize, message: &str) -> (usize, String) {
+    (line, message.to_string())
+}
+
+#[test]
+fn test_several_statement_errors_in_one_function() {
+    let source = r#"
+int main() {
+    int total;
+    total = 1 +;
+    total = totl * 2;
+    if (total > 3 { total = 0; }
+    printf("%d\n", total)
+    return total;
+}
+"#;
+    assert_eq!(
+        errors(source),
+        vec![
+            error(4, "Unexpected token in expression: Semicolon"),
+            error(5, "Undefined variable `totl`"),
+            error(6, "Expected ')' after 'if' condition"),
+            error(8, "Expected semicolon after expression"),
+        ]
+    );
+}
+
+#[test]
+fn test_errors_in_separate_functions_and_globals() {
+    let source = r#"
+int counter
+int helper(int a) {
+    return a + missing;
+}
+char *name;
+int broken(int a,, int b) {
+    return a + b;
+}
+int main() {
+    return helper(1) + undefined_fn(2);
+}
+"#;
+    assert_eq!(
+        errors(source),
+        vec![
+            error(3, "Expected ';' after global declaration"),
+            error(4, "Undefined variable `missing`"),
+            error(7, "Expected ')' after parameters"),
+            error(11, "Unknown function `undefined_fn`"),
+        ]
+    );
+}
+
+#[test]
+fn test_nested_blocks_recover_locally() {
+    let source = r#"
+int main() {
+    int i;
+    i = 0;
+    while (i < 10) {
+        if (i == 5) {
+            i = i + ;
+        }
+        i = i + 1;
+        j = 2;
+    }
+    return i;
+}
+"#;
+    assert_eq!(
+        errors(source),
+        vec![
+            error(7, "Unexpected token in expression: Semicolon"),
+            error(10, "Undefined variable `j`"),
+        ]
+    );
+}
+
+#[test]
+fn test_no_cascade_at_end_of_input() {
+    // the missing `}` is reported once, not once per enclosing block
+    assert_eq!(
+        errors("int main() {\n    while (1) {\n        return 0;\n"),
+        vec![error(4, "Expected '}' before end of input")]
+    );
+
+    // a broken expression that swallows the rest of the file is reported once
+    assert_eq!(
+        errors("int main() {\n    return (1 + 2;\n}\n"),
+        vec![error(2, "Expected ')' after expression")]
+    );
+}
+
+#[test]
+fn test_lexer_and_parser_errors_together() {
+    let source = "int main() {\n    int x;\n    x = 09;\n    x = x +* 2;\n    return '';\n}\n";
+    assert_eq!(
+        errors(source),
+        vec![
+            error(3, "invalid digit '9' in octal literal `09`"),
+            error(4, "Cannot dereference a non-pointer value"),
+            error(5, "empty character constant"),
+        ]
+    );
+}
+
+#[test]
+fn test_conditional_branches_must_have_compatible_types() {
+    assert_eq!(
+        errors("int main() {\n    char *s;\n    return 1 ? s : 1.5;\n}\n"),
+        vec![error(3, "Mismatched types `char *` and `float` in conditional expression")]
+    );
+}
+
+#[test]
+fn test_arguments_must_be_separated_by_commas() {
+    assert_eq!(
+        errors("int f(int a, int b) { return a; }\nint main() {\n    f(1 2);\n    f(1, 2,);\n    return y;\n}\n"),
+        vec![
+            error(3, "Expected ',' or ')' after function argument"),
+            error(4, "Unexpected token in expression: RParen"),
+            error(5, "Undefined variable `y`"),
+        ]
+    );
+}
+
+#[test]
+fn test_calls_must_pass_every_parameter() {
+    assert_eq!(
+        errors("int add(int a, int b) { return a + b; }\nint main() {\n    return add(1) + 1;\n}\n"),
+        vec![error(3, "`add` takes 2 argument(s) but 1 were given")]
+    );
+    // a call in tail position too
+    assert_eq!(
+        errors("int add(int a, int b) { return a + b; }\nint f() {\n    return add(1, 2, 3);\n}\n"),
+        vec![error(3, "`add` takes 2 argument(s) but 3 were given")]
+    );
+}
+
+#[test]
+fn test_valid_code_after_errors_still_compiles() {
+    let mut parser = Parser::new("int bad( { }\nint good(int a) { return a * 2; }\nint main() { return good(21); }\n");
+    assert!(parser.try_parse().is_err());
+    assert_eq!(parser.diagnostics.len(), 1);
+    assert!(parser.functions.contains_key("good"));
+    assert!(parser.functions.contains_key("main"));
+}
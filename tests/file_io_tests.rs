@@ -0,0 +1,124 @@
+/// Tests for the `open`/`read`/`write`/`close` builtins and the VM's file allow-list.
+/// Each test works in its own scratch directory under the system temp dir.
+mod common;
+
+use c4_rust::vm::SharedBuffer;
+use common::compile;
+use std::fs;
+use std::path::PathBuf;
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("c4_rust_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run_sandboxed(source: &str, readable: &[&PathBuf], writable: &[&PathBuf]) -> (i64, String) {
+    let program = compile(source);
+    let mut vm = program.vm();
+    for path in readable {
+        vm.allow_read(path);
+    }
+    for path in writable {
+        vm.allow_write(path);
+    }
+    let output = SharedBuffer::new();
+    vm.set_output(output.clone());
+
+    let result = vm.run_from(&program.code, program.entry());
+    (result.unwrap(), output.to_string_lossy())
+}
+
+#[test]
+fn test_read_allowed_file() {
+    let dir = scratch_dir("read");
+    let file = dir.join("input.txt");
+    fs::write(&file, "hello from the host").unwrap();
+
+    let src = r#"
+    int main() {
+        int fd;
+        char *buf;
+        int n;
+        buf = malloc(64);
+        fd = open("PATH", 0);
+        n = read(fd, buf, 10);
+        printf("%.*s|", n, buf);
+        n = read(fd, buf, 64);
+        printf("%.*s|", n, buf);
+        printf("%d", read(fd, buf, 64));
+        return close(fd);
+    }
+    "#
+    .replace("PATH", file.to_str().unwrap());
+    assert_eq!(
+        run_sandboxed(&src, &[&file], &[]),
+        (0, "hello from| the host|0".to_string())
+    );
+}
+
+#[test]
+fn test_write_then_read_back() {
+    let dir = scratch_dir("write");
+    let file = dir.join("out.txt");
+
+    // O_WRONLY | O_CREAT | O_TRUNC
+    let src = r#"
+    int main() {
+        int fd;
+        fd = open("PATH", 577, 420);
+        write(fd, "written by c4", 13);
+        close(fd);
+        write(1, "ok", 2);
+        return fd;
+    }
+    "#
+    .replace("PATH", file.to_str().unwrap());
+    assert_eq!(run_sandboxed(&src, &[], &[&dir]), (3, "ok".to_string()));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "written by c4");
+}
+
+#[test]
+fn test_paths_outside_allow_list_are_refused() {
+    let dir = scratch_dir("denied");
+    let secret = dir.join("secret.txt");
+    fs::write(&secret, "top secret").unwrap();
+    let allowed = dir.join("public");
+    fs::create_dir_all(&allowed).unwrap();
+
+    let src = r#"
+    int main() {
+        printf("%d ", open("SECRET", 0));
+        printf("%d ", open("ESCAPE", 0));
+        printf("%d ", open("SECRET", 1));
+        printf("%d", close(42));
+        return 0;
+    }
+    "#
+    .replace("SECRET", secret.to_str().unwrap())
+    .replace("ESCAPE", allowed.join("../secret.txt").to_str().unwrap());
+    assert_eq!(run_sandboxed(&src, &[&allowed], &[&allowed]).1, "-1 -1 -1 -1");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_dangling_symlinks_are_not_followed() {
+    let dir = scratch_dir("symlink");
+    let outside = dir.join("outside.txt");
+    let allowed = dir.join("public");
+    fs::create_dir_all(&allowed).unwrap();
+    std::os::unix::fs::symlink(&outside, allowed.join("link.txt")).unwrap();
+
+    // O_WRONLY | O_CREAT | O_TRUNC through a link to a file that doesn't exist yet
+    let src = r#"
+    int main() {
+        printf("%d ", open("LINK", 577, 420));
+        printf("%d", open("LINK", 0));
+        return 0;
+    }
+    "#
+    .replace("LINK", allowed.join("link.txt").to_str().unwrap());
+    assert_eq!(run_sandboxed(&src, &[&allowed], &[&allowed]).1, "-1 -1");
+    assert!(!outside.exists());
+}
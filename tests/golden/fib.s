@@ -0,0 +1,29 @@
+.func fib 1
+    Enter 1
+    LoadLocal 0
+    Imm 2
+    Lt
+    Jz L0
+    LoadLocal 0
+    Leave
+L0:
+    LoadLocal 0
+    Imm 1
+    Sub
+    Call fib
+    LoadLocal 0
+    Imm 2
+    Sub
+    Call fib
+    Add
+    Leave
+    Imm 0
+    Leave
+.func main 0
+    Enter 0
+    Imm 10
+    Call fib
+    Jmp L9999
+    Imm 0
+    Jmp L9999
+L9999:
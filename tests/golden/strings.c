@@ -0,0 +1,16 @@
+char *greeting;
+int count;
+
+int length(char *s) {
+  int n;
+  n = 0;
+  while (*s) { s = s + 1; n = n + 1; }
+  return n;
+}
+
+int main() {
+  greeting = "hello\n";
+  count = length(greeting) + length("ok");
+  printf("%s", greeting);
+  return count;
+}
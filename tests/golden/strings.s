@@ -0,0 +1,56 @@
+.global greeting
+.global count
+.string str.0 "hello\n"
+.string str.1 "ok"
+.string str.2 "%s"
+.func length 1
+    Enter 2
+    Imm 0
+    StoreLocal 1
+    Pop
+L0:
+    LoadLocal 0
+    DerefChar
+    Jz L1
+    LoadLocal 0
+    Imm 1
+    Add
+    StoreLocal 0
+    Pop
+    LoadLocal 1
+    Imm 1
+    Add
+    StoreLocal 1
+    Pop
+    Jmp L0
+L1:
+    LoadLocal 1
+    Leave
+    Imm 0
+    Leave
+.func main 0
+    Enter 0
+    Imm greeting
+    Imm str.0
+    Store
+    Pop
+    Imm count
+    Imm greeting
+    Deref
+    Call length
+    Imm str.1
+    Call length
+    Add
+    Store
+    Pop
+    Imm greeting
+    Deref
+    Imm str.2
+    Printf 2
+    Pop
+    Imm count
+    Deref
+    Jmp L9999
+    Imm 0
+    Jmp L9999
+L9999:
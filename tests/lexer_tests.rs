@@ -1,5 +1,5 @@
 use c4_rust::lexer::Lexer;
-use c4_rust::token::TokenKind;
+use c4_rust::token::{Span, TokenKind};
 
 fn tokenize_kinds(input: &str) -> Vec<TokenKind> {
     let mut lexer = Lexer::new(input);
@@ -41,7 +41,7 @@ fn test_operators() {
 fn test_string_literal() {
     let input = r#" "hello\nworld" "#;
     let expected = vec![
-        TokenKind::String("hello\nworld".to_string()),
+        TokenKind::String(b"hello\nworld".to_vec()),
         TokenKind::Eof,
     ];
     assert_eq!(tokenize_kinds(input), expected);
@@ -81,3 +81,244 @@ fn test_skips_comments() {
 
     assert_eq!(tokenize_kinds(input), expected);
 }
+
+#[test]
+fn test_skips_block_comments() {
+    let input = "/* leading */ int /* inline */ x /* spans\n two\n lines */ ; /**/ /***/ y";
+    let mut lexer = Lexer::new(input);
+    let mut tokens = Vec::new();
+    loop {
+        let tok = lexer.next_token();
+        if matches!(tok.kind, TokenKind::Eof) {
+            break;
+        }
+        tokens.push((tok.kind, tok.span.line));
+    }
+    assert_eq!(
+        tokens,
+        vec![
+            (TokenKind::Int, 1),
+            (TokenKind::Id("x".to_string()), 1),
+            (TokenKind::Semicolon, 3),
+            (TokenKind::Id("y".to_string()), 3),
+        ]
+    );
+    assert!(lexer.diagnostics.is_empty());
+}
+
+#[test]
+fn test_unterminated_block_comment_is_an_error() {
+    let mut lexer = Lexer::new("int x;\n/* never closed *\n");
+    let kinds: Vec<TokenKind> = std::iter::from_fn(|| {
+        let tok = lexer.next_token();
+        (tok.kind != TokenKind::Eof).then_some(tok.kind)
+    })
+    .collect();
+    assert_eq!(kinds, vec![TokenKind::Int, TokenKind::Id("x".to_string()), TokenKind::Semicolon]);
+    assert_eq!(lexer.diagnostics.len(), 1);
+    assert_eq!(lexer.diagnostics[0].line, 2);
+    assert_eq!(lexer.diagnostics[0].message, "unterminated comment");
+}
+
+#[test]
+fn test_directives_left_for_the_lexer_are_errors() {
+    let mut lexer = Lexer::new("#include <stdio.h>\nint x;\n");
+    let kinds: Vec<TokenKind> = std::iter::from_fn(|| {
+        let tok = lexer.next_token();
+        (tok.kind != TokenKind::Eof).then_some(tok.kind)
+    })
+    .collect();
+    assert_eq!(kinds, vec![TokenKind::Int, TokenKind::Id("x".to_string()), TokenKind::Semicolon]);
+    assert_eq!(lexer.diagnostics.len(), 1);
+    assert_eq!(lexer.diagnostics[0].line, 1);
+    assert_eq!(
+        lexer.diagnostics[0].message,
+        "stray `#` in program (directives are handled by the preprocessor)"
+    );
+}
+
+#[test]
+fn test_stray_characters_are_errors() {
+    let mut lexer = Lexer::new("x @ y;\ns.f $\n");
+    let kinds: Vec<TokenKind> = std::iter::from_fn(|| {
+        let tok = lexer.next_token();
+        (tok.kind != TokenKind::Eof).then_some(tok.kind)
+    })
+    .collect();
+    let id = |name: &str| TokenKind::Id(name.to_string());
+    assert_eq!(kinds, vec![id("x"), id("y"), TokenKind::Semicolon, id("s"), id("f")]);
+    let errors: Vec<(usize, &str)> = lexer.diagnostics.iter().map(|d| (d.line, d.message.as_str())).collect();
+    assert_eq!(
+        errors,
+        vec![(1, "stray `@` in program"), (2, "stray `.` in program"), (2, "stray `$` in program")]
+    );
+}
+
+#[test]
+fn test_integer_literal_forms() {
+    let input = "0 42 0xFF 0X1f 0777 0b1010 10UL 7u 3ll 0xFFFFFFFFFFFFFFFF 9223372036854775807";
+    let expected = vec![
+        TokenKind::Num(0),
+        TokenKind::Num(42),
+        TokenKind::Num(255),
+        TokenKind::Num(31),
+        TokenKind::Num(511),
+        TokenKind::Num(10),
+        TokenKind::Num(10),
+        TokenKind::Num(7),
+        TokenKind::Num(3),
+        TokenKind::Num(-1),
+        TokenKind::Num(i64::MAX),
+        TokenKind::Eof,
+    ];
+    assert_eq!(tokenize_kinds(input), expected);
+}
+
+#[test]
+fn test_float_literal_forms() {
+    let input = "1.5 .25 3. 1e-9 2.5E3 1e+2 0.5f";
+    let expected = vec![
+        TokenKind::Float(1.5),
+        TokenKind::Float(0.25),
+        TokenKind::Float(3.0),
+        TokenKind::Float(1e-9),
+        TokenKind::Float(2500.0),
+        TokenKind::Float(100.0),
+        TokenKind::Float(0.5),
+        TokenKind::Eof,
+    ];
+    assert_eq!(tokenize_kinds(input), expected);
+}
+
+#[test]
+fn test_malformed_numbers_are_diagnosed() {
+    let cases = [
+        ("9223372036854775808", "integer literal `9223372036854775808` is too large"),
+        ("0x10000000000000000", "integer literal `0x10000000000000000` is too large"),
+        ("08", "invalid digit '8' in octal literal `08`"),
+        ("0b102", "invalid digit '2' in binary literal `0b102`"),
+        ("0x", "malformed integer literal `0x`"),
+        ("10abc", "invalid suffix `abc` on integer literal `10abc`"),
+        ("1uu", "invalid suffix `uu` on integer literal `1uu`"),
+        ("1e", "malformed floating-point literal `1e`"),
+        ("1.2.3", "malformed floating-point literal `1.2.3`"),
+        ("1e999", "floating-point literal `1e999` is too large"),
+    ];
+    for (input, message) in cases {
+        let mut lexer = Lexer::new(input);
+        assert_eq!(lexer.next_token().kind, TokenKind::Num(0), "{}", input);
+        assert_eq!(lexer.next_token().kind, TokenKind::Eof, "{}", input);
+        let messages: Vec<&str> = lexer.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![message]);
+    }
+}
+
+#[test]
+fn test_all_escape_sequences() {
+    let input = r#" "\0\x41\101\a\b\f\v\?\\" '\0' '\x7f' '\177' '\'' "#;
+    let expected = vec![
+        TokenKind::String(b"\0AA\x07\x08\x0c\x0b?\\".to_vec()),
+        TokenKind::CharLiteral('\0'),
+        TokenKind::CharLiteral('\x7f'),
+        TokenKind::CharLiteral('\x7f'),
+        TokenKind::CharLiteral('\''),
+        TokenKind::Eof,
+    ];
+    assert_eq!(tokenize_kinds(input), expected);
+
+    // escapes produce single bytes, not UTF-8 encodings
+    assert_eq!(tokenize_kinds(r#" "\xff\377" "#)[0], TokenKind::String(vec![0xff, 0xff]));
+}
+
+#[test]
+fn test_unknown_escapes_keep_the_character_with_a_warning() {
+    let mut lexer = Lexer::new(r#" "a\qb\é" "#);
+    assert_eq!(lexer.next_token().kind, TokenKind::String("aqbé".as_bytes().to_vec()));
+    assert!(lexer.diagnostics.is_empty());
+    let warnings: Vec<&str> = lexer.warnings.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(warnings, vec!["unknown escape sequence `\\q`", "unknown escape sequence `\\é`"]);
+}
+
+#[test]
+fn test_adjacent_strings_are_concatenated() {
+    let input = "\"ab\" \"cd\"\n  /* comment */ \"ef\" ;";
+    let expected = vec![
+        TokenKind::String(b"abcdef".to_vec()),
+        TokenKind::Semicolon,
+        TokenKind::Eof,
+    ];
+    assert_eq!(tokenize_kinds(input), expected);
+}
+
+#[test]
+fn test_multi_character_constants_are_ints_with_a_warning() {
+    let mut lexer = Lexer::new("'ab' '\\0\\xff'");
+    assert_eq!(lexer.next_token().kind, TokenKind::Num(0x6162));
+    assert_eq!(lexer.next_token().kind, TokenKind::Num(0xff));
+    assert!(lexer.diagnostics.is_empty());
+    let warnings: Vec<&str> = lexer.warnings.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(warnings, vec!["multi-character character constant"; 2]);
+}
+
+#[test]
+fn test_malformed_literals_are_diagnosed() {
+    let cases = [
+        ("\"abc\nint x;", "unterminated string literal"),
+        ("'a", "unterminated character constant"),
+        ("''", "empty character constant"),
+        ("\"\\x\"", "\\x used with no following hex digits"),
+        ("\"\\x100\"", "hex escape sequence out of range"),
+        ("'\\777'", "octal escape sequence out of range"),
+    ];
+    for (input, message) in cases {
+        let mut lexer = Lexer::new(input);
+        while lexer.next_token().kind != TokenKind::Eof {}
+        let messages: Vec<&str> = lexer.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec![message], "{}", input);
+    }
+
+    // an unterminated string stops at the end of its line
+    let mut lexer = Lexer::new("\"abc\nint x;");
+    lexer.next_token();
+    assert_eq!(lexer.next_token().kind, TokenKind::Int);
+}
+
+#[test]
+fn test_token_spans() {
+    let mut lexer = Lexer::new("int x;\n  x = 0x1F; // done\n\"a\" \"b\"").with_file_id(2);
+    let mut spans = Vec::new();
+    loop {
+        let tok = lexer.next_token();
+        spans.push((tok.span.start, tok.span.end, tok.span.line, tok.span.column));
+        assert_eq!(tok.span.file_id, 2);
+        if tok.kind == TokenKind::Eof {
+            break;
+        }
+    }
+    assert_eq!(
+        spans,
+        vec![
+            (0, 3, 1, 1),   // int
+            (4, 5, 1, 5),   // x
+            (5, 6, 1, 6),   // ;
+            (9, 10, 2, 3),  // x
+            (11, 12, 2, 5), // =
+            (13, 17, 2, 7), // 0x1F
+            (17, 18, 2, 11),// ;
+            (27, 34, 3, 1), // "a" "b"
+            (34, 34, 3, 8), // end of input
+        ]
+    );
+}
+
+#[test]
+fn test_diagnostics_carry_spans() {
+    let mut lexer = Lexer::new("x = 12abc;");
+    while lexer.next_token().kind != TokenKind::Eof {}
+    let span = lexer.diagnostics[0].span;
+    assert_eq!(span, Some(Span { file_id: 0, start: 4, end: 9, line: 1, column: 5 }));
+    assert_eq!(
+        lexer.diagnostics[0].to_string(),
+        "line 1:5: invalid suffix `abc` on integer literal `12abc`"
+    );
+}
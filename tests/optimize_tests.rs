@@ -0,0 +1,286 @@
+/// Tests for the optimization passes: what each pass does to the bytecode, and differential
+/// runs checking that optimized programs print and return exactly what unoptimized ones do.
+mod common;
+
+use c4_rust::asm::{assemble, to_asm};
+use c4_rust::instruction::{Compare, Instruction};
+use c4_rust::inline::{inline_functions, INLINE_THRESHOLD};
+use c4_rust::optimize::optimize;
+use c4_rust::program::Program;
+use c4_rust::vm::SharedBuffer;
+use common::{compile, read_source};
+use std::path::Path;
+
+/// Programs covering the language, each run before and after optimizing.
+const PROGRAMS: &[&str] = &[
+    "int main() { return 3 + 4 * 2 - 5; }",
+    "int main() { print(-7 / 2); print(-7 % 2); print(1 << 62 << 3); print(-16 >> 2); return ~5 ^ 3 | 8 & 12; }",
+    "int main() { print(1.5 * 2); print(7 / 2.0); print(3 < 2.5); print(1 == 1.0); print(-2.5); return 0; }",
+    "int main() { print(!0); print(!7); print(!0.0); print(-(-9223372036854775807 - 1)); return 2 > 1 && 0 || 5; }",
+    "int main() { int x; x = 9; print(x + 0); print(x * 1); print(x - 0); print(x / 1); print(x << 0); return x & -1; }",
+    "int main() { int i; int s; i = 0; s = 0; while (i < 10) { if (i % 3 == 0) s = s + i * 2; i++; } return s; }",
+    "int main() { if (1) print(1); else print(2); if (0) print(3); while (0) print(4); return 0 ? 5 : 6; }",
+    "int main() { int x; x = 0; while (1) { x++; if (x > 4) return x; } }",
+    "int g; int *p; int main() { p = &g; *p = 2 + 3; return g * (1 + 1); }",
+    "char *s; int main() { s = \"hello\"; printf(\"%s %d %c\\n\", s, 6 * 7, s[1 + 0]); return s[4] - 'a'; }",
+    "enum { A = 2, B = 6 }; int main() { return B + A * (int)4; }",
+    "int fact(int n) { int r; r = 1; while (n > 1) { r = r * n; n = n - 1; } return r; } int main() { return fact(2 + 3); }",
+    "int fib(int n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } int main() { return fib(5 * 3 - 3); }",
+    "int sum(int a, int b) { return a + b; } int main() { return sum(1 + 1, sum(3 * 3, 0)); }",
+    "int main() { int x; x = 10; x = x + 0; print(x); print(x ? 1 + 1 : 0); return (x > 5) + (x < 5); }",
+    "int x; { x = 4 * 4; print(x); return x + 0; }",
+    "int main() { print(1 / 0 + 1); return 0; }",
+    "int f() { return 1; print(9); } int g() { return f(); } int main() { if (0) return g(); return f(); print(2); }",
+    "int f(int x) { if (x) return 1; else return 2; print(3); } int main() { while (1) { return f(0); } }",
+    "int gcd(int a, int b) { if (b == 0) return a; return gcd(b, a % b); } int main() { return gcd(1071, 462); }",
+    "int sq(int x) { return x * x; } int sumsq(int a, int b) { return sq(a) + sq(b); } int main() { return sumsq(sq(2), 3); }",
+    "int count(int n) { int c; c = c + n; return c; } int main() { int i; int s; i = 0; s = 0; while (i < 5) { s = s * 10 + count(i); i++; } return s; }",
+];
+
+fn optimized(source: &str, level: u8) -> Program {
+    let mut program = compile(source);
+    optimize(&mut program, level);
+    program
+}
+
+/// Run `program` with `args` passed to `main`, returning its result (`None` if the VM
+/// panicked) and what it printed.
+fn run(program: &Program, args: &[&str]) -> (Option<i64>, String) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let output = SharedBuffer::new();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut vm = program.vm();
+        vm.set_output(output.clone());
+        vm.allow_read(root.join("input"));
+        if !args.is_empty() {
+            let argv: Vec<String> = args.iter().map(|a| root.join(a).display().to_string()).collect();
+            vm.set_args(&argv);
+        }
+        vm.run_from(&program.code, program.entry())
+    }));
+    (result.ok().flatten(), output.to_string_lossy())
+}
+
+/// Check that every level gives `program` the same behavior as `-O0`.
+fn assert_same_behavior(source: &str, args: &[&str]) {
+    let baseline = run(&compile(source), args);
+    for level in 1..=3 {
+        let program = optimized(source, level);
+        assert_eq!(program.verify(), Ok(()), "-O{}: {}", level, source);
+        assert_eq!(run(&program, args), baseline, "-O{}: {}", level, source);
+    }
+}
+
+#[test]
+fn test_optimized_programs_behave_the_same() {
+    for source in PROGRAMS {
+        assert_same_behavior(source, &[]);
+    }
+}
+
+#[test]
+fn test_optimized_sample_programs_behave_the_same() {
+    for path in ["input/hello.c", "input/test_all_features.c"] {
+        assert_same_behavior(&read_source(path), &[]);
+    }
+    let c4 = read_source("input/c4.c");
+    assert_same_behavior(&c4, &["input/c4.c", "input/hello.c"]);
+}
+
+#[test]
+fn test_level_zero_changes_nothing() {
+    let source = read_source("input/test_all_features.c");
+    assert_eq!(optimized(&source, 0), compile(&source));
+}
+
+#[test]
+fn test_constant_expressions_fold_to_one_immediate() {
+    let program = optimized("int main() { return 3 + 4 * 2 - 5; }", 1);
+    assert_eq!(program.code[..3], [Instruction::Enter(0), Instruction::Imm(6), Instruction::Jmp(9999)]);
+
+    let program = optimized("int main() { print(1.5 * 2 + 1); print(!(2 < 1)); return -(2 << 3); }", 1);
+    assert!(program.code.contains(&Instruction::ImmF(4.0)));
+    assert!(program.code.contains(&Instruction::Imm(1)));
+    assert!(program.code.contains(&Instruction::Imm(-16)));
+    assert!(!program.code.iter().any(|i| matches!(i, Instruction::Mul | Instruction::Add | Instruction::Lt)));
+}
+
+#[test]
+fn test_identities_are_simplified() {
+    let program = optimized("int f(int x) { return (x * 1 - 0) / 1; }", 1);
+    assert_eq!(
+        program.code[..3],
+        [Instruction::Enter(1), Instruction::LoadLocal(0), Instruction::Leave]
+    );
+}
+
+#[test]
+fn test_identities_hold_for_float_operands() {
+    let source = "int main() { int x; x = 0.0; x = -x; print(x * 1 - 0); print(x + 0); x = 1.5; print(x / 1); return x << 0; }";
+    assert_same_behavior(source, &[]);
+    let program = optimized(source, 1);
+    assert!(!program.code.iter().any(|i| matches!(i, Instruction::Mul | Instruction::Sub | Instruction::Div)));
+    assert!(program.code.contains(&Instruction::Add));
+    assert!(program.code.contains(&Instruction::Shl));
+    assert_eq!(run(&program, &[]), (None, "-0\n0\n1.5\n".to_string()));
+}
+
+#[test]
+fn test_failing_operations_are_left_for_run_time() {
+    let program = optimized("int main() { return 1 / 0 + 5 % 0; }", 1);
+    assert!(program.code.contains(&Instruction::Div));
+    assert!(program.code.contains(&Instruction::Mod));
+}
+
+#[test]
+fn test_constant_branches_are_resolved() {
+    let program = optimized("int main() { if (2 > 1) print(1); else print(2); while (0) print(3); return 0; }", 1);
+    assert!(!program.code.iter().any(|i| matches!(i, Instruction::Jz(_) | Instruction::Jnz(_))));
+    assert_eq!(run(&program, &[]), (Some(0), "1\n".to_string()));
+}
+
+#[test]
+fn test_entry_points_follow_the_code() {
+    let source = "int a() { return 1 + 1; } int b() { return 2 * 3 + a(); } int main() { return b() + 2 * 2; }";
+    let program = optimized(source, 1);
+    for (name, &entry) in &program.functions {
+        assert!(matches!(program.code[entry], Instruction::Enter(_)), "{}", name);
+    }
+    assert_eq!(program.main_label, Some(program.functions["main"]));
+    assert_eq!(program.spans.len(), program.code.len());
+    assert_eq!(run(&program, &[]).0, Some(12));
+}
+
+#[test]
+fn test_unreachable_code_is_removed() {
+    let program = optimized("int f(int x) { return x; print(1); } int main() { return f(2); print(3); }", 1);
+    let f = program.functions["f"];
+    assert_eq!(
+        program.code[f..f + 3],
+        [Instruction::Enter(1), Instruction::LoadLocal(0), Instruction::Leave]
+    );
+    assert!(!program.code.contains(&Instruction::Print));
+    // the exit label stays last
+    assert_eq!(program.code.last(), Some(&Instruction::Label(9999)));
+    assert_eq!(run(&program, &[]).0, Some(2));
+}
+
+#[test]
+fn test_uncalled_functions_are_removed() {
+    let source = "int a() { return 1; } int b() { return a() + 1; } int c() { return 3; } int main() { return c(); }";
+    let program = optimized(source, 1);
+    let mut names: Vec<&str> = program.functions.keys().map(String::as_str).collect();
+    names.sort_unstable();
+    assert_eq!(names, ["c", "main"]);
+    assert_eq!(program.function_arity.len(), 2);
+    for &entry in program.functions.values() {
+        assert!(matches!(program.code[entry], Instruction::Enter(_)));
+    }
+    assert_eq!(program.main_label, Some(program.functions["main"]));
+    assert_eq!(program.spans.len(), program.code.len());
+    assert_eq!(run(&program, &[]).0, Some(3));
+}
+
+#[test]
+fn test_dead_code_warnings() {
+    let source = "int unused() { return 1; }\nint main() {\n  if (0) print(1);\n  return 2;\n  print(3);\n}";
+    let warnings = |level: u8| {
+        let mut program = compile(source);
+        let warnings = optimize(&mut program, level);
+        warnings.iter().map(|w| (w.message.clone(), w.line)).collect::<Vec<_>>()
+    };
+    let uncalled = ("function `unused` is never called".to_string(), 1);
+    let unreachable = |line| ("unreachable code".to_string(), line);
+    // `if (0)` is only known to be dead once it is folded
+    assert_eq!(warnings(0), [uncalled.clone(), unreachable(5)]);
+    assert_eq!(warnings(1), [uncalled, unreachable(3), unreachable(5)]);
+    // the code generator's own leftovers are not worth a warning
+    assert_eq!(warnings(1).len(), 3);
+    let clean = "int f(int x) { if (x) return 1; else return 2; } int main() { while (1) { return f(0); } }";
+    assert_eq!(optimize(&mut compile(clean), 1), []);
+}
+
+#[test]
+fn test_inlined_programs_behave_the_same() {
+    let mut sources: Vec<String> = PROGRAMS.iter().map(|s| s.to_string()).collect();
+    for path in ["input/hello.c", "input/test_all_features.c"] {
+        sources.push(read_source(path));
+    }
+    for source in &sources {
+        let baseline = run(&compile(source), &[]);
+        for threshold in [4, INLINE_THRESHOLD, 200] {
+            let mut program = compile(source);
+            inline_functions(&mut program, threshold);
+            assert_eq!(program.verify(), Ok(()), "threshold {}: {}", threshold, source);
+            assert_eq!(run(&program, &[]), baseline, "threshold {}: {}", threshold, source);
+        }
+    }
+    let c4 = read_source("input/c4.c");
+    let args = ["input/c4.c", "input/hello.c"];
+    let mut program = compile(&c4);
+    inline_functions(&mut program, 200);
+    assert_eq!(program.verify(), Ok(()));
+    assert_eq!(run(&program, &args), run(&compile(&c4), &args));
+}
+
+#[test]
+fn test_small_functions_are_inlined() {
+    let source = "int sum(int a, int b) { return a - b; } int main() { int x; x = 4; return sum(x, 1) * sum(10, x); }";
+    let program = optimized(source, 2);
+    assert!(!program.code.iter().any(|i| matches!(i, Instruction::Call(_) | Instruction::Leave)));
+    // inlined everywhere, so the function itself is gone
+    assert!(!program.functions.contains_key("sum"));
+    assert!(matches!(program.code[0], Instruction::Enter(3)));
+    assert_eq!(run(&program, &[]).0, Some(18));
+}
+
+#[test]
+fn test_recursive_and_large_functions_stay_calls() {
+    let calls = |program: &Program| program.code.iter().filter(|i| matches!(i, Instruction::Call(_))).count();
+    let source = "int fib(int n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } int main() { return fib(10); }";
+    let mut program = compile(source);
+    inline_functions(&mut program, 1000);
+    assert_eq!(program, compile(source));
+
+    let source = "int sum(int a, int b) { return a + b; } int main() { return sum(1, 2); }";
+    let mut program = compile(source);
+    inline_functions(&mut program, 4);
+    assert_eq!(calls(&program), 1);
+    inline_functions(&mut program, 8);
+    assert_eq!(calls(&program), 0);
+}
+
+#[test]
+fn test_peephole_fuses_loop_updates_and_branches() {
+    let source = "int main() { int i; int n; i = 0; n = 0; while (i < 10) { n = n - 2; i++; } return n; }";
+    let program = optimized(source, 2);
+    let count = |wanted: &Instruction| program.code.iter().filter(|i| *i == wanted).count();
+    assert_eq!(count(&Instruction::AddLocal(1, -2)), 1);
+    assert_eq!(count(&Instruction::AddLocal(0, 1)), 1);
+    assert_eq!(program.code.iter().filter(|i| matches!(i, Instruction::CmpJz(Compare::Lt, _))).count(), 1);
+    assert!(!program.code.iter().any(|i| matches!(i, Instruction::Lt | Instruction::Jz(_) | Instruction::Sub)));
+    assert_eq!(run(&program, &[]).0, Some(-20));
+}
+
+#[test]
+fn test_peephole_keeps_values_that_are_used() {
+    // the incremented value is the result of `++i` and `i = i + 1`, and `i++` yields the old one
+    let source = "int main() { int i; int a; int b; int c; i = 5; a = ++i; b = (i = i + 1); c = i++; return a * 100 + b * 10 + c + i; }";
+    let program = optimized(source, 2);
+    assert!(program.code.iter().any(|i| matches!(i, Instruction::AddLocal(..))));
+    assert_eq!(run(&program, &[]), run(&compile(source), &[]));
+}
+
+#[test]
+fn test_superinstructions_survive_assembly_and_binary_round_trips() {
+    let program = optimized(&read_source("input/test_all_features.c"), 2);
+    assert!(program.code.iter().any(|i| matches!(i, Instruction::CmpJz(..))));
+
+    let mut reassembled = assemble(&to_asm(&program).unwrap()).unwrap();
+    reassembled.spans = program.spans.clone();
+    assert_eq!(reassembled, program);
+
+    let mut bytes = Vec::new();
+    program.write_to(&mut bytes).unwrap();
+    assert_eq!(Program::read_from(&mut bytes.as_slice()).unwrap(), program);
+}
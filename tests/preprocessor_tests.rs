@@ -0,0 +1,150 @@
+/// Tests for the C preprocessor: macro expansion, conditional compilation, includes and
+/// the diagnostics reported for malformed directives.
+mod common;
+
+use c4_rust::preprocessor::Preprocessor;
+use common::compile;
+use std::fs;
+use std::path::PathBuf;
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("c4_rust_pp_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn expand(source: &str) -> String {
+    Preprocessor::new().preprocess(source, "test.c").expect("preprocessing failed").text
+}
+
+fn run(source: &str) -> i64 {
+    run_text(&expand(source))
+}
+
+fn run_text(text: &str) -> i64 {
+    let program = compile(text);
+    program.vm().run_from(&program.code, program.entry()).expect("VM did not return a value")
+}
+
+#[test]
+fn test_object_and_function_like_macros() {
+    let source = r#"
+#define N 10
+#define SQUARE(x) ((x) * (x))
+#define ADD(a, b) ((a) + (b))
+int main() { return ADD(SQUARE(N), SQUARE(1 + 1)); }
+"#;
+    assert_eq!(run(source), 104);
+}
+
+#[test]
+fn test_stringify_paste_and_variadic_macros() {
+    let out = expand("#define STR(x) #x\n#define CAT(a, b) a ## b\n#define LOG(fmt, ...) printf(fmt, __VA_ARGS__)\nSTR(a + b) CAT(foo, bar) LOG(\"%d\", 1, 2)\n");
+    assert_eq!(out.trim(), "\"a + b\" foobar printf(\"%d\", 1, 2)");
+}
+
+#[test]
+fn test_self_referential_macro_is_not_reexpanded() {
+    assert_eq!(expand("#define x x + 1\nx\n").trim(), "x + 1");
+}
+
+#[test]
+fn test_replacement_is_rescanned_with_the_rest_of_the_line() {
+    assert_eq!(expand("#define g f\n#define f(x) x\ng(1)\n").trim(), "1");
+    assert_eq!(expand("#define id(x) x\n#define call id\ncall(call)(2) + call\n").trim(), "id(2) + id");
+    assert_eq!(expand("#define a b\n#define b a\na b\n").trim(), "a b");
+}
+
+#[test]
+fn test_lines_are_spliced_before_comments_are_removed() {
+    let out = expand("int a; // comment \\\ncontinued\nint b; /\\\n* block *\\\n/ int c;\n");
+    assert_eq!(out, "int a; \n\nint b;   int c;\n\n\n");
+
+    let err = Preprocessor::new().preprocess("int x;\nint y; /\\\n* open\n", "bad.c").unwrap_err();
+    assert_eq!(err.line, 2);
+    assert!(err.message.contains("unterminated comment"), "{}", err);
+}
+
+#[test]
+fn test_conditionals() {
+    let source = r#"
+#define LEVEL 2
+#ifdef MISSING
+int a = 1;
+#elif LEVEL > 1 && defined(LEVEL)
+int main() { return 7; }
+#else
+int b = 2;
+#endif
+#ifndef LEVEL
+#error not reached
+#endif
+"#;
+    assert_eq!(run(source), 7);
+}
+
+#[test]
+fn test_line_numbers_are_preserved() {
+    let out = expand("#define A 1\n#if 0\nskipped\n#endif\nA __LINE__\n");
+    assert_eq!(out.lines().nth(4), Some("1 5"));
+}
+
+#[test]
+fn test_includes_with_guards_and_search_paths() {
+    let dir = scratch_dir("include");
+    fs::create_dir_all(dir.join("inc")).unwrap();
+    fs::write(dir.join("inc/twice.h"), "#ifndef TWICE_H\n#define TWICE_H\nint twice(int x) { return 2 * x; }\n#endif\n").unwrap();
+    fs::write(dir.join("inc/once.h"), "#pragma once\nint one() { return 1; }\n").unwrap();
+    fs::write(dir.join("local.h"), "#include <twice.h>\n#define BASE 20\n").unwrap();
+    fs::write(dir.join("main.c"), "#include <stdio.h>\n#include \"local.h\"\n#include <twice.h>\n#include <once.h>\n#include <once.h>\nint main() { return twice(BASE) + one(); }\n").unwrap();
+
+    let mut pp = Preprocessor::new();
+    pp.add_include_path(dir.join("inc"));
+    let text = pp.preprocess_file(dir.join("main.c")).expect("preprocessing failed").text;
+    assert_eq!(text.matches("int twice").count(), 1);
+    assert_eq!(text.matches("int one").count(), 1);
+    assert_eq!(run_text(&text), 41);
+}
+
+#[test]
+fn test_define_long_long_program_runs() {
+    let source = r#"
+#define int long long
+int add(int a, int b) { return a + b; }
+int main() { int x; x = add(40, 2); return x; }
+"#;
+    assert_eq!(run(source), 42);
+}
+
+#[test]
+fn test_predefined_macros() {
+    let mut pp = Preprocessor::new();
+    pp.define("DEBUG", "1");
+    assert!(pp.is_defined("DEBUG"));
+    let out = pp.preprocess("#if DEBUG\nyes\n#endif\n", "test.c").unwrap().text;
+    assert_eq!(out.trim(), "yes");
+    pp.undefine("DEBUG");
+    let out = pp.preprocess("#if DEBUG\nyes\n#endif\n", "test.c").unwrap().text;
+    assert_eq!(out.trim(), "");
+}
+
+#[test]
+fn test_directive_errors() {
+    let err = |source: &str| Preprocessor::new().preprocess(source, "bad.c").unwrap_err();
+
+    let missing = err("int x;\n#include \"nowhere.h\"\n");
+    assert_eq!(missing.line, 2);
+    assert_eq!(missing.file.as_deref(), Some("bad.c"));
+    assert!(missing.message.contains("nowhere.h"), "{}", missing);
+
+    let unterminated = err("#ifdef X\nint y;\n");
+    assert!(unterminated.message.contains("#if"), "{}", unterminated);
+
+    let error = err("\n\n#error stop here\n");
+    assert_eq!(error.line, 3);
+    assert!(error.message.contains("stop here"), "{}", error);
+
+    assert!(err("#endif\n").message.contains("#endif"));
+    assert!(err("#define F(a) a\nF(1, 2)\n").message.contains("F"));
+}
@@ -0,0 +1,135 @@
+/// Tests for the syntax tree pretty-printer: printed C re-parses to the same tree, and the
+/// text and JSON forms look the way the `--dump-ast` option documents.
+use c4_rust::ast::{Decl, ExprKind, TranslationUnit};
+use c4_rust::parser::Parser;
+use c4_rust::preprocessor::Preprocessor;
+use c4_rust::pretty::{dump_ast, to_c, to_json, AstFormat};
+use std::path::Path;
+
+fn parse(source: &str) -> TranslationUnit {
+    Parser::new(source).parse_ast().unwrap_or_else(|errors| panic!("{}\n{}", errors[0], source))
+}
+
+/// Print `source`'s tree as C, re-parse it, and check the trees match.
+fn assert_round_trips(source: &str) {
+    let mut original = parse(source);
+    let printed = to_c(&original);
+    let mut reparsed = parse(&printed);
+    original.clear_spans();
+    reparsed.clear_spans();
+    assert_eq!(original, reparsed, "printed as:\n{}", printed);
+    assert_eq!(to_c(&reparsed), printed, "printing is not stable");
+}
+
+#[test]
+fn test_example_programs_round_trip() {
+    for file in ["input/c4.c", "input/hello.c", "input/test_all_features.c"] {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(file);
+        let preprocessed = Preprocessor::new().preprocess_file(&path).unwrap();
+        assert_round_trips(&preprocessed.text);
+    }
+}
+
+#[test]
+fn test_tricky_expressions_round_trip() {
+    assert_round_trips(
+        r#"
+int main() {
+    int a, b, *p, **pp;
+    a = b = (a + b) * (a - (b - 1)) / 2 % 3;
+    a = a ? b : a ? 1 : 2;
+    a = (a ? b : a) ? 1 : 2;
+    a = -(-a) + - -a - +a + ~!a;
+    p = &*p; a = *&a; a = **pp; a = *pp[1]; a = (*pp)[1];
+    a = *p++ + (*p)++ + ++*p + --a - a--;
+    a = (char)a + (int *)p - p + sizeof(char **) + (char)(a + 1);
+    a = a << 1 >> 2 & 3 | 4 ^ 5 && a || !a < b == (a < b) != a >= b;
+    a = (a = 1) + (a || b) * 2;
+    a = 0xffffffffffffffff + 'a' + '\n' + '\'' + '\0' + '\xff';
+    printf("%d \"quoted\" \\ \t tab\n\001 \x7f \3777", f(a, b + 1), 1.5, .25, 1e-9);
+    if (a) if (b) a = 1; else a = 2;
+    if (a) { a = 1; } else if (b) a = 2; else { ; }
+    while (a) { { a--; } }
+    return;
+}
+"#,
+    );
+}
+
+#[test]
+fn test_text_output() {
+    let source = "enum Color { RED, GREEN = 4 }; int *g;\nint f(int x){if(x<0)return -x;else{while(x>9)x=x/10;}return x;}";
+    assert_eq!(
+        dump_ast(source, AstFormat::Text).unwrap(),
+        "enum Color { RED = 0, GREEN = 4 };\n\
+         int *g;\n\
+         int f(int x) {\n    \
+             if (x < 0)\n        \
+                 return -x;\n    \
+             else {\n        \
+                 while (x > 9)\n            \
+                     x = x / 10;\n    \
+             }\n    \
+             return x;\n\
+         }\n"
+    );
+}
+
+#[test]
+fn test_json_output() {
+    let json = to_json(&parse("int main() { return a[1] + 2; }"));
+    assert!(json.starts_with("{\n  \"node\": \"TranslationUnit\",\n  \"decls\": [\n"), "{}", json);
+    let expected = [
+        r#""node": "Function""#,
+        r#""name": "main""#,
+        r#""params": []"#,
+        r#""node": "Return""#,
+        r#""op": "+""#,
+        r#""node": "Index""#,
+        r#""base": {"#,
+        r#""node": "Int""#,
+        r#""value": 2"#,
+        r#""span": { "line": 1, "column": 21, "start": 20, "end": 28 }"#,
+    ];
+    for piece in expected {
+        assert!(json.contains(piece), "missing {} in\n{}", piece, json);
+    }
+
+    // strings are escaped for JSON
+    let json = to_json(&parse("int main() { printf(\"a\\\"b\\n\"); }"));
+    assert!(json.contains(r#""value": "a\"b\n""#), "{}", json);
+}
+
+#[test]
+fn test_json_unary_ops_and_floats() {
+    let json = to_json(&parse("int main() { int x; x = -x; x++; --x; return 0; }"));
+    let flat = json.split_whitespace().collect::<Vec<_>>().join(" ");
+    for piece in [
+        r#""op": "-", "postfix": false"#,
+        r#""op": "++", "postfix": true"#,
+        r#""op": "--", "postfix": false"#,
+    ] {
+        assert!(flat.contains(piece), "missing {} in\n{}", piece, json);
+    }
+
+    // infinities and NaN aren't JSON numbers
+    let mut unit = parse("int main() { 1.5; 2.5; }");
+    let mut values = vec![f64::INFINITY, f64::NAN].into_iter();
+    if let Decl::Function(function) = &mut unit.decls[0] {
+        for stmt in &mut function.body {
+            stmt.walk_mut(&mut |_| {}, &mut |expr| {
+                if let ExprKind::Float(f) = &mut expr.kind {
+                    *f = values.next().unwrap();
+                }
+            });
+        }
+    }
+    let json = to_json(&unit);
+    assert!(json.contains(r#""value": "inf""#) && json.contains(r#""value": "NaN""#), "{}", json);
+}
+
+#[test]
+fn test_syntax_errors_are_returned() {
+    let errors = dump_ast("int main() { return (1; }", AstFormat::Json).unwrap_err();
+    assert_eq!(errors[0].message, "Expected ')' after expression");
+}
@@ -0,0 +1,81 @@
+/// Tests for the C `printf` formatter used by the VM's `printf` builtin.
+/// Expected strings are what glibc's `printf` produces for the same format and arguments.
+use c4_rust::format::sprintf;
+use c4_rust::vm::Value;
+
+fn fmt(format: &str, args: &[Value]) -> String {
+    let out = sprintf(format.as_bytes(), args, |addr| {
+        // tests pass string arguments as indices into this table
+        ["hello", "c4"][addr as usize - 1].as_bytes().to_vec()
+    });
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_plain_text_has_no_implicit_newline() {
+    assert_eq!(fmt("abc", &[]), "abc");
+    assert_eq!(fmt("100%%\n", &[]), "100%\n");
+}
+
+#[test]
+fn test_integer_conversions() {
+    let args = [Value::Int(42), Value::Int(-7), Value::Int(255), Value::Int(255), Value::Int(8)];
+    assert_eq!(fmt("%d %i %x %X %o", &args), "42 -7 ff FF 10");
+    assert_eq!(fmt("%u", &[Value::Int(-1)]), "18446744073709551615");
+    assert_eq!(fmt("%ld %lld", &[Value::Int(1), Value::Int(2)]), "1 2");
+}
+
+#[test]
+fn test_width_precision_and_flags() {
+    assert_eq!(fmt("[%5d]", &[Value::Int(42)]), "[   42]");
+    assert_eq!(fmt("[%-5d]", &[Value::Int(42)]), "[42   ]");
+    assert_eq!(fmt("[%05d]", &[Value::Int(-42)]), "[-0042]");
+    assert_eq!(fmt("[%+d]", &[Value::Int(5)]), "[+5]");
+    assert_eq!(fmt("[% d]", &[Value::Int(5)]), "[ 5]");
+    assert_eq!(fmt("[%.3d]", &[Value::Int(7)]), "[007]");
+    assert_eq!(fmt("[%#x]", &[Value::Int(255)]), "[0xff]");
+    assert_eq!(fmt("[%*d]", &[Value::Int(4), Value::Int(1)]), "[   1]");
+}
+
+#[test]
+fn test_char_and_string_conversions() {
+    assert_eq!(fmt("%c%c", &[Value::Int('o' as i64), Value::Int('k' as i64)]), "ok");
+    assert_eq!(fmt("%s, %s!", &[Value::Int(1), Value::Int(2)]), "hello, c4!");
+    assert_eq!(fmt("[%8.4s]", &[Value::Int(1)]), "[    hell]");
+    assert_eq!(fmt("[%-6s]", &[Value::Int(2)]), "[c4    ]");
+    assert_eq!(fmt("%.*s", &[Value::Int(2), Value::Int(1)]), "he");
+    assert_eq!(fmt("%s", &[Value::Int(0)]), "(null)");
+}
+
+#[test]
+fn test_float_conversions() {
+    assert_eq!(fmt("%f", &[Value::Flt(3.25)]), "3.250000");
+    assert_eq!(fmt("%.2f", &[Value::Flt(2.71628)]), "2.72");
+    assert_eq!(fmt("[%8.3f]", &[Value::Flt(-1.5)]), "[  -1.500]");
+    assert_eq!(fmt("[%08.2f]", &[Value::Flt(-1.5)]), "[-0001.50]");
+    assert_eq!(fmt("%e", &[Value::Flt(12345.678)]), "1.234568e+04");
+    assert_eq!(fmt("%f", &[Value::Int(2)]), "2.000000");
+}
+
+#[test]
+fn test_pointer_conversion() {
+    assert_eq!(fmt("%p", &[Value::Int(0x1000)]), "0x1000");
+    assert_eq!(fmt("%p", &[Value::Int(0)]), "(nil)");
+}
+
+#[test]
+fn test_scanf_conversions_and_widths() {
+    use c4_rust::format::{scanf, Scanned};
+    let mut input = std::io::Cursor::new(b"  -12 3.5e1 abcdef x".to_vec());
+    let values = scanf(b"%d %f %3s%*s %c", &mut input).unwrap();
+    assert_eq!(
+        values,
+        vec![
+            Scanned::Int(-12),
+            Scanned::Flt(35.0),
+            Scanned::Str(b"abc".to_vec()),
+            Scanned::Chars(b"x".to_vec()),
+        ]
+    );
+    assert_eq!(scanf(b"%d", &mut input), None);
+}
@@ -0,0 +1,52 @@
+/// Self-hosting tests: the original C4 compiler (`input/c4.c`) is compiled by this crate and
+/// run on the VM, where it compiles and runs `input/hello.c` with its own interpreter.
+/// Expected outputs were taken from a natively compiled `c4.c`.
+mod common;
+
+use c4_rust::vm::SharedBuffer;
+use common::{compile, read_source};
+use std::path::Path;
+
+/// Compile `input/c4.c` and run it with `args` (relative to the crate root).
+fn run_c4(args: &[&str]) -> (i64, String) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let source = read_source("input/c4.c");
+    let program = compile(&source);
+    let mut vm = program.vm();
+    vm.allow_read(root.join("input"));
+
+    let mut argv = vec![root.join("input/c4.c").display().to_string()];
+    argv.extend(args.iter().map(|a| root.join(a).display().to_string()));
+    vm.set_args(&argv);
+    let output = SharedBuffer::new();
+    vm.set_output(output.clone());
+
+    let result = vm.run_from(&program.code, program.entry());
+    (result.unwrap(), output.to_string_lossy())
+}
+
+#[test]
+fn test_c4_compiles_and_runs_hello() {
+    assert_eq!(
+        run_c4(&["input/hello.c"]),
+        (42, "Hello, world!\nexit(42) cycle = 9\n".to_string())
+    );
+}
+
+#[test]
+fn test_c4_runs_itself_running_hello() {
+    assert_eq!(
+        run_c4(&["input/c4.c", "input/hello.c"]),
+        (
+            42,
+            "Hello, world!\nexit(42) cycle = 9\nexit(42) cycle = 26095\n".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_c4_reports_missing_file() {
+    let (status, output) = run_c4(&["input/missing.c"]);
+    assert_eq!(status, -1);
+    assert!(output.starts_with("could not open("), "{}", output);
+}
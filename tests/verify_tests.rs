@@ -0,0 +1,175 @@
+/// Tests for the bytecode verifier: compiled programs pass, and hand-assembled programs
+/// with bad jumps, calls, frame slots or stack depths are rejected.
+mod common;
+
+use c4_rust::asm::assemble;
+use common::{compile, read_source};
+
+/// The verifier's complaint about `text`, as `index: message`.
+fn rejection(text: &str) -> String {
+    let program = assemble(text).unwrap_or_else(|e| panic!("{}", e.message));
+    let error = program.verify().expect_err("malformed program was accepted");
+    format!("{}: {}", error.index, error.message)
+}
+
+#[test]
+fn test_compiled_programs_verify() {
+    for path in ["input/hello.c", "input/test_all_features.c", "input/c4.c"] {
+        assert_eq!(compile(&read_source(path)).verify(), Ok(()), "{}", path);
+    }
+}
+
+#[test]
+fn test_well_formed_assembly_verifies() {
+    let program = assemble(
+        "
+.func loop 1
+    Enter 2
+top:
+    LoadLocal 0
+    Jz done
+    LoadLocal 0
+    Imm 1
+    Sub
+    StoreLocal 0
+    Pop
+    Jmp top
+done:
+    LoadLocal 1
+    Leave
+.func main 0
+    Enter 0
+    Imm 3
+    Call loop
+    Jmp L9999
+L9999:
+",
+    )
+    .unwrap();
+    assert_eq!(program.verify(), Ok(()));
+}
+
+#[test]
+fn test_missing_jump_target() {
+    assert_eq!(rejection("    Imm 1\n    Jz L5\n    Imm 2\n"), "1: jump to missing label 5");
+}
+
+#[test]
+fn test_duplicate_label() {
+    assert_eq!(rejection("L1:\n    Imm 1\nL1:\n"), "2: label 1 is defined twice");
+}
+
+#[test]
+fn test_call_must_target_a_function_entry() {
+    let text = "
+.func f 0
+    Enter 0
+    Imm 1
+    Leave
+.func main 0
+    Enter 0
+    Call 1
+    Jmp L9999
+L9999:
+";
+    assert_eq!(rejection(text), "4: `Call 1` does not target a known function entry");
+    let text = text.replace("Call 1", "TailCall 1");
+    assert_eq!(rejection(&text), "4: `TailCall 1` does not target a known function entry");
+}
+
+#[test]
+fn test_local_slots_stay_inside_the_frame() {
+    let text = "
+.func f 1
+    Enter 2
+    LoadLocal 2
+    Leave
+";
+    assert_eq!(rejection(text), "1: `LoadLocal(2)` is outside the frame of 2 slot(s)");
+
+    // top-level code may only use the slots pushed for it
+    assert_eq!(rejection(".locals 1\n    Imm 4\n    StoreLocal 1\n"), "1: `StoreLocal(1)` is outside the frame of 1 slot(s)");
+    assert_eq!(rejection(".func f 2\n    Enter 1\n"), "0: frame of 1 slot(s) has no room for 2 argument(s)");
+}
+
+#[test]
+fn test_stack_underflow() {
+    assert_eq!(rejection("    Imm 1\n    Add\n"), "1: `Add` needs 2 value(s) but the stack holds 1");
+    let call = "
+.func f 2
+    Enter 2
+    Imm 0
+    Leave
+.func main 0
+    Enter 0
+    Imm 1
+    Call f
+";
+    assert_eq!(rejection(call), "5: `Call(0)` needs 2 value(s) but the stack holds 1");
+}
+
+#[test]
+fn test_depth_must_agree_where_paths_meet() {
+    // the loop pushes a value on every iteration
+    let growing = "
+top:
+    Imm 1
+    Jmp top
+";
+    assert_eq!(rejection(growing), "0: stack depth is 0 on one path and 1 on another");
+
+    // one branch leaves an extra value behind
+    let unbalanced = "
+    Imm 1
+    Jz skip
+    Imm 2
+skip:
+    Imm 3
+    Pop
+";
+    assert_eq!(rejection(unbalanced), "3: stack depth is 1 on one path and 0 on another");
+}
+
+#[test]
+fn test_leave_needs_a_caller() {
+    let text = "
+.func main 0
+    Enter 0
+    Imm 1
+    Leave
+";
+    assert_eq!(rejection(text), "2: `Leave` outside a called function has no caller to return to");
+    assert_eq!(
+        rejection("    Imm 1\n    Leave\n"),
+        "1: `Leave` outside a called function has no caller to return to"
+    );
+}
+
+#[test]
+fn test_tail_call_needs_a_caller() {
+    let from_main = "
+.func f 0
+    Enter 0
+    Imm 1
+    Leave
+.func main 0
+    Enter 0
+    TailCall f
+";
+    assert_eq!(rejection(from_main), "4: `TailCall 0` outside a called function has no caller to return to");
+    let top_level = "
+    TailCall f
+.func f 0
+    Enter 0
+    Imm 1
+    Leave
+";
+    assert_eq!(rejection(top_level), "0: `TailCall 1` outside a called function has no caller to return to");
+}
+
+#[test]
+fn test_print_f_needs_a_float() {
+    assert_eq!(rejection("    PrintF\n"), "0: `PrintF` needs a value but the float stack is empty");
+    assert_eq!(assemble("    PushF 1.5\n    PrintF\n").unwrap().verify(), Ok(()));
+}
+